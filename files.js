@@ -0,0 +1,81 @@
+// JavaScript bridge for saving files
+// Shared by the popup and the collapsed viewer
+
+/**
+ * Export data as JSON file
+ * @param {string} data - JSON string to export
+ * @param {string} filename - Filename for download
+ */
+export function exportToFile(data, filename) {
+  const blob = new Blob([data], { type: 'application/json' });
+  const url = URL.createObjectURL(blob);
+  const a = document.createElement('a');
+  a.href = url;
+  a.download = filename;
+  document.body.appendChild(a);
+  a.click();
+  document.body.removeChild(a);
+  URL.revokeObjectURL(url);
+}
+
+/**
+ * Save data as a JSON file in the downloads folder and wait until it is
+ * written (used to archive sessions before deleting them)
+ * @param {string} data - JSON string to save
+ * @param {string} filename - Filename for download
+ * @returns {Promise<void>} Rejects if the download is not allowed, is
+ *   interrupted or is cancelled
+ */
+export async function downloadFile(data, filename) {
+  if (!(await chrome.permissions.contains({ permissions: ['downloads'] }))) {
+    throw new Error('Saving archives needs the downloads permission');
+  }
+
+  const blob = new Blob([data], { type: 'application/json' });
+  const url = URL.createObjectURL(blob);
+  try {
+    const downloadId = await chrome.downloads.download({
+      url,
+      filename,
+      saveAs: false,
+      conflictAction: 'uniquify',
+    });
+    await downloadFinished(downloadId);
+  } finally {
+    URL.revokeObjectURL(url);
+  }
+}
+
+/**
+ * Wait for a download to complete
+ * @param {number} downloadId - ID from chrome.downloads.download
+ */
+function downloadFinished(downloadId) {
+  return new Promise((resolve, reject) => {
+    const settle = (state, error) => {
+      if (state === 'complete') {
+        chrome.downloads.onChanged.removeListener(listener);
+        resolve();
+      } else if (state === 'interrupted') {
+        chrome.downloads.onChanged.removeListener(listener);
+        reject(new Error(`Download interrupted: ${error || 'unknown reason'}`));
+      }
+    };
+    const listener = delta => {
+      if (delta.id === downloadId && delta.state) {
+        settle(delta.state.current, delta.error && delta.error.current);
+      }
+    };
+    chrome.downloads.onChanged.addListener(listener);
+
+    // It may have finished before the listener was added
+    chrome.downloads.search({ id: downloadId }).then(([item]) => {
+      if (item) {
+        settle(item.state, item.error);
+      }
+    });
+  });
+}
+
+// Log that the bridge is loaded
+console.log('Tab Hoarder files.js bridge loaded');
@@ -0,0 +1,950 @@
+//! Operations run against the browser and storage
+//!
+//! The popup calls these with `ChromeTabs` and `ChromeStorage`; tests run
+//! them against `MemoryBrowser` and `MemoryStorage`. Every operation that
+//! closes or reorders tabs records its inverse in the undo journal before
+//! touching anything, so even a partial failure can be undone.
+//!
+//! A collapse first checks that its session fits in storage, making room
+//! as the `QuotaPolicy` preference says, so a full store never costs tabs.
+
+use crate::operations::{plan_sort, GroupHandling, OperationPlan, PlannedOperation, SortStrategy};
+use crate::storage::quota::plan_room;
+use crate::storage::{keys, RoomPlan, StorageRepository};
+use crate::tab_data::{CollapsedSession, PendingCollapse, SavedTab, TabGroupInfo, TabInfo};
+use crate::tabs::{TabBackend, TabUpdate};
+use crate::undo::{JournalEntry, OperationKind, UndoAction, UndoJournal};
+use uuid::Uuid;
+
+/// The stored undo journal, or an empty one if none is stored
+pub async fn load_undo_journal(storage: &impl StorageRepository) -> UndoJournal {
+    storage
+        .get(keys::UNDO_JOURNAL)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Add an operation's inverse to the stored journal
+///
+/// A journal that cannot be saved does not stop the operation.
+pub async fn record_undo(storage: &impl StorageRepository, kind: OperationKind, undo: UndoAction, now: f64) {
+    let mut journal = load_undo_journal(storage).await;
+    journal.record(JournalEntry {
+        id: Uuid::new_v4().to_string(),
+        kind,
+        timestamp: now,
+        undo,
+    });
+    if let Err(e) = storage.set(keys::UNDO_JOURNAL, &journal).await {
+        log::warn!("Failed to save undo journal: {}", e);
+    }
+}
+
+/// Sort the current window
+pub async fn sort_tabs(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    strategy: &SortStrategy,
+    groups: GroupHandling,
+    now: f64,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    let tabs = browser.query().await?;
+    let plan = plan_sort(&tabs, strategy, groups);
+    record_undo(storage, OperationKind::Sort, UndoAction::restore_order(&tabs), now).await;
+    browser.apply_sort_plan(&plan, progress).await
+}
+
+/// The session a collapse plan saves
+fn collapse_session(plan: &OperationPlan, now: f64) -> CollapsedSession {
+    CollapsedSession {
+        id: Uuid::new_v4().to_string(),
+        name: plan.session.as_ref().map(|session| session.name.clone()).unwrap_or_default(),
+        timestamp: now,
+        tabs: plan
+            .tabs_to_save()
+            .into_iter()
+            .map(|tab| SavedTab {
+                saved_at: Some(now),
+                ..tab
+            })
+            .collect(),
+    }
+}
+
+/// How `session` will fit in storage, without changing anything
+///
+/// Fails when the `QuotaPolicy` preference cannot make enough room.
+pub async fn plan_storage_room(
+    storage: &impl StorageRepository,
+    session: &CollapsedSession,
+) -> Result<RoomPlan, String> {
+    let data = storage.load_sessions().await?;
+    let report = storage.quota_report(&data).await?;
+    let encoding = storage.get(keys::SESSION_ENCODING).await?.unwrap_or_default();
+    let policy = storage.get(keys::QUOTA_POLICY).await?.unwrap_or_default();
+    plan_room(&report, &data, session, encoding, policy)
+}
+
+/// Predict, before review is confirmed, whether a collapse plan fits
+pub async fn predict_collapse(
+    storage: &impl StorageRepository,
+    plan: &OperationPlan,
+    now: f64,
+) -> Result<RoomPlan, String> {
+    plan_storage_room(storage, &collapse_session(plan, now)).await
+}
+
+/// Execute the checked items of a reviewed plan
+///
+/// Collapse saves the plan's session and checks the save before closing
+/// anything; see `collapse`. Sessions the quota policy
+/// archives are handed to `archive` first, which must only return once
+/// they are safely written somewhere else.
+pub async fn run_plan(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    plan: &OperationPlan,
+    now: f64,
+    archive: impl AsyncFnOnce(&[CollapsedSession]) -> Result<(), String>,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    let tab_ids = plan.tabs_to_close();
+    if tab_ids.is_empty() {
+        return Ok(());
+    }
+
+    match plan.operation {
+        PlannedOperation::RemoveDuplicates => {
+            let undo = UndoAction::reopen(&plan.included_tabs(), None);
+            record_undo(storage, OperationKind::RemoveDuplicates, undo, now).await;
+            browser
+                .remove_tabs(&tab_ids, progress)
+                .await
+                .map_err(|e| format!("Remove failed: {}", e))
+        }
+        PlannedOperation::Collapse => collapse(browser, storage, plan, now, archive, progress).await,
+    }
+}
+
+/// Save a collapse plan's tabs as a session, then close exactly what was
+/// saved
+///
+/// Algorithm:
+/// 1. Make room as the quota policy says; sessions to archive go to
+///    `archive`, and nothing is deleted if it fails
+/// 2. Store a `PendingCollapse` marker
+/// 3. Save the session, reload it and compare; if it did not come back
+///    as written, close nothing
+/// 4. Close the planned tabs that still show the URL they were saved
+///    with (a tab navigated away in the meantime stays open), plus the
+///    duplicate copies the plan includes
+/// 5. Clear the marker
+///
+/// A marker left behind means steps 3-5 were cut short (e.g. the popup
+/// closed); `check_interrupted_collapse` reports it on the next open.
+async fn collapse(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    plan: &OperationPlan,
+    now: f64,
+    archive: impl AsyncFnOnce(&[CollapsedSession]) -> Result<(), String>,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    let session = collapse_session(plan, now);
+
+    let room = plan_storage_room(storage, &session).await?;
+    if let RoomPlan::Archive(sessions) = &room {
+        archive(sessions).await.map_err(|e| format!("Failed to archive old sessions: {}", e))?;
+    }
+
+    let marker = PendingCollapse {
+        session_id: session.id.clone(),
+        session_name: session.name.clone(),
+        tab_ids: plan.tabs_to_close(),
+        started: now,
+    };
+    storage
+        .set(keys::PENDING_COLLAPSE, &Some(marker))
+        .await
+        .map_err(|e| format!("Failed to start collapse: {}", e))?;
+
+    let saved = save_verified(storage, &room, &session).await;
+    let result = match saved {
+        Ok(()) => close_saved_tabs(browser, storage, plan, &session.id, now, progress).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = storage.set(keys::PENDING_COLLAPSE, &None).await {
+        log::warn!("Failed to clear collapse marker: {}", e);
+    }
+    result
+}
+
+/// Make room and add `session` in one write, then read it back
+async fn save_verified(storage: &impl StorageRepository, room: &RoomPlan, session: &CollapsedSession) -> Result<(), String> {
+    storage
+        .update_sessions(|data| {
+            room.apply(data, session);
+            data.add_session(session.clone());
+        })
+        .await
+        .map_err(|e| format!("Failed to save: {}", e))?;
+
+    let reloaded = storage
+        .load_sessions()
+        .await
+        .map_err(|e| format!("Failed to verify the saved session: {}", e))?;
+    if !reloaded.sessions.contains(session) {
+        return Err("The saved session did not read back as written, so no tabs were closed".to_string());
+    }
+    Ok(())
+}
+
+/// Close the plan's tabs that are still what was saved
+async fn close_saved_tabs(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    plan: &OperationPlan,
+    session_id: &str,
+    now: f64,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    let open_tabs = browser.query().await?;
+    let closing: Vec<TabInfo> = plan
+        .included_tabs()
+        .into_iter()
+        .filter(|tab| open_tabs.iter().any(|open| open.id == tab.id && open.url == tab.url))
+        .collect();
+    if closing.len() < plan.tabs_to_close().len() {
+        log::warn!("Leaving {} changed tabs open", plan.tabs_to_close().len() - closing.len());
+    }
+
+    let undo = UndoAction::reopen(&closing, Some(session_id.to_string()));
+    record_undo(storage, OperationKind::Collapse, undo, now).await;
+
+    let tab_ids: Vec<i32> = closing.iter().map(|tab| tab.id).collect();
+    browser
+        .remove_tabs(&tab_ids, progress)
+        .await
+        .map_err(|e| format!("Failed to close: {}", e))
+}
+
+/// A collapse that was cut short, found on the next open
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterruptedCollapse {
+    pub session_name: String,
+    /// Whether its session made it to storage
+    pub session_saved: bool,
+    pub tabs: usize,
+    /// How many of its tabs are still open in the current window
+    pub still_open: usize,
+}
+
+impl InterruptedCollapse {
+    pub fn message(&self) -> String {
+        if !self.session_saved {
+            format!(
+                "Collapsing \"{}\" was interrupted before the session was saved; no tabs were closed.",
+                self.session_name
+            )
+        } else if self.still_open > 0 {
+            format!(
+                "Collapsing \"{}\" was interrupted: the session was saved, but {} of its {} tabs are still open.",
+                self.session_name, self.still_open, self.tabs
+            )
+        } else {
+            format!(
+                "Collapsing \"{}\" was interrupted after closing its tabs; the session is saved.",
+                self.session_name
+            )
+        }
+    }
+}
+
+/// Report (once) a collapse whose marker was left behind
+pub async fn check_interrupted_collapse(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+) -> Result<Option<InterruptedCollapse>, String> {
+    let Some(marker) = storage.get(keys::PENDING_COLLAPSE).await?.flatten() else {
+        return Ok(None);
+    };
+
+    let data = storage.load_sessions().await?;
+    let open_tabs = browser.query().await?;
+    let interrupted = InterruptedCollapse {
+        session_saved: data.sessions.iter().any(|session| session.id == marker.session_id),
+        tabs: marker.tab_ids.len(),
+        still_open: marker
+            .tab_ids
+            .iter()
+            .filter(|id| open_tabs.iter().any(|tab| tab.id == **id))
+            .count(),
+        session_name: marker.session_name,
+    };
+
+    storage.set(keys::PENDING_COLLAPSE, &None).await?;
+    Ok(Some(interrupted))
+}
+
+/// Close a single tab
+pub async fn close_tab(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    tab: &TabInfo,
+    now: f64,
+) -> Result<(), String> {
+    close_tabs(browser, storage, std::slice::from_ref(tab), now, |_| {}).await
+}
+
+/// Close tabs picked by the user, as one undoable operation
+pub async fn close_tabs(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    tabs: &[TabInfo],
+    now: f64,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    if tabs.is_empty() {
+        return Ok(());
+    }
+    let undo = UndoAction::reopen(tabs, None);
+    record_undo(storage, OperationKind::CloseTab, undo, now).await;
+    let tab_ids: Vec<i32> = tabs.iter().map(|tab| tab.id).collect();
+    browser.remove_tabs(&tab_ids, progress).await
+}
+
+/// IDs of `tabs` from left to right, whatever order they were picked in
+fn in_tab_order<'a>(tabs: impl IntoIterator<Item = &'a TabInfo>) -> Vec<i32> {
+    let mut ordered: Vec<&TabInfo> = tabs.into_iter().collect();
+    ordered.sort_by_key(|tab| tab.index);
+    ordered.iter().map(|tab| tab.id).collect()
+}
+
+/// Pin or unpin tabs, keeping their order
+///
+/// Changing a tab's pinned state moves it to the edge of the pinned block,
+/// so tabs are pinned left to right and unpinned right to left.
+pub async fn set_pinned(
+    browser: &impl TabBackend,
+    tabs: &[TabInfo],
+    pinned: bool,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    let mut tab_ids = in_tab_order(tabs.iter().filter(|tab| tab.pinned != pinned));
+    if !pinned {
+        tab_ids.reverse();
+    }
+    let update = TabUpdate {
+        pinned: Some(pinned),
+        ..TabUpdate::default()
+    };
+    browser.update_tabs(&tab_ids, update, progress).await
+}
+
+/// Put tabs in a new group titled `title`, keeping their order
+///
+/// Pinned tabs cannot be grouped and are left out. Returns the group's ID,
+/// or `None` when every tab was pinned.
+pub async fn group_tabs(
+    browser: &impl TabBackend,
+    tabs: &[TabInfo],
+    title: &str,
+    progress: impl Fn(u8) + 'static,
+) -> Result<Option<i32>, String> {
+    let tab_ids = in_tab_order(tabs.iter().filter(|tab| !tab.pinned));
+    if tab_ids.is_empty() {
+        return Ok(None);
+    }
+    let info = TabGroupInfo {
+        title: title.to_string(),
+        ..TabGroupInfo::default()
+    };
+    browser.create_group(&tab_ids, &info, progress).await.map(Some)
+}
+
+/// Move tabs into a new window, keeping their order; returns its ID
+pub async fn move_to_new_window(
+    browser: &impl TabBackend,
+    tabs: &[TabInfo],
+    progress: impl Fn(u8) + 'static,
+) -> Result<i32, String> {
+    browser.move_to_new_window(&in_tab_order(tabs), progress).await
+}
+
+/// Run the inverse of a journal entry, then drop it from the journal
+///
+/// Entries can be reverted out of order; positions are then best effort,
+/// since later operations may have moved or closed the same tabs.
+pub async fn revert(
+    browser: &impl TabBackend,
+    storage: &impl StorageRepository,
+    entry: &JournalEntry,
+    progress: impl Fn(u8) + 'static,
+) -> Result<(), String> {
+    match &entry.undo {
+        UndoAction::ReopenTabs { tabs, session_id } => {
+            let reopened = browser.reopen_tabs(tabs, progress).await?;
+
+            // The reopened tabs leave the session they were saved to; tabs
+            // the collapse left open or that failed to reopen stay saved
+            if let Some(session_id) = session_id {
+                let urls: Vec<&str> = reopened.iter().map(|&i| tabs[i].url.as_str()).collect();
+                storage
+                    .update_sessions(|data| {
+                        data.remove_reopened_tabs(session_id, &urls);
+                    })
+                    .await?;
+            }
+        }
+        UndoAction::RestoreOrder { plan } => {
+            let open_tabs = browser.query().await?;
+            browser.apply_sort_plan(&plan.restricted_to(&open_tabs), progress).await?;
+        }
+    }
+
+    let mut journal = load_undo_journal(storage).await;
+    journal.take(&entry.id);
+    storage.set(keys::UNDO_JOURNAL, &journal).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::{plan_collapse, plan_remove_duplicates, KeepPolicy, NoDomainPlacement, SortPreset};
+    use crate::storage::quota::NEARLY_FULL_PERCENT;
+    use crate::storage::{MemoryStorage, QuotaPolicy, StorageUsage, SESSION_KEY_PREFIX};
+    use serde_json::{Map, Value};
+    use crate::restore::{plan_restore, RestoreOptions};
+    use crate::tab_data::TabGroupInfo;
+    use crate::tabs::{MemoryBrowser, TabUpdate};
+    use crate::url_normalizer::UrlNormalizer;
+    use crate::test_support::block_on;
+
+    const NOW: f64 = 1709370900000.0;
+
+    /// A window with a pinned tab, a two-tab group and a duplicate:
+    /// mail (pinned) | docs, wiki (group) | news | docs again
+    fn create_test_browser() -> MemoryBrowser {
+        let browser = MemoryBrowser::new();
+        let mail = browser.open("https://mail.example.com/", "Mail");
+        let docs = browser.open("https://docs.rs/serde", "serde docs");
+        let wiki = browser.open("https://en.wikipedia.org/wiki/Rust", "Rust");
+        browser.open("https://news.ycombinator.com/", "HN");
+        browser.open("https://docs.rs/serde", "serde docs (again)");
+
+        block_on(browser.update(mail, TabUpdate { pinned: Some(true), ..TabUpdate::default() })).unwrap();
+        block_on(browser.group(&[docs, wiki], None)).unwrap();
+        browser
+    }
+
+    /// Storage that acknowledges session writes but never keeps them
+    struct LossyStorage(MemoryStorage);
+
+    impl StorageRepository for LossyStorage {
+        async fn get_raw(&self, key: &str) -> Result<Option<Value>, String> {
+            self.0.get_raw(key).await
+        }
+
+        async fn get_many_raw(&self, keys: &[String]) -> Result<Map<String, Value>, String> {
+            self.0.get_many_raw(keys).await
+        }
+
+        async fn set_raw(&self, key: &str, value: Value) -> Result<(), String> {
+            self.0.set_raw(key, value).await
+        }
+
+        async fn remove_raw(&self, keys: &[String]) -> Result<(), String> {
+            self.0.remove_raw(keys).await
+        }
+
+        async fn compare_and_set(
+            &self,
+            expected_revision: u64,
+            mut items: Map<String, Value>,
+            removed_keys: &[String],
+        ) -> Result<bool, String> {
+            items.retain(|key, _| !key.starts_with(SESSION_KEY_PREFIX));
+            self.0.compare_and_set(expected_revision, items, removed_keys).await
+        }
+
+        async fn usage(&self) -> Result<StorageUsage, String> {
+            self.0.usage().await
+        }
+    }
+
+    /// Storage holding one large old session, with too little room left
+    /// for another collapse
+    fn create_full_storage(policy: QuotaPolicy) -> MemoryStorage {
+        let storage = MemoryStorage::new();
+        let old = CollapsedSession {
+            id: "old".to_string(),
+            name: "Old".to_string(),
+            timestamp: NOW - 1e9,
+            tabs: (0..50)
+                .map(|i| SavedTab {
+                    url: format!("https://example.com/page/{}", i),
+                    title: format!("Page {}", i),
+                    domain: "example.com".to_string(),
+                    pinned: false,
+                    ..SavedTab::default()
+                })
+                .collect(),
+        };
+        block_on(storage.update_sessions(|data| data.add_session(old))).unwrap();
+        block_on(storage.set(keys::QUOTA_POLICY, &policy)).unwrap();
+
+        let in_use = block_on(storage.usage()).unwrap().bytes_in_use;
+        let quota = (in_use / NEARLY_FULL_PERCENT + 1) * 100;
+        MemoryStorage::from_items(storage.items()).with_quota(quota)
+    }
+
+    fn urls(tabs: &[TabInfo]) -> Vec<&str> {
+        tabs.iter().map(|tab| tab.url.as_str()).collect()
+    }
+
+    fn urls_of_saved(session: &CollapsedSession) -> Vec<&str> {
+        session.tabs.iter().map(|tab| tab.url.as_str()).collect()
+    }
+
+    fn layout(tabs: &[TabInfo]) -> Vec<(String, bool, Option<i32>)> {
+        tabs.iter()
+            .map(|tab| (tab.url.clone(), tab.pinned, tab.group_id))
+            .collect()
+    }
+
+    #[test]
+    fn test_collapse_then_restore() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        // Only the skipped duplicate stays open
+        let remaining = block_on(browser.query()).unwrap();
+        assert_eq!(urls(&remaining), vec!["https://docs.rs/serde"]);
+
+        let data = block_on(storage.load_sessions()).unwrap();
+        assert_eq!(data.sessions.len(), 1);
+        let session = &data.sessions[0];
+        assert_eq!(session.tabs.len(), 4);
+
+        assert_eq!(block_on(storage.get(keys::PENDING_COLLAPSE)), Ok(Some(None)));
+
+        block_on(browser.restore_tabs(&plan_restore(&session.tabs, RestoreOptions::default()), |_| {})).unwrap();
+
+        let restored = block_on(browser.query()).unwrap();
+        assert_eq!(restored.len(), 5);
+        // The pinned tab comes back pinned, ahead of everything else
+        assert_eq!(restored[0].url, "https://mail.example.com/");
+        assert!(restored[0].pinned);
+        for tab in &tabs {
+            assert!(restored.iter().any(|r| r.url == tab.url));
+        }
+    }
+
+    #[test]
+    fn test_collapse_keeps_tab_metadata_for_restore() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+        let group_id = tabs[1].group_id.unwrap();
+        let rust_group = TabGroupInfo {
+            title: "Rust".to_string(),
+            color: "orange".to_string(),
+            collapsed: false,
+        };
+        block_on(browser.update_group(group_id, &rust_group)).unwrap();
+        let hn = tabs[3].id;
+        block_on(browser.update(hn, TabUpdate { muted: Some(true), ..TabUpdate::default() })).unwrap();
+        let tabs = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, true, "Session");
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        let data = block_on(storage.load_sessions()).unwrap();
+        let saved = &data.sessions[0].tabs;
+        assert!(saved.iter().all(|tab| tab.saved_at == Some(NOW) && tab.window_id == Some(1)));
+        let wiki = saved.iter().find(|tab| tab.url.contains("wikipedia")).unwrap();
+        assert_eq!(wiki.group.as_ref().map(|group| group.title.as_str()), Some("Rust"));
+        assert_eq!(wiki.index, Some(2));
+
+        // Saved in domain order, restored in the order the window had
+        assert_ne!(saved.iter().map(|tab| tab.url.as_str()).collect::<Vec<_>>(), urls(&tabs));
+        block_on(browser.restore_tabs(&plan_restore(saved, RestoreOptions::default()), |_| {})).unwrap();
+        let restored = block_on(browser.query()).unwrap();
+        // The closed duplicate was not saved
+        assert_eq!(urls(&restored), urls(&tabs[..4]));
+        assert!(restored[0].pinned);
+        assert!(restored[3].muted);
+        assert!(!restored[2].muted);
+        assert_eq!(restored[1].group.as_ref(), Some(&rust_group));
+        assert_eq!(restored[1].group_id, restored[2].group_id);
+    }
+
+    #[test]
+    fn test_collapse_then_undo_removes_session() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&before, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        let journal = block_on(load_undo_journal(&storage));
+        let entry = journal.last().unwrap().clone();
+        assert_eq!(entry.kind, OperationKind::Collapse);
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(urls(&after), urls(&before));
+        assert!(block_on(storage.load_sessions()).unwrap().sessions.is_empty());
+        assert!(block_on(load_undo_journal(&storage)).is_empty());
+    }
+
+    #[test]
+    fn test_undo_collapse_only_removes_reopened_tabs() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+
+        // The news tab navigated elsewhere, so the collapse leaves it open
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        let news = plan.items.iter_mut().find(|item| item.tab.url.contains("ycombinator")).unwrap();
+        news.tab.url = "https://news.ycombinator.com/item?id=1".to_string();
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+        let session_id = block_on(storage.load_sessions()).unwrap().sessions[0].id.clone();
+        block_on(storage.update_sessions(|data| {
+            data.update_session_name(&session_id, "Renamed".to_string());
+        }))
+        .unwrap();
+
+        let entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        assert_eq!(block_on(browser.query()).unwrap().len(), tabs.len());
+        let sessions = block_on(storage.load_sessions()).unwrap().sessions;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, "Renamed");
+        assert_eq!(urls_of_saved(&sessions[0]), vec!["https://news.ycombinator.com/item?id=1"]);
+    }
+
+    #[test]
+    fn test_remove_duplicates_then_undo() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+
+        let plan = plan_remove_duplicates(&before, &UrlNormalizer::default(), KeepPolicy::PreferGrouped);
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        // The grouped copy is kept
+        let deduped = block_on(browser.query()).unwrap();
+        assert_eq!(deduped.len(), 4);
+        assert!(deduped.iter().any(|tab| tab.url == "https://docs.rs/serde" && tab.group_id.is_some()));
+
+        let entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(layout(&after), layout(&before));
+    }
+
+    #[test]
+    fn test_sort_keeps_pinned_and_groups_then_undo() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+        let strategy = SortPreset::DomainThenUrl.strategy();
+
+        block_on(sort_tabs(&browser, &storage, &strategy, GroupHandling::GroupsAsUnits, NOW, |_| {})).unwrap();
+
+        let sorted = block_on(browser.query()).unwrap();
+        assert_eq!(sorted[0].url, "https://mail.example.com/");
+        assert!(sorted[0].pinned);
+        let group_positions: Vec<usize> = sorted
+            .iter()
+            .enumerate()
+            .filter(|(_, tab)| tab.group_id.is_some())
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(group_positions.len(), 2);
+        assert_eq!(group_positions[1], group_positions[0] + 1);
+
+        let entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        assert_eq!(entry.kind, OperationKind::Sort);
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(layout(&after), layout(&before));
+    }
+
+    #[test]
+    fn test_undo_sort_when_group_is_gone() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+        let strategy = SortPreset::DomainThenUrl.strategy();
+        block_on(sort_tabs(&browser, &storage, &strategy, GroupHandling::GroupsAsUnits, NOW, |_| {})).unwrap();
+
+        // The recorded group was closed or ungrouped since
+        let mut entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        let UndoAction::RestoreOrder { plan } = &mut entry.undo else {
+            panic!("sort records its order");
+        };
+        for unit in plan.units.iter_mut().filter(|unit| unit.group_id.is_some()) {
+            unit.group_id = Some(999);
+        }
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(urls(&after), urls(&before));
+        assert!(block_on(load_undo_journal(&storage)).is_empty());
+    }
+
+    #[test]
+    fn test_close_tab_then_undo() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+
+        block_on(close_tab(&browser, &storage, &before[2], NOW)).unwrap();
+        assert_eq!(block_on(browser.query()).unwrap().len(), 4);
+
+        let entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        assert_eq!(entry.kind, OperationKind::CloseTab);
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        // Reopened in its old slot, back inside its group
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(layout(&after), layout(&before));
+    }
+
+    #[test]
+    fn test_close_several_tabs_as_one_undo() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let before = block_on(browser.query()).unwrap();
+
+        let closing = [before[4].clone(), before[3].clone()];
+        block_on(close_tabs(&browser, &storage, &closing, NOW, |_| {})).unwrap();
+        assert_eq!(block_on(browser.query()).unwrap().len(), 3);
+
+        let journal = block_on(load_undo_journal(&storage));
+        assert_eq!(journal.newest_first().count(), 1);
+        let entry = journal.last().unwrap().clone();
+        assert_eq!(entry.undo.tab_count(), 2);
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        assert_eq!(layout(&after), layout(&before));
+    }
+
+    #[test]
+    fn test_pin_and_unpin_keep_order() {
+        let browser = create_test_browser();
+        let tabs = block_on(browser.query()).unwrap();
+        // Picked in a different order than they appear
+        let picked = [tabs[4].clone(), tabs[3].clone()];
+
+        block_on(set_pinned(&browser, &picked, true, |_| {})).unwrap();
+        let pinned = block_on(browser.query()).unwrap();
+        assert_eq!(
+            urls(&pinned[..3]),
+            vec!["https://mail.example.com/", "https://news.ycombinator.com/", "https://docs.rs/serde"]
+        );
+        assert!(pinned[..3].iter().all(|tab| tab.pinned));
+
+        block_on(set_pinned(&browser, &pinned[..3], false, |_| {})).unwrap();
+        let unpinned = block_on(browser.query()).unwrap();
+        assert!(unpinned.iter().all(|tab| !tab.pinned));
+        assert_eq!(urls(&unpinned[..3]), urls(&pinned[..3]));
+    }
+
+    #[test]
+    fn test_group_tabs_skips_pinned() {
+        let browser = create_test_browser();
+        let tabs = block_on(browser.query()).unwrap();
+        let picked = [tabs[4].clone(), tabs[0].clone(), tabs[3].clone()];
+
+        let group_id = block_on(group_tabs(&browser, &picked, "Reading", |_| {})).unwrap().unwrap();
+
+        let after = block_on(browser.query()).unwrap();
+        let members: Vec<&TabInfo> = after.iter().filter(|tab| tab.group_id == Some(group_id)).collect();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].url, "https://news.ycombinator.com/");
+        assert_eq!(members[0].group.as_ref().unwrap().title, "Reading");
+        assert!(after[0].pinned && after[0].group_id.is_none());
+        assert_eq!(block_on(group_tabs(&browser, &after[..1], "Pinned", |_| {})).unwrap(), None);
+    }
+
+    #[test]
+    fn test_move_picked_tabs_to_new_window() {
+        let browser = create_test_browser();
+        let tabs = block_on(browser.query()).unwrap();
+        let picked = [tabs[4].clone(), tabs[2].clone()];
+
+        let window_id = block_on(move_to_new_window(&browser, &picked, |_| {})).unwrap();
+
+        assert_eq!(urls(&browser.window_tabs(window_id)), urls(&[tabs[2].clone(), tabs[4].clone()]));
+        assert_eq!(browser.window_tabs(1).len(), 3);
+    }
+
+    #[test]
+    fn test_collapse_can_close_duplicates() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, true, "Session");
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        assert!(block_on(browser.query()).unwrap().is_empty());
+        let data = block_on(storage.load_sessions()).unwrap();
+        assert_eq!(data.sessions[0].tabs.len(), 4);
+
+        // Undo brings back both copies (the emptied group is gone, as in
+        // Chrome, so they come back ungrouped)
+        let entry = block_on(load_undo_journal(&storage)).last().unwrap().clone();
+        block_on(revert(&browser, &storage, &entry, |_| {})).unwrap();
+        assert_eq!(urls(&block_on(browser.query()).unwrap()), urls(&tabs));
+    }
+
+    #[test]
+    fn test_collapse_leaves_changed_tabs_open() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+
+        // The news tab navigated elsewhere after the plan was made
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        let news = plan.items.iter_mut().find(|item| item.tab.url.contains("ycombinator")).unwrap();
+        news.tab.url = "https://news.ycombinator.com/item?id=1".to_string();
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        let remaining = block_on(browser.query()).unwrap();
+        assert_eq!(urls(&remaining), vec!["https://news.ycombinator.com/", "https://docs.rs/serde"]);
+    }
+
+    #[test]
+    fn test_unverified_save_closes_nothing() {
+        let browser = create_test_browser();
+        let storage = LossyStorage(MemoryStorage::new());
+        let tabs = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        let result = block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {}));
+
+        assert!(result.unwrap_err().contains("no tabs were closed"));
+        assert_eq!(block_on(browser.query()).unwrap().len(), tabs.len());
+        assert!(block_on(load_undo_journal(&storage)).is_empty());
+        assert_eq!(block_on(storage.get(keys::PENDING_COLLAPSE)), Ok(Some(None)));
+    }
+
+    #[test]
+    fn test_interrupted_collapse_is_reported_once() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+        let session = CollapsedSession {
+            id: "saved".to_string(),
+            name: "Morning".to_string(),
+            timestamp: NOW,
+            tabs: Vec::new(),
+        };
+        block_on(storage.update_sessions(|data| data.add_session(session))).unwrap();
+        // Cut short after closing one of two tabs
+        let marker = PendingCollapse {
+            session_id: "saved".to_string(),
+            session_name: "Morning".to_string(),
+            tab_ids: vec![tabs[0].id, 999],
+            started: NOW,
+        };
+        block_on(storage.set(keys::PENDING_COLLAPSE, &Some(marker))).unwrap();
+
+        let interrupted = block_on(check_interrupted_collapse(&browser, &storage)).unwrap().unwrap();
+
+        assert!(interrupted.session_saved);
+        assert_eq!((interrupted.still_open, interrupted.tabs), (1, 2));
+        assert_eq!(
+            interrupted.message(),
+            "Collapsing \"Morning\" was interrupted: the session was saved, but 1 of its 2 tabs are still open."
+        );
+        assert_eq!(block_on(check_interrupted_collapse(&browser, &storage)), Ok(None));
+    }
+
+    #[test]
+    fn test_collapse_refused_when_storage_is_full() {
+        let browser = create_test_browser();
+        let storage = create_full_storage(QuotaPolicy::Refuse);
+        let tabs = block_on(browser.query()).unwrap();
+
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        let predicted = block_on(predict_collapse(&storage, &plan, NOW));
+        let result = block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {}));
+
+        assert!(predicted.unwrap_err().starts_with("Not enough storage"));
+        assert!(result.unwrap_err().starts_with("Not enough storage"));
+        assert_eq!(block_on(browser.query()).unwrap().len(), tabs.len());
+        let data = block_on(storage.load_sessions()).unwrap();
+        assert_eq!(data.sessions.len(), 1);
+        assert!(block_on(load_undo_journal(&storage)).is_empty());
+    }
+
+    #[test]
+    fn test_collapse_archives_oldest_when_storage_is_full() {
+        let browser = create_test_browser();
+        let storage = create_full_storage(QuotaPolicy::ArchiveOldest);
+        let tabs = block_on(browser.query()).unwrap();
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+
+        let mut archived = Vec::new();
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |sessions| {
+            archived.extend(sessions.iter().map(|session| session.id.clone()));
+            Ok(())
+        }, |_| {}))
+        .unwrap();
+
+        assert_eq!(archived, vec!["old"]);
+        let data = block_on(storage.load_sessions()).unwrap();
+        let names: Vec<&str> = data.sessions.iter().map(|session| session.name.as_str()).collect();
+        assert_eq!(names, vec!["Session"]);
+        assert_eq!(block_on(browser.query()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_archive_keeps_everything() {
+        let browser = create_test_browser();
+        let storage = create_full_storage(QuotaPolicy::ArchiveOldest);
+        let tabs = block_on(browser.query()).unwrap();
+        let plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+
+        let result = block_on(run_plan(&browser, &storage, &plan, NOW, async |_| {
+            Err("download blocked".to_string())
+        }, |_| {}));
+
+        assert_eq!(result, Err("Failed to archive old sessions: download blocked".to_string()));
+        assert_eq!(block_on(browser.query()).unwrap().len(), tabs.len());
+        assert_eq!(block_on(storage.load_sessions()).unwrap().sessions[0].id, "old");
+    }
+
+    #[test]
+    fn test_unchecked_items_are_left_open() {
+        let browser = create_test_browser();
+        let storage = MemoryStorage::new();
+        let tabs = block_on(browser.query()).unwrap();
+
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+        plan.set_all(false);
+        block_on(run_plan(&browser, &storage, &plan, NOW, async |_| Ok(()), |_| {})).unwrap();
+
+        assert_eq!(block_on(browser.query()).unwrap().len(), tabs.len());
+        assert!(block_on(storage.load_sessions()).unwrap().sessions.is_empty());
+        assert!(block_on(load_undo_journal(&storage)).is_empty());
+    }
+}
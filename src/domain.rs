@@ -1,88 +1,386 @@
-/// Domain extraction and counting logic for Tab Hoarder
+//! Domain extraction and counting logic for Tab Hoarder
+
+use crate::public_suffix::{public_suffix_list, SuffixMode};
 use std::collections::HashMap;
-use url::Url;
+use std::sync::atomic::{AtomicBool, Ordering};
+use url::{Host, Url};
+
+/// Suffix mode used by `extract_domain` (false = ICANN only)
+static INCLUDE_PRIVATE_SUFFIXES: AtomicBool = AtomicBool::new(true);
+
+/// Choose whether private suffixes (github.io, blogspot.com, ...) count as
+/// public suffixes for every subsequent `extract_domain` call
+pub fn set_suffix_mode(mode: SuffixMode) {
+    INCLUDE_PRIVATE_SUFFIXES.store(mode == SuffixMode::IncludePrivate, Ordering::Relaxed);
+}
+
+/// The suffix mode currently used by `extract_domain`
+pub fn suffix_mode() -> SuffixMode {
+    if INCLUDE_PRIVATE_SUFFIXES.load(Ordering::Relaxed) {
+        SuffixMode::IncludePrivate
+    } else {
+        SuffixMode::IcannOnly
+    }
+}
 
-/// Extract the domain from a URL with smart TLD handling
+/// Extract the registrable domain (eTLD+1) from a URL
 ///
-/// Algorithm:
-/// 1. Parse URL using url::Url to extract hostname
-/// 2. Split hostname by "."
-/// 3. Get last segment (TLD)
-/// 4. If TLD is 2 letters AND second-to-last is "co" or "com":
-///    → Return last 3 segments (e.g., "example.com.au", "site.co.uk")
-/// 5. Else:
-///    → Return last 2 segments (e.g., "microsoft.com", "zinfandel.io")
-/// 6. Handle edge cases (localhost, IPs, etc.)
+/// Uses the suffix mode set with `set_suffix_mode` (private suffixes count
+/// by default). See `extract_domain_with_mode`.
 ///
 /// Examples:
 /// - https://www.google.com/search → google.com
 /// - https://ai.microsoft.com → microsoft.com
 /// - https://news.bbc.co.uk/article → bbc.co.uk
 /// - https://shop.example.com.au/products → example.com.au
+/// - https://foo.github.io → foo.github.io
 pub fn extract_domain(url: &str) -> Option<String> {
+    extract_domain_with_mode(url, suffix_mode())
+}
+
+/// What a URL's host is, so each operation can decide how to treat it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HostKind {
+    /// A DNS name with a registrable domain (github.com)
+    Domain,
+    /// An IPv4 address (192.168.1.1)
+    Ipv4,
+    /// An IPv6 address ([::1])
+    Ipv6,
+    /// localhost or a *.localhost name
+    Localhost,
+    /// file:// URLs
+    LocalFile,
+    /// Browser pages: chrome://, about:, edge://, view-source:, ...
+    BrowserInternal,
+    /// Extension pages: chrome-extension://, moz-extension://
+    Extension,
+    /// Inline data: URLs
+    Data,
+    /// Anything else without a usable host (javascript:, mailto:, blank URLs)
+    Opaque,
+}
+
+impl HostKind {
+    /// Whether URLs of this kind have a host that `extract_domain` returns
+    pub fn has_domain(self) -> bool {
+        matches!(
+            self,
+            HostKind::Domain | HostKind::Ipv4 | HostKind::Ipv6 | HostKind::Localhost
+        )
+    }
+
+    /// Group label for URLs of this kind that have no domain
+    pub fn label(self) -> &'static str {
+        match self {
+            HostKind::Domain => "Domains",
+            HostKind::Ipv4 => "IPv4 addresses",
+            HostKind::Ipv6 => "IPv6 addresses",
+            HostKind::Localhost => "localhost",
+            HostKind::LocalFile => "Local files",
+            HostKind::BrowserInternal => "Browser pages",
+            HostKind::Extension => "Extensions",
+            HostKind::Data => "Data URLs",
+            HostKind::Opaque => "Other",
+        }
+    }
+}
+
+/// Classify the host of a URL
+///
+/// Special schemes are recognised before the host is looked at, so
+/// `chrome://settings` is BrowserInternal rather than a domain "settings".
+/// Scheme-less input ("example.com/page") is treated as http.
+pub fn classify_url(url: &str) -> HostKind {
+    match parse_url(url) {
+        Some(parsed) => classify_parsed(&parsed),
+        None => HostKind::Opaque,
+    }
+}
+
+/// Extract the registrable domain (eTLD+1) from a URL
+///
+/// Algorithm:
+/// 1. Parse URL using url::Url and classify its host (see `HostKind`)
+/// 2. Localhost and IP addresses are returned as-is; kinds without a
+///    domain (files, browser pages, data: URLs, ...) return None
+/// 3. Look up the longest matching rule in the Public Suffix List,
+///    honouring wildcard and exception rules and `mode`
+/// 4. Return the public suffix plus one more label
+/// 5. If the host is itself a public suffix (e.g. "github.io"), return it as-is
+pub fn extract_domain_with_mode(url: &str, mode: SuffixMode) -> Option<String> {
+    let parsed = parse_url(url)?;
+
+    match classify_parsed(&parsed) {
+        HostKind::Domain => {
+            // Fully-qualified hosts ("example.com.") group with their relative form
+            let hostname = parsed.host_str()?.trim_end_matches('.');
+            let domain = public_suffix_list()
+                .registrable_domain(hostname, mode)
+                .unwrap_or(hostname);
+            Some(domain.to_string())
+        }
+        HostKind::Ipv4 | HostKind::Ipv6 => parsed.host_str().map(str::to_string),
+        HostKind::Localhost => Some("localhost".to_string()),
+        HostKind::LocalFile
+        | HostKind::BrowserInternal
+        | HostKind::Extension
+        | HostKind::Data
+        | HostKind::Opaque => None,
+    }
+}
+
+/// Extract the full host of a URL ("docs.github.com"), without a trailing dot
+pub fn extract_host(url: &str) -> Option<String> {
+    let parsed = parse_url(url)?;
+    if !classify_parsed(&parsed).has_domain() {
+        return None;
+    }
+    parsed.host_str().map(|host| host.trim_end_matches('.').to_string())
+}
+
+/// Extract the part of the host in front of the registrable domain
+///
+/// Examples:
+/// - https://docs.github.com → "docs"
+/// - https://a.b.example.co.uk → "a.b"
+/// - https://github.com → "" (no subdomain)
+pub fn extract_subdomain(url: &str) -> Option<String> {
+    let host = extract_host(url)?;
+    let domain = extract_domain(url)?;
+    let subdomain = host
+        .strip_suffix(&domain)
+        .map(|prefix| prefix.trim_end_matches('.'))
+        .unwrap_or_default();
+    Some(subdomain.to_string())
+}
+
+/// Key used to group a URL: its domain, or its kind's label when it has none
+pub fn group_key(url: &str) -> (HostKind, String) {
+    group_key_with_mode(url, suffix_mode())
+}
+
+/// `group_key` with an explicit suffix mode instead of the global one
+pub fn group_key_with_mode(url: &str, mode: SuffixMode) -> (HostKind, String) {
+    let kind = classify_url(url);
+    let key = if kind.has_domain() {
+        extract_domain_with_mode(url, mode).unwrap_or_else(|| kind.label().to_string())
+    } else {
+        kind.label().to_string()
+    };
+    (kind, key)
+}
+
+/// Parse a URL, retrying scheme-less input ("example.com/page") as http
+fn parse_url(url: &str) -> Option<Url> {
     if url.is_empty() {
         return None;
     }
 
-    // Try to parse as URL using url::Url
-    let hostname = match Url::parse(url) {
-        Ok(parsed_url) => {
-            // host_str() returns None if there's no host (e.g., "https://")
-            parsed_url.host_str()?.to_string()
-        }
-        Err(e) => {
-            // Only try fallback for RelativeUrlWithoutBase errors
-            // For other errors (like EmptyHost), we should return None
-            if e == url::ParseError::RelativeUrlWithoutBase {
-                // Fallback for non-standard URLs: try adding a scheme
-                Url::parse(&format!("http://{}", url))
-                    .ok()?
-                    .host_str()?
-                    .to_string()
+    match Url::parse(url) {
+        Ok(parsed_url) => Some(parsed_url),
+        // Only try fallback for RelativeUrlWithoutBase errors
+        // For other errors (like EmptyHost), we should return None
+        Err(url::ParseError::RelativeUrlWithoutBase) => Url::parse(&format!("http://{}", url)).ok(),
+        Err(_) => None,
+    }
+}
+
+fn classify_parsed(url: &Url) -> HostKind {
+    match url.scheme() {
+        "file" => return HostKind::LocalFile,
+        "data" => return HostKind::Data,
+        "chrome-extension" | "moz-extension" | "extension" => return HostKind::Extension,
+        "chrome" | "chrome-untrusted" | "chrome-search" | "about" | "edge" | "brave"
+        | "opera" | "vivaldi" | "devtools" | "view-source" => return HostKind::BrowserInternal,
+        _ => {}
+    }
+
+    match url.host() {
+        Some(Host::Ipv4(_)) => HostKind::Ipv4,
+        Some(Host::Ipv6(_)) => HostKind::Ipv6,
+        Some(Host::Domain(host)) => {
+            let host = host.trim_end_matches('.');
+            if host.is_empty() {
+                HostKind::Opaque
+            } else if host == "localhost" || host.ends_with(".localhost") {
+                HostKind::Localhost
             } else {
-                return None;
+                HostKind::Domain
             }
         }
-    };
+        None => HostKind::Opaque,
+    }
+}
+
+/// A domain in both its grouping (ASCII/punycode) and display (Unicode) forms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayDomain {
+    /// ASCII form as returned by `extract_domain`, used for grouping
+    pub ascii: String,
+    /// Unicode form for display (`xn--bcher-kva.de` → `bücher.de`)
+    pub unicode: String,
+    /// Set when the Unicode form looks like a spoof of another domain
+    pub warning: Option<SpoofWarning>,
+}
+
+/// Why a domain looks like a homograph spoof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpoofWarning {
+    /// A label mixes scripts that are not normally written together
+    /// (e.g. Latin "pple" with a Cyrillic "а")
+    MixedScript { label: String },
+    /// A label is written entirely in a non-Latin script using only letters
+    /// that look like Latin ones (e.g. Cyrillic "аррӏе" for "apple")
+    WholeScriptConfusable { label: String, script: Script },
+}
 
-    // Special cases: localhost and IP addresses
-    if hostname == "localhost" || is_ip_address(&hostname) {
-        return Some(hostname);
+impl SpoofWarning {
+    /// Short human-readable explanation for tooltips
+    pub fn message(&self) -> String {
+        match self {
+            SpoofWarning::MixedScript { label } => {
+                format!("\"{}\" mixes characters from different alphabets", label)
+            }
+            SpoofWarning::WholeScriptConfusable { label, script } => {
+                format!("\"{}\" uses {:?} letters that look like Latin ones", label, script)
+            }
+        }
     }
+}
+
+/// Writing system of a character, as far as spoof detection cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Script {
+    /// Digits, hyphens and combining marks: compatible with every script
+    Common,
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hiragana,
+    Katakana,
+    Bopomofo,
+    Hangul,
+    Han,
+    Other,
+}
+
+/// Script combinations that legitimately appear in a single label
+const ALLOWED_SCRIPT_MIXES: &[&[Script]] = &[
+    &[Script::Latin, Script::Han, Script::Hiragana, Script::Katakana],
+    &[Script::Latin, Script::Han, Script::Bopomofo],
+    &[Script::Latin, Script::Han, Script::Hangul],
+];
 
-    let parts: Vec<&str> = hostname.split('.').collect();
+/// Cyrillic letters that are visually indistinguishable from Latin ones
+const CYRILLIC_LATIN_LOOKALIKES: &str = "асԁеһіјӏорԛѕԝхуүѵԍгпъ";
 
-    // Need at least 2 parts for a valid domain
-    if parts.len() < 2 {
-        return Some(hostname);
+/// Greek letters that are visually indistinguishable from Latin ones
+const GREEK_LATIN_LOOKALIKES: &str = "αικνορτυχ";
+
+/// Classify a character's script by Unicode block
+pub fn script_of(c: char) -> Script {
+    match c {
+        '0'..='9' | '-' | '\u{30FC}' | '\u{0300}'..='\u{036F}' => Script::Common,
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => Script::Latin,
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Script::Greek,
+        '\u{0400}'..='\u{052F}' => Script::Cyrillic,
+        '\u{0530}'..='\u{058F}' => Script::Armenian,
+        '\u{0590}'..='\u{05FF}' => Script::Hebrew,
+        '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' => Script::Arabic,
+        '\u{0900}'..='\u{097F}' => Script::Devanagari,
+        '\u{0E00}'..='\u{0E7F}' => Script::Thai,
+        '\u{3040}'..='\u{309F}' => Script::Hiragana,
+        '\u{30A0}'..='\u{30FF}' => Script::Katakana,
+        '\u{3100}'..='\u{312F}' => Script::Bopomofo,
+        '\u{1100}'..='\u{11FF}' | '\u{AC00}'..='\u{D7AF}' => Script::Hangul,
+        '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' => Script::Han,
+        _ => Script::Other,
     }
+}
 
-    // Determine if we need 3 parts (for .co.uk, .com.au style TLDs)
-    let tld = parts[parts.len() - 1];
-    let num_parts = if parts.len() >= 3
-        && tld.len() == 2
-        && matches!(parts[parts.len() - 2], "co" | "com")
-    {
-        3
-    } else {
-        2
-    };
+/// Check a Unicode domain for mixed-script and whole-script homographs
+///
+/// Algorithm (a simplified form of Chrome's IDN display policy), per label:
+/// 1. Collect the scripts used, ignoring Common characters
+/// 2. More than one script is a spoof unless the set is a CJK+Latin mix
+///    from `ALLOWED_SCRIPT_MIXES`
+/// 3. A label that is entirely Cyrillic or Greek and made only of
+///    Latin-lookalike letters is a spoof
+pub fn detect_homograph(unicode_domain: &str) -> Option<SpoofWarning> {
+    for label in unicode_domain.split('.') {
+        if label.is_ascii() {
+            continue;
+        }
+
+        let mut scripts: Vec<Script> = label
+            .chars()
+            .map(script_of)
+            .filter(|s| *s != Script::Common)
+            .collect();
+        scripts.sort_unstable_by_key(|s| *s as u8);
+        scripts.dedup();
+
+        let mixed = scripts.len() > 1
+            && !ALLOWED_SCRIPT_MIXES
+                .iter()
+                .any(|allowed| scripts.iter().all(|s| allowed.contains(s)));
+        if mixed {
+            return Some(SpoofWarning::MixedScript { label: label.to_string() });
+        }
+
+        if let [script @ (Script::Cyrillic | Script::Greek)] = scripts[..] {
+            let lookalikes = if script == Script::Cyrillic {
+                CYRILLIC_LATIN_LOOKALIKES
+            } else {
+                GREEK_LATIN_LOOKALIKES
+            };
+            let all_lookalikes = label
+                .chars()
+                .filter(|c| script_of(*c) != Script::Common)
+                .all(|c| c.to_lowercase().all(|lc| lookalikes.contains(lc)));
+            if all_lookalikes {
+                return Some(SpoofWarning::WholeScriptConfusable {
+                    label: label.to_string(),
+                    script,
+                });
+            }
+        }
+    }
 
-    Some(parts[parts.len() - num_parts..].join("."))
+    None
 }
 
+/// Build the display form of an ASCII (punycode) domain
+pub fn display_domain(ascii: &str) -> DisplayDomain {
+    let (unicode, result) = idna::domain_to_unicode(ascii);
+    let unicode = if result.is_ok() { unicode } else { ascii.to_string() };
+    let warning = detect_homograph(&unicode);
+
+    DisplayDomain {
+        ascii: ascii.to_string(),
+        unicode,
+        warning,
+    }
+}
 
-/// Check if a string looks like an IP address
-fn is_ip_address(s: &str) -> bool {
-    // Simple check: if it starts with a digit and contains only digits and dots
-    s.chars().next().map_or(false, |c| c.is_ascii_digit())
-        && s.chars().all(|c| c.is_ascii_digit() || c == '.')
+/// Extract the domain from a URL in both grouping and display forms
+pub fn extract_display_domain(url: &str) -> Option<DisplayDomain> {
+    extract_domain(url).map(|ascii| display_domain(&ascii))
 }
 
 /// Count domain occurrences from a list of URLs
+///
+/// URLs without a domain are counted under their `HostKind` label
+/// ("Browser pages", "Local files", ...) rather than being skipped.
 pub fn count_domains(urls: &[String]) -> HashMap<String, usize> {
     urls.iter()
-        .filter_map(|url| extract_domain(url))
+        .map(|url| group_key(url).1)
         .fold(HashMap::new(), |mut counts, domain| {
             *counts.entry(domain).or_insert(0) += 1;
             counts
@@ -151,12 +449,152 @@ mod tests {
         assert_eq!(extract_domain("https://"), None);
     }
 
+    #[test]
+    fn test_classify_url() {
+        assert_eq!(classify_url("https://github.com/rust-lang"), HostKind::Domain);
+        assert_eq!(classify_url("https://1.example/page"), HostKind::Domain);
+        assert_eq!(classify_url("http://192.168.1.1:8080"), HostKind::Ipv4);
+        assert_eq!(classify_url("http://[::1]:3000/"), HostKind::Ipv6);
+        assert_eq!(classify_url("http://[2001:db8::1]/"), HostKind::Ipv6);
+        assert_eq!(classify_url("http://localhost:3000"), HostKind::Localhost);
+        assert_eq!(classify_url("http://app.localhost"), HostKind::Localhost);
+        assert_eq!(classify_url("file:///home/user/notes.txt"), HostKind::LocalFile);
+        assert_eq!(classify_url("chrome://settings"), HostKind::BrowserInternal);
+        assert_eq!(classify_url("about:blank"), HostKind::BrowserInternal);
+        assert_eq!(classify_url("chrome-extension://abcdef/popup.html"), HostKind::Extension);
+        assert_eq!(classify_url("data:text/plain,hello"), HostKind::Data);
+        assert_eq!(classify_url("javascript:void(0)"), HostKind::Opaque);
+        assert_eq!(classify_url(""), HostKind::Opaque);
+        assert_eq!(classify_url("https://"), HostKind::Opaque);
+    }
+
+    #[test]
+    fn test_extract_domain_ip_and_special_schemes() {
+        assert_eq!(extract_domain("https://1.example/page"), Some("1.example".to_string()));
+        assert_eq!(extract_domain("http://[::1]:3000/"), Some("[::1]".to_string()));
+        assert_eq!(extract_domain("http://app.localhost:8080"), Some("localhost".to_string()));
+        assert_eq!(extract_domain("chrome://settings"), None);
+        assert_eq!(extract_domain("file:///tmp/a.html"), None);
+        assert_eq!(extract_domain("data:text/plain,hello"), None);
+    }
+
+    #[test]
+    fn test_extract_host_and_subdomain() {
+        assert_eq!(extract_host("https://docs.github.com/en"), Some("docs.github.com".to_string()));
+        assert_eq!(extract_subdomain("https://docs.github.com/en"), Some("docs".to_string()));
+        assert_eq!(extract_subdomain("https://a.b.example.co.uk"), Some("a.b".to_string()));
+        assert_eq!(extract_subdomain("https://github.com"), Some(String::new()));
+        assert_eq!(extract_subdomain("chrome://settings"), None);
+    }
+
+    #[test]
+    fn test_group_key() {
+        assert_eq!(group_key("https://www.google.com"), (HostKind::Domain, "google.com".to_string()));
+        assert_eq!(group_key("chrome://history"), (HostKind::BrowserInternal, "Browser pages".to_string()));
+        assert_eq!(group_key("file:///tmp/a.html"), (HostKind::LocalFile, "Local files".to_string()));
+    }
+
+    #[test]
+    fn test_count_domains_keeps_special_urls() {
+        let urls = vec![
+            "https://github.com".to_string(),
+            "chrome://settings".to_string(),
+            "chrome://extensions".to_string(),
+            "file:///tmp/a.html".to_string(),
+        ];
+
+        let counts = count_domains(&urls);
+
+        assert_eq!(counts.get("github.com"), Some(&1));
+        assert_eq!(counts.get("Browser pages"), Some(&2));
+        assert_eq!(counts.get("Local files"), Some(&1));
+        assert_eq!(counts.values().sum::<usize>(), urls.len());
+    }
+
     #[test]
     fn test_extract_domain_io_domains() {
         assert_eq!(extract_domain("https://zinfandel.io"), Some("zinfandel.io".to_string()));
         assert_eq!(extract_domain("https://api.zinfandel.io"), Some("zinfandel.io".to_string()));
     }
 
+    #[test]
+    fn test_extract_domain_public_suffix_list() {
+        assert_eq!(extract_domain("https://foo.github.io/docs"), Some("foo.github.io".to_string()));
+        assert_eq!(extract_domain("https://bar.blogspot.com"), Some("bar.blogspot.com".to_string()));
+        assert_eq!(extract_domain("https://www.x.gov.uk"), Some("x.gov.uk".to_string()));
+        assert_eq!(extract_domain("https://www.a.ac.jp"), Some("a.ac.jp".to_string()));
+        assert_eq!(extract_domain("https://bucket.s3.amazonaws.com"), Some("bucket.s3.amazonaws.com".to_string()));
+        assert_eq!(extract_domain("https://s3.amazonaws.com"), Some("s3.amazonaws.com".to_string()));
+        assert_eq!(extract_domain("https://example.com."), Some("example.com".to_string()));
+    }
+
+    #[test]
+    fn test_extract_domain_wildcard_and_exception_rules() {
+        // *.kawasaki.jp with !city.kawasaki.jp
+        assert_eq!(extract_domain("https://www.foo.kawasaki.jp"), Some("www.foo.kawasaki.jp".to_string()));
+        assert_eq!(extract_domain("https://www.city.kawasaki.jp"), Some("city.kawasaki.jp".to_string()));
+    }
+
+    #[test]
+    fn test_extract_domain_icann_only() {
+        let mode = SuffixMode::IcannOnly;
+        assert_eq!(extract_domain_with_mode("https://foo.github.io", mode), Some("github.io".to_string()));
+        assert_eq!(extract_domain_with_mode("https://bar.blogspot.com", mode), Some("blogspot.com".to_string()));
+        assert_eq!(extract_domain_with_mode("https://news.bbc.co.uk", mode), Some("bbc.co.uk".to_string()));
+    }
+
+    #[test]
+    fn test_extract_domain_idn_is_ascii() {
+        assert_eq!(extract_domain("https://www.bücher.de"), Some("xn--bcher-kva.de".to_string()));
+        assert_eq!(extract_domain("https://shop.xn--bcher-kva.de"), Some("xn--bcher-kva.de".to_string()));
+    }
+
+    #[test]
+    fn test_display_domain_unicode() {
+        let display = display_domain("xn--bcher-kva.de");
+        assert_eq!(display.ascii, "xn--bcher-kva.de");
+        assert_eq!(display.unicode, "bücher.de");
+        assert_eq!(display.warning, None);
+
+        let display = display_domain("google.com");
+        assert_eq!(display.unicode, "google.com");
+        assert_eq!(display.warning, None);
+    }
+
+    #[test]
+    fn test_display_domain_legitimate_scripts() {
+        // Japanese label mixing Han, Hiragana and Katakana
+        assert_eq!(detect_homograph("日本語のテスト.jp"), None);
+        // All-Cyrillic label with letters that do not look Latin
+        assert_eq!(detect_homograph("пример.рф"), None);
+        assert_eq!(detect_homograph("例え.テスト"), None);
+    }
+
+    #[test]
+    fn test_detect_mixed_script_homograph() {
+        // Cyrillic "а" among Latin letters
+        let warning = detect_homograph("\u{0430}pple.com");
+        assert!(matches!(warning, Some(SpoofWarning::MixedScript { .. })));
+
+        let display = extract_display_domain("https://www.xn--pple-43d.com/login").unwrap();
+        assert_eq!(display.ascii, "xn--pple-43d.com");
+        assert_eq!(display.unicode, "\u{0430}pple.com");
+        assert!(display.warning.is_some());
+    }
+
+    #[test]
+    fn test_detect_whole_script_homograph() {
+        // "аррӏе" written entirely in Cyrillic
+        let warning = detect_homograph("\u{0430}\u{0440}\u{0440}\u{04CF}\u{0435}.com");
+        assert_eq!(
+            warning,
+            Some(SpoofWarning::WholeScriptConfusable {
+                label: "\u{0430}\u{0440}\u{0440}\u{04CF}\u{0435}".to_string(),
+                script: Script::Cyrillic,
+            })
+        );
+    }
+
     #[test]
     fn test_count_domains() {
         let urls = vec![
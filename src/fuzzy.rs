@@ -0,0 +1,320 @@
+//! Fuzzy matching for the popup search, in the style of fzf
+//!
+//! A pattern matches when its characters appear in the text in order, not
+//! necessarily next to each other. Matches score higher when characters
+//! start words ("gh" in "GitHub"), follow each other, and leave short gaps,
+//! so "gh rust iss" ranks "GitHub · rust-lang/rust · Issues" first.
+
+use std::ops::Range;
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+/// A word character after whitespace or at the start of the text
+const BONUS_BOUNDARY_WHITE: i64 = 10;
+/// A word character after `/`, `,`, `:`, `;` or `|`
+const BONUS_BOUNDARY_DELIMITER: i64 = 9;
+/// A word character after any other punctuation, or a punctuation character
+const BONUS_BOUNDARY: i64 = 8;
+/// An upper-case letter after a lower-case one, or a digit after a letter
+const BONUS_CAMEL: i64 = 7;
+/// The least a character right after the previous match earns; it also
+/// earns the bonus of the first character of the run
+const BONUS_CONSECUTIVE: i64 = 4;
+/// The first pattern character's bonus counts this many times
+const FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+const NONE: i64 = i64::MIN / 2;
+
+/// Where a pattern matched and how well
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Byte offsets of the matched characters, in order
+    pub positions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Digit,
+}
+
+fn class_of(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::White
+    } else if matches!(c, '/' | ',' | ':' | ';' | '|') {
+        CharClass::Delimiter
+    } else if c.is_lowercase() {
+        CharClass::Lower
+    } else if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Digit
+    } else if c.is_alphabetic() {
+        // Letters without case, e.g. CJK
+        CharClass::Lower
+    } else {
+        CharClass::NonWord
+    }
+}
+
+fn bonus(previous: CharClass, current: CharClass) -> i64 {
+    use CharClass::*;
+    match (previous, current) {
+        (_, White | Delimiter | NonWord) => BONUS_BOUNDARY,
+        (White, _) => BONUS_BOUNDARY_WHITE,
+        (Delimiter, _) => BONUS_BOUNDARY_DELIMITER,
+        (NonWord, _) => BONUS_BOUNDARY,
+        (Lower, Upper) | (Lower | Upper, Digit) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+fn fold(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// Best-scoring way to find `pattern` in `text`, if its characters all
+/// appear in order
+pub fn fuzzy_match(pattern: &str, text: &str, case_sensitive: bool) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().map(|c| fold(c, case_sensitive)).collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let (offsets, chars): (Vec<usize>, Vec<char>) = text.char_indices().unzip();
+    let folded: Vec<char> = chars.iter().map(|&c| fold(c, case_sensitive)).collect();
+
+    // Cheap check before scoring: is it a subsequence at all?
+    let mut remaining = pattern.iter().peekable();
+    for c in &folded {
+        if remaining.peek() == Some(&c) {
+            remaining.next();
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    let n = chars.len();
+    let bonuses: Vec<i64> = (0..n)
+        .map(|j| {
+            let previous = if j == 0 { CharClass::White } else { class_of(chars[j - 1]) };
+            bonus(previous, class_of(chars[j]))
+        })
+        .collect();
+
+    // scores[i][j]: best score with pattern[..=i] matched and pattern[i] at
+    // text[j]; from[i][j]: where pattern[i - 1] matched on that path;
+    // runs[i][j]: the bonus of the run of consecutive matches ending there
+    let mut scores = vec![vec![NONE; n]; pattern.len()];
+    let mut from = vec![vec![usize::MAX; n]; pattern.len()];
+    let mut runs = vec![vec![0; n]; pattern.len()];
+
+    for (i, &p) in pattern.iter().enumerate() {
+        // Best score of pattern[i - 1] matched at k <= j - 2, with the gap
+        // up to j - 1 already charged
+        let mut gapped = NONE;
+        let mut gapped_from = usize::MAX;
+
+        for j in 0..n {
+            if i > 0 && j >= 2 {
+                let opened = scores[i - 1][j - 2] + GAP_START;
+                let extended = gapped + GAP_EXTENSION;
+                if opened >= extended && scores[i - 1][j - 2] > NONE {
+                    gapped = opened;
+                    gapped_from = j - 2;
+                } else if gapped > NONE {
+                    gapped = extended;
+                }
+            }
+            if folded[j] != p {
+                continue;
+            }
+
+            if i == 0 {
+                scores[0][j] = SCORE_MATCH + bonuses[j] * FIRST_CHAR_MULTIPLIER;
+                runs[0][j] = bonuses[j];
+                continue;
+            }
+            let run = if j >= 1 { runs[i - 1][j - 1].max(bonuses[j]) } else { 0 };
+            let consecutive = if j >= 1 && scores[i - 1][j - 1] > NONE {
+                scores[i - 1][j - 1] + SCORE_MATCH + run.max(BONUS_CONSECUTIVE)
+            } else {
+                NONE
+            };
+            let after_gap = if gapped > NONE {
+                gapped + SCORE_MATCH + bonuses[j]
+            } else {
+                NONE
+            };
+            if consecutive >= after_gap && consecutive > NONE {
+                scores[i][j] = consecutive;
+                from[i][j] = j - 1;
+                runs[i][j] = run;
+            } else if after_gap > NONE {
+                scores[i][j] = after_gap;
+                from[i][j] = gapped_from;
+                runs[i][j] = bonuses[j];
+            }
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut j, score) = scores[last]
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, score)| *score > NONE)
+        .fold(None, |best: Option<(usize, i64)>, (j, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((j, score)),
+        })?;
+
+    let mut positions = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        positions[i] = offsets[j];
+        j = from[i][j];
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+/// How every word of a query matched a set of fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldsMatch {
+    pub score: i64,
+    /// Matched byte offsets in each field, sorted
+    pub positions: Vec<Vec<usize>>,
+}
+
+/// Match each word against whichever of `fields` it fits best; every word
+/// must match somewhere
+///
+/// On equal scores the earlier field wins, so list the one shown to the
+/// user first.
+pub fn match_fields(words: &[String], fields: &[&str], case_sensitive: bool) -> Option<FieldsMatch> {
+    let mut result = FieldsMatch {
+        score: 0,
+        positions: vec![Vec::new(); fields.len()],
+    };
+
+    for word in words {
+        let (field, found) = fields
+            .iter()
+            .enumerate()
+            .filter_map(|(field, text)| fuzzy_match(word, text, case_sensitive).map(|found| (field, found)))
+            .fold(None, |best: Option<(usize, FuzzyMatch)>, (field, found)| match best {
+                Some((_, ref best_found)) if best_found.score >= found.score => best,
+                _ => Some((field, found)),
+            })?;
+        result.score += found.score;
+        result.positions[field].extend(found.positions);
+    }
+
+    for positions in &mut result.positions {
+        positions.sort_unstable();
+        positions.dedup();
+    }
+    Some(result)
+}
+
+/// Byte ranges covering the characters at `positions` (sorted), with
+/// neighbouring characters joined
+pub fn match_ranges(text: &str, positions: &[usize]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for &start in positions {
+        let Some(c) = text.get(start..).and_then(|rest| rest.chars().next()) else {
+            continue;
+        };
+        let end = start + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISSUES: &str = "GitHub · rust-lang/rust · Issues";
+
+    fn words(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn matched(pattern: &str, text: &str) -> String {
+        let found = fuzzy_match(pattern, text, false).unwrap();
+        match_ranges(text, &found.positions)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    #[test]
+    fn test_subsequence_required() {
+        assert!(fuzzy_match("ghi", ISSUES, false).is_some());
+        assert!(fuzzy_match("sig", ISSUES, false).is_none());
+        assert!(fuzzy_match("xyz", ISSUES, false).is_none());
+        assert_eq!(fuzzy_match("", ISSUES, false).unwrap().score, 0);
+    }
+
+    #[test]
+    fn test_prefers_word_starts() {
+        assert_eq!(matched("gh", ISSUES), "G|H");
+        assert_eq!(matched("rl", "rust-lang"), "r|l");
+        assert_eq!(matched("iss", ISSUES), "Iss");
+        assert_eq!(matched("lang", "rust-lang/rust"), "lang");
+    }
+
+    #[test]
+    fn test_ranks_boundaries_and_runs_higher() {
+        let score = |pattern: &str, text: &str| fuzzy_match(pattern, text, false).unwrap().score;
+
+        assert!(score("iss", "Issues") > score("iss", "Mississippi"));
+        assert!(score("doc", "docs.rs") > score("doc", "d-o-c"));
+        assert!(score("gh", "GitHub") > score("gh", "eight"));
+    }
+
+    #[test]
+    fn test_case_sensitive() {
+        assert!(fuzzy_match("GH", ISSUES, true).is_some());
+        assert!(fuzzy_match("gh", "GITHUB", true).is_none());
+        assert!(fuzzy_match("gh", "GITHUB", false).is_some());
+    }
+
+    #[test]
+    fn test_match_fields_needs_every_word() {
+        let fields = [ISSUES, "https://github.com/rust-lang/rust/issues", "github.com"];
+
+        let found = match_fields(&words("gh rust iss"), &fields, false).unwrap();
+        let title: Vec<&str> = match_ranges(ISSUES, &found.positions[0])
+            .into_iter()
+            .map(|range| &ISSUES[range])
+            .collect();
+        assert_eq!(title, vec!["G", "H", "rust", "Iss"]);
+        assert!(match_fields(&words("gh python"), &fields, false).is_none());
+    }
+
+    #[test]
+    fn test_ranges_join_multibyte_neighbours() {
+        let text = "a·b";
+        assert_eq!(match_ranges(text, &[0, 1, 3]), vec![0..4]);
+        assert_eq!(match_ranges(text, &[0, 3]), vec![0..1, 3..4]);
+    }
+}
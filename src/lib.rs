@@ -1,12 +1,29 @@
-/// Tab Hoarder - Chrome Extension for Tab Management
-/// Built with Rust + WASM + Yew
+//! Tab Hoarder - Chrome Extension for Tab Management
+//! Built with Rust + WASM + Yew
 
-mod domain;
-mod tab_data;
-mod operations;
-mod storage;
+// Backend traits (`TabBackend`, `StorageRepository`) use async fns: the
+// extension runs single-threaded in WASM, so their futures never need Send
+#![allow(async_fn_in_trait)]
+
+pub mod domain;
+pub mod public_suffix;
+pub mod tab_data;
+pub mod operations;
+pub mod storage;
+pub mod url_normalizer;
+pub mod undo;
+pub mod restore;
+pub mod search_index;
+pub mod query;
+pub mod fuzzy;
+pub mod switcher;
+pub mod tabs;
+pub mod actions;
 pub mod ui;
 
+#[cfg(test)]
+mod test_support;
+
 use wasm_bindgen::prelude::*;
 
 // Set up panic hook for better error messages in the browser console
@@ -1,56 +1,929 @@
-/// Tab operations: sorting, uniqueness, etc.
+//! Tab operations: sorting, uniqueness, etc.
 
-use crate::domain::extract_domain;
-use crate::tab_data::TabInfo;
+use crate::domain::{classify_url, extract_host, extract_subdomain, group_key, HostKind};
+use crate::tab_data::{SavedTab, TabInfo};
+use crate::url_normalizer::UrlNormalizer;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 
-/// Sort tabs by domain (precompute domain for each tab)
+/// Where tabs without a domain (files, browser pages, ...) go when sorting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NoDomainPlacement {
+    Start,
+    #[default]
+    End,
+}
+
+/// Sort tabs by domain, placing tabs without a domain at the end
 pub fn sort_tabs_by_domain(tabs: &[TabInfo]) -> Vec<TabInfo> {
-    let mut tabs_with_domain: Vec<(TabInfo, String)> = tabs
-        .iter()
-        .filter_map(|tab| {
-            extract_domain(&tab.url).map(|domain| (tab.clone(), domain))
-        })
+    sort_tabs_by_domain_with(tabs, NoDomainPlacement::End)
+}
+
+/// Sort tabs by domain (precompute domain for each tab)
+///
+/// Every tab is kept: the result is always a permutation of `tabs`. Tabs
+/// with a domain, IP address or localhost host sort by that key; the rest
+/// (files, browser pages, extensions, data: and other URLs) form one block
+/// per `HostKind`, placed before or after the domains per `placement`.
+pub fn sort_tabs_by_domain_with(tabs: &[TabInfo], placement: NoDomainPlacement) -> Vec<TabInfo> {
+    SortStrategy::domain_then_url()
+        .with_no_domain_placement(placement)
+        .sort(tabs)
+}
+
+/// A single key tabs can be ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    /// Registrable domain; tabs without one form per-kind blocks
+    Domain,
+    /// Host labels in front of the domain ("docs" in docs.github.com)
+    Subdomain,
+    /// Full host ("docs.github.com")
+    Host,
+    /// Title, case-insensitive
+    Title,
+    /// Title with digit runs compared numerically ("Part 2" < "Part 10")
+    NaturalTitle,
+    /// Full URL
+    Url,
+    /// URL path and query ("/rust-lang/rust/issues?q=is:open")
+    UrlPath,
+    /// Time the tab was last focused, oldest first
+    LastAccessed,
+    /// Order the tabs were opened in (Chrome assigns increasing tab IDs)
+    OpenOrder,
+    /// Number of tabs sharing the tab's domain, largest group first
+    GroupCountDesc,
+}
+
+/// One step of a sort: a key and its direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortCriterion {
+    pub key: SortKey,
+    pub reverse: bool,
+}
+
+/// An ordered list of sort keys, applied left to right
+///
+/// Sorting is stable: tabs equal on every key keep their current order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortStrategy {
+    pub criteria: Vec<SortCriterion>,
+    #[serde(default)]
+    pub no_domain: NoDomainPlacement,
+}
+
+impl SortStrategy {
+    pub fn new() -> Self {
+        SortStrategy {
+            criteria: Vec::new(),
+            no_domain: NoDomainPlacement::End,
+        }
+    }
+
+    /// The original ordering: domain, then full URL
+    pub fn domain_then_url() -> Self {
+        SortStrategy::new().then_by(SortKey::Domain).then_by(SortKey::Url)
+    }
+
+    /// Append a key, ascending
+    pub fn then_by(mut self, key: SortKey) -> Self {
+        self.criteria.push(SortCriterion { key, reverse: false });
+        self
+    }
+
+    /// Append a key, descending
+    pub fn then_by_desc(mut self, key: SortKey) -> Self {
+        self.criteria.push(SortCriterion { key, reverse: true });
+        self
+    }
+
+    /// Flip the direction of every key
+    pub fn reversed(mut self) -> Self {
+        for criterion in &mut self.criteria {
+            criterion.reverse = !criterion.reverse;
+        }
+        self
+    }
+
+    pub fn with_no_domain_placement(mut self, placement: NoDomainPlacement) -> Self {
+        self.no_domain = placement;
+        self
+    }
+
+    /// Sort tabs; the result is always a permutation of `tabs`
+    pub fn sort(&self, tabs: &[TabInfo]) -> Vec<TabInfo> {
+        let keyed: Vec<TabKeys> = tabs.iter().map(TabKeys::new).collect();
+
+        let mut group_counts: HashMap<&str, usize> = HashMap::new();
+        if self.uses(SortKey::GroupCountDesc) {
+            for keys in &keyed {
+                *group_counts.entry(keys.group.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        let mut order: Vec<usize> = (0..tabs.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.criteria
+                .iter()
+                .map(|criterion| {
+                    let ordering = self.compare(criterion.key, &keyed[a], &keyed[b], &group_counts);
+                    if criterion.reverse { ordering.reverse() } else { ordering }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        order.into_iter().map(|i| tabs[i].clone()).collect()
+    }
+
+    fn uses(&self, key: SortKey) -> bool {
+        self.criteria.iter().any(|criterion| criterion.key == key)
+    }
+
+    fn compare(
+        &self,
+        key: SortKey,
+        a: &TabKeys,
+        b: &TabKeys,
+        group_counts: &HashMap<&str, usize>,
+    ) -> Ordering {
+        match key {
+            SortKey::Domain => sort_bucket(a.kind, self.no_domain)
+                .cmp(&sort_bucket(b.kind, self.no_domain))
+                .then_with(|| a.group.cmp(&b.group)),
+            SortKey::Subdomain => a.subdomain.cmp(&b.subdomain),
+            SortKey::Host => a.host.cmp(&b.host),
+            SortKey::Title => a.title_lower.cmp(&b.title_lower),
+            SortKey::NaturalTitle => natural_cmp(&a.tab.title, &b.tab.title),
+            SortKey::Url => a.tab.url.cmp(&b.tab.url),
+            SortKey::UrlPath => a.path.cmp(&b.path),
+            SortKey::LastAccessed => a
+                .tab
+                .last_accessed
+                .unwrap_or(0.0)
+                .total_cmp(&b.tab.last_accessed.unwrap_or(0.0)),
+            SortKey::OpenOrder => a.tab.id.cmp(&b.tab.id),
+            SortKey::GroupCountDesc => {
+                let count = |keys: &TabKeys| group_counts.get(keys.group.as_str()).copied().unwrap_or(0);
+                count(b).cmp(&count(a))
+            }
+        }
+    }
+}
+
+impl Default for SortStrategy {
+    fn default() -> Self {
+        Self::domain_then_url()
+    }
+}
+
+/// Ready-made strategies offered in the Sort/unique pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortPreset {
+    DomainThenUrl,
+    BiggestDomainsFirst,
+    DomainSubdomainTitle,
+    HostThenPath,
+    Title,
+    RecentlyUsedFirst,
+    OpenOrder,
+}
+
+impl SortPreset {
+    pub const ALL: [SortPreset; 7] = [
+        SortPreset::DomainThenUrl,
+        SortPreset::BiggestDomainsFirst,
+        SortPreset::DomainSubdomainTitle,
+        SortPreset::HostThenPath,
+        SortPreset::Title,
+        SortPreset::RecentlyUsedFirst,
+        SortPreset::OpenOrder,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortPreset::DomainThenUrl => "Domain, then URL",
+            SortPreset::BiggestDomainsFirst => "Biggest domains first",
+            SortPreset::DomainSubdomainTitle => "Domain, subdomain, then title",
+            SortPreset::HostThenPath => "Full host, then path",
+            SortPreset::Title => "Title",
+            SortPreset::RecentlyUsedFirst => "Recently used first",
+            SortPreset::OpenOrder => "Order opened",
+        }
+    }
+
+    pub fn strategy(self) -> SortStrategy {
+        match self {
+            SortPreset::DomainThenUrl => SortStrategy::domain_then_url(),
+            SortPreset::BiggestDomainsFirst => SortStrategy::new()
+                .then_by(SortKey::GroupCountDesc)
+                .then_by(SortKey::Domain)
+                .then_by(SortKey::Url),
+            SortPreset::DomainSubdomainTitle => SortStrategy::new()
+                .then_by(SortKey::Domain)
+                .then_by(SortKey::Subdomain)
+                .then_by(SortKey::NaturalTitle),
+            SortPreset::HostThenPath => SortStrategy::new()
+                .then_by(SortKey::Host)
+                .then_by(SortKey::UrlPath),
+            SortPreset::Title => SortStrategy::new().then_by(SortKey::NaturalTitle),
+            SortPreset::RecentlyUsedFirst => SortStrategy::new().then_by_desc(SortKey::LastAccessed),
+            SortPreset::OpenOrder => SortStrategy::new().then_by(SortKey::OpenOrder),
+        }
+    }
+}
+
+/// How Chrome tab groups are treated when sorting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GroupHandling {
+    /// Sort inside each group; groups keep their slots and ungrouped tabs
+    /// are sorted into the remaining slots
+    #[default]
+    WithinGroups,
+    /// Sort inside each group, then sort groups and ungrouped tabs together,
+    /// each group placed by its first tab
+    GroupsAsUnits,
+}
+
+/// A run of tabs moved together: one ungrouped tab, or a whole tab group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortUnit {
+    pub group_id: Option<i32>,
+    pub tab_ids: Vec<i32>,
+}
+
+/// Target layout for a sort, as consumed by `applySortPlan` in tabs.js
+///
+/// Pinned tabs are never moved: units are placed from `start_index`, the
+/// first index after the pinned block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortPlan {
+    pub start_index: i32,
+    pub units: Vec<SortUnit>,
+}
+
+impl SortPlan {
+    /// Unpinned tab IDs in their final left-to-right order
+    pub fn tab_order(&self) -> Vec<i32> {
+        self.units.iter().flat_map(|unit| unit.tab_ids.iter().copied()).collect()
+    }
+
+    /// The same plan limited to tabs that are still open and unpinned
+    ///
+    /// Tabs closed or pinned since the plan was made are dropped, a group
+    /// that no longer exists is placed as plain tabs, and units start after
+    /// the window's current pinned block.
+    pub fn restricted_to(&self, open: &[TabInfo]) -> SortPlan {
+        let movable: HashSet<i32> = open.iter().filter(|tab| !tab.pinned).map(|tab| tab.id).collect();
+        let groups: HashSet<i32> = open.iter().filter_map(|tab| tab.group_id).collect();
+        SortPlan {
+            start_index: open.iter().filter(|tab| tab.pinned).count() as i32,
+            units: self
+                .units
+                .iter()
+                .map(|unit| SortUnit {
+                    group_id: unit.group_id.filter(|group_id| groups.contains(group_id)),
+                    tab_ids: unit.tab_ids.iter().copied().filter(|id| movable.contains(id)).collect(),
+                })
+                .filter(|unit| !unit.tab_ids.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// Plan a sort that leaves pinned tabs in place and keeps tab groups intact
+///
+/// Algorithm:
+/// 1. Order tabs by their current index and set the pinned ones aside
+/// 2. Split the unpinned tabs into units: each group (as it currently
+///    appears) is one unit, each ungrouped tab is its own unit
+/// 3. Sort the tabs inside every group with `strategy`
+/// 4. WithinGroups: sort the ungrouped tabs and put them back into the
+///    slots ungrouped tabs occupied, leaving groups where they were.
+///    GroupsAsUnits: sort all units by their first tab.
+pub fn plan_sort(tabs: &[TabInfo], strategy: &SortStrategy, groups: GroupHandling) -> SortPlan {
+    let (pinned_count, mut units) = current_units(tabs);
+
+    for (group_id, members) in &mut units {
+        if group_id.is_some() {
+            *members = strategy.sort(members);
+        }
+    }
+
+    let units: Vec<TabUnit> = match groups {
+        GroupHandling::WithinGroups => {
+            let ungrouped: Vec<TabInfo> = units
+                .iter()
+                .filter(|(group_id, _)| group_id.is_none())
+                .map(|(_, members)| members[0].clone())
+                .collect();
+            let mut sorted_ungrouped = strategy.sort(&ungrouped).into_iter();
+
+            units
+                .into_iter()
+                .map(|(group_id, members)| match group_id {
+                    Some(_) => (group_id, members),
+                    None => (None, vec![sorted_ungrouped.next().unwrap_or_else(|| members[0].clone())]),
+                })
+                .collect()
+        }
+        GroupHandling::GroupsAsUnits => {
+            let leaders: Vec<TabInfo> = units.iter().map(|(_, members)| members[0].clone()).collect();
+            let sorted_leaders = strategy.sort(&leaders);
+
+            // Leaders are unique tabs, so map each back to its unit
+            let mut by_leader: HashMap<i32, TabUnit> = units
+                .into_iter()
+                .map(|unit| (unit.1[0].id, unit))
+                .collect();
+            sorted_leaders
+                .iter()
+                .filter_map(|leader| by_leader.remove(&leader.id))
+                .collect()
+        }
+    };
+
+    SortPlan {
+        start_index: pinned_count as i32,
+        units: units
+            .into_iter()
+            .map(|(group_id, members)| SortUnit {
+                group_id,
+                tab_ids: members.iter().map(|tab| tab.id).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Plan that puts tabs back in the order they have in `tabs`
+///
+/// Used to undo a sort: record this before moving anything, then apply it
+/// (narrowed with `SortPlan::restricted_to`) to restore the old layout.
+pub fn plan_current_order(tabs: &[TabInfo]) -> SortPlan {
+    let (pinned_count, units) = current_units(tabs);
+    SortPlan {
+        start_index: pinned_count as i32,
+        units: units
+            .into_iter()
+            .map(|(group_id, members)| SortUnit {
+                group_id,
+                tab_ids: members.iter().map(|tab| tab.id).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// A unit being planned: its group and member tabs
+type TabUnit = (Option<i32>, Vec<TabInfo>);
+
+/// Pinned tab count and the unpinned tabs split into units, in index order
+///
+/// Each group (as it currently appears) is one unit; each ungrouped tab is
+/// its own unit.
+fn current_units(tabs: &[TabInfo]) -> (usize, Vec<TabUnit>) {
+    let mut ordered: Vec<&TabInfo> = tabs.iter().collect();
+    ordered.sort_by_key(|tab| tab.index);
+
+    let pinned_count = ordered.iter().filter(|tab| tab.pinned).count();
+    let unpinned: Vec<TabInfo> = ordered
+        .into_iter()
+        .filter(|tab| !tab.pinned)
+        .cloned()
         .collect();
 
-    tabs_with_domain.sort_unstable_by(|a, b| {
-        a.1.cmp(&b.1).then_with(|| a.0.url.cmp(&b.0.url))
-    });
+    // Build units in current order, merging tabs of the same group
+    let mut units: Vec<TabUnit> = Vec::new();
+    for tab in unpinned {
+        let existing = tab
+            .group_id
+            .and_then(|group_id| units.iter().position(|(id, _)| *id == Some(group_id)));
+        match existing {
+            Some(position) => units[position].1.push(tab),
+            None => units.push((tab.group_id, vec![tab])),
+        }
+    }
+
+    (pinned_count, units)
+}
+
+/// Precomputed sort keys for one tab
+struct TabKeys<'a> {
+    tab: &'a TabInfo,
+    kind: HostKind,
+    group: String,
+    host: String,
+    subdomain: String,
+    path: String,
+    title_lower: String,
+}
+
+impl<'a> TabKeys<'a> {
+    fn new(tab: &'a TabInfo) -> Self {
+        let (kind, group) = group_key(&tab.url);
+        TabKeys {
+            tab,
+            kind,
+            group,
+            host: extract_host(&tab.url).unwrap_or_default(),
+            subdomain: extract_subdomain(&tab.url).unwrap_or_default(),
+            path: url_path(&tab.url),
+            title_lower: tab.title.to_lowercase(),
+        }
+    }
+}
+
+/// Path and query of a URL, or the whole string if it does not parse
+fn url_path(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(parsed) => match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Compare strings case-insensitively, treating digit runs as numbers
+///
+/// "Chapter 2" < "Chapter 10"; leading zeros are ignored ("07" == "7").
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
 
-    tabs_with_domain.into_iter().map(|(tab, _)| tab).collect()
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_num = take_digits(&mut a_chars);
+                let b_num = take_digits(&mut b_chars);
+                let ordering = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(&b_num));
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering.is_ne() {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Consume a run of ASCII digits, dropping leading zeros
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        if !(digits.is_empty() && c == '0') {
+            digits.push(c);
+        }
+    }
+    digits
 }
 
-/// Make tabs unique by URL (keep first occurrence)
+/// Domain-like hosts share one bucket; each other kind gets its own,
+/// all of them before or after the domain bucket
+fn sort_bucket(kind: HostKind, placement: NoDomainPlacement) -> (bool, Option<HostKind>) {
+    if kind.has_domain() {
+        (placement == NoDomainPlacement::Start, None)
+    } else {
+        (placement == NoDomainPlacement::End, Some(kind))
+    }
+}
+
+/// Longest favicon URL worth saving; larger data: URLs would dwarf the
+/// rest of the tab
+const MAX_SAVED_FAVICON_LEN: usize = 2048;
+
+/// Build the saved form of tabs being collapsed
+///
+/// Returns one SavedTab per input tab, in order, so the tabs closed after
+/// saving are exactly the ones stored. Tabs without a domain are saved
+/// under their `HostKind` label. `saved_at` is left for the caller.
+pub fn saved_tabs_for(tabs: &[TabInfo]) -> Vec<SavedTab> {
+    tabs.iter()
+        .map(|tab| SavedTab {
+            url: tab.url.clone(),
+            title: tab.title.clone(),
+            domain: group_key(&tab.url).1,
+            pinned: tab.pinned,
+            fav_icon_url: tab
+                .fav_icon_url
+                .clone()
+                .filter(|url| !url.is_empty() && url.len() <= MAX_SAVED_FAVICON_LEN),
+            last_accessed: tab.last_accessed,
+            saved_at: None,
+            index: Some(tab.index),
+            window_id: tab.window_id,
+            group: tab.group.clone(),
+            muted: tab.muted,
+            incognito: tab.incognito,
+        })
+        .collect()
+}
+
+/// Make tabs unique by URL (keep first occurrence), using the default
+/// `UrlNormalizer` rules
 pub fn make_tabs_unique(tabs: &[TabInfo]) -> (Vec<TabInfo>, Vec<i32>) {
-    let mut seen_urls = std::collections::HashSet::new();
-    let mut keep_tabs = Vec::new();
-    let mut remove_ids = Vec::new();
+    make_tabs_unique_with(tabs, &UrlNormalizer::default())
+}
+
+/// Make tabs unique by normalized URL (keep first occurrence)
+///
+/// Opaque URLs (blank, javascript:, ...) say nothing about the page, so
+/// those tabs are never treated as duplicates of each other.
+pub fn make_tabs_unique_with(tabs: &[TabInfo], normalizer: &UrlNormalizer) -> (Vec<TabInfo>, Vec<i32>) {
+    let result = find_duplicates(tabs, normalizer, KeepPolicy::FirstSeen);
+    (result.keep, result.remove_ids)
+}
 
-    for tab in tabs {
-        if seen_urls.contains(&tab.url) {
+/// Which tab survives when several tabs show the same page
+///
+/// Every policy falls back to the leftmost tab when it does not single out
+/// one tab (e.g. PreferPinned with no pinned copy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum KeepPolicy {
+    /// The copy that comes first in the input slice
+    FirstSeen,
+    /// The copy with the lowest tab index
+    #[default]
+    Leftmost,
+    PreferPinned,
+    PreferActive,
+    PreferMostRecent,
+    /// The copy that belongs to a tab group
+    PreferGrouped,
+}
+
+impl KeepPolicy {
+    /// Policies offered in the Sort/unique pane
+    pub const CHOICES: [KeepPolicy; 5] = [
+        KeepPolicy::Leftmost,
+        KeepPolicy::PreferPinned,
+        KeepPolicy::PreferActive,
+        KeepPolicy::PreferMostRecent,
+        KeepPolicy::PreferGrouped,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KeepPolicy::FirstSeen => "First in list",
+            KeepPolicy::Leftmost => "Keep leftmost",
+            KeepPolicy::PreferPinned => "Keep pinned",
+            KeepPolicy::PreferActive => "Keep active tab",
+            KeepPolicy::PreferMostRecent => "Keep most recently used",
+            KeepPolicy::PreferGrouped => "Keep grouped tab",
+        }
+    }
+
+    /// Ordering where the tab to keep compares as the smallest
+    fn compare(self, a: &TabInfo, b: &TabInfo) -> Ordering {
+        let preference = match self {
+            KeepPolicy::FirstSeen | KeepPolicy::Leftmost => Ordering::Equal,
+            KeepPolicy::PreferPinned => b.pinned.cmp(&a.pinned),
+            KeepPolicy::PreferActive => b.active.cmp(&a.active),
+            KeepPolicy::PreferMostRecent => b
+                .last_accessed
+                .unwrap_or(0.0)
+                .total_cmp(&a.last_accessed.unwrap_or(0.0)),
+            KeepPolicy::PreferGrouped => b.group_id.is_some().cmp(&a.group_id.is_some()),
+        };
+        match self {
+            KeepPolicy::FirstSeen => preference,
+            _ => preference.then_with(|| a.index.cmp(&b.index)),
+        }
+    }
+}
+
+/// Tabs that normalize to the same URL, and which one was kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateCluster {
+    /// Normalized URL shared by every tab in the cluster
+    pub key: String,
+    pub kept: TabInfo,
+    pub closed: Vec<TabInfo>,
+}
+
+/// Outcome of duplicate detection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupResult {
+    /// Tabs that stay open, in input order
+    pub keep: Vec<TabInfo>,
+    /// IDs of tabs to close, in input order
+    pub remove_ids: Vec<i32>,
+    /// One entry per URL that had more than one tab, in order of first
+    /// appearance
+    pub clusters: Vec<DuplicateCluster>,
+}
+
+/// Find duplicate tabs by normalized URL and choose survivors with `policy`
+///
+/// Opaque URLs (blank, javascript:, ...) say nothing about the page, so
+/// those tabs are never treated as duplicates of each other.
+pub fn find_duplicates(tabs: &[TabInfo], normalizer: &UrlNormalizer, policy: KeepPolicy) -> DedupResult {
+    // Cluster tab positions by normalized URL, in order of first appearance
+    let mut cluster_index: HashMap<String, usize> = HashMap::new();
+    let mut clusters: Vec<(String, Vec<usize>)> = Vec::new();
+    for (position, tab) in tabs.iter().enumerate() {
+        if classify_url(&tab.url) == HostKind::Opaque {
+            continue;
+        }
+        let key = normalizer.normalize(&tab.url);
+        match cluster_index.get(&key) {
+            Some(&i) => clusters[i].1.push(position),
+            None => {
+                cluster_index.insert(key.clone(), clusters.len());
+                clusters.push((key, vec![position]));
+            }
+        }
+    }
+
+    let mut removed = vec![false; tabs.len()];
+    let mut duplicate_clusters = Vec::new();
+    for (key, positions) in clusters.into_iter().filter(|(_, positions)| positions.len() > 1) {
+        // min_by returns the first of equal elements, so ties keep input order
+        let kept = *positions
+            .iter()
+            .min_by(|&&a, &&b| policy.compare(&tabs[a], &tabs[b]))
+            .expect("clusters are never empty");
+
+        let closed: Vec<TabInfo> = positions
+            .iter()
+            .filter(|&&position| position != kept)
+            .map(|&position| {
+                removed[position] = true;
+                tabs[position].clone()
+            })
+            .collect();
+
+        duplicate_clusters.push(DuplicateCluster {
+            key,
+            kept: tabs[kept].clone(),
+            closed,
+        });
+    }
+
+    let mut keep = Vec::new();
+    let mut remove_ids = Vec::new();
+    for (tab, removed) in tabs.iter().zip(removed) {
+        if removed {
             remove_ids.push(tab.id);
         } else {
-            seen_urls.insert(tab.url.clone());
-            keep_tabs.push(tab.clone());
+            keep.push(tab.clone());
         }
     }
 
-    (keep_tabs, remove_ids)
+    DedupResult {
+        keep,
+        remove_ids,
+        clusters: duplicate_clusters,
+    }
+}
+
+/// Destructive operation an `OperationPlan` describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlannedOperation {
+    /// Close duplicate tabs
+    RemoveDuplicates,
+    /// Save tabs to a new session, then close them
+    Collapse,
+}
+
+/// Why a tab is part of a plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlanReason {
+    /// Same page as the tab that stays open
+    DuplicateOf { kept_id: i32, kept_title: String },
+    /// Written to the new session before closing
+    SaveToSession,
+}
+
+impl PlanReason {
+    pub fn description(&self) -> String {
+        match self {
+            PlanReason::DuplicateOf { kept_title, .. } => format!("Duplicate of \"{}\"", kept_title),
+            PlanReason::SaveToSession => "Save to session".to_string(),
+        }
+    }
+}
+
+/// One tab the plan would act on; unchecked items are left alone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanItem {
+    pub tab: TabInfo,
+    pub reason: PlanReason,
+    pub included: bool,
+}
+
+/// Session a collapse plan writes before closing any tab
+///
+/// Its tabs are the checked `SaveToSession` items, so unchecking a tab
+/// also leaves it out of the session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedSession {
+    pub name: String,
+}
+
+/// Reviewable description of a destructive operation
+///
+/// Built by `plan_remove_duplicates` or `plan_collapse` without touching the
+/// browser. The popup shows the session to write and the tabs to close,
+/// lets the user uncheck tabs, and then applies only what is still
+/// included. Neither operation moves tabs; sorting has its own undoable
+/// `SortPlan`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationPlan {
+    pub operation: PlannedOperation,
+    pub items: Vec<PlanItem>,
+    /// Session written first (collapse only)
+    pub session: Option<PlannedSession>,
+}
+
+impl OperationPlan {
+    /// Whether the plan has nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Flip the `included` flag of the item for `tab_id`
+    pub fn toggle(&mut self, tab_id: i32) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.tab.id == tab_id) {
+            item.included = !item.included;
+        }
+    }
+
+    /// Include or exclude every item
+    pub fn set_all(&mut self, included: bool) {
+        for item in &mut self.items {
+            item.included = included;
+        }
+    }
+
+    /// Number of items still included
+    pub fn included_count(&self) -> usize {
+        self.items.iter().filter(|item| item.included).count()
+    }
+
+    /// Included tabs that will be closed, in plan order
+    ///
+    /// An included duplicate is skipped when the tab it copies is part of
+    /// the plan but unchecked, so closing it never loses the page.
+    pub fn included_tabs(&self) -> Vec<TabInfo> {
+        let unchecked = |id: i32| self.items.iter().any(|item| item.tab.id == id && !item.included);
+        self.items
+            .iter()
+            .filter(|item| item.included)
+            .filter(|item| !matches!(item.reason, PlanReason::DuplicateOf { kept_id, .. } if unchecked(kept_id)))
+            .map(|item| item.tab.clone())
+            .collect()
+    }
+
+    /// IDs of the tabs to close
+    pub fn tabs_to_close(&self) -> Vec<i32> {
+        self.included_tabs().iter().map(|tab| tab.id).collect()
+    }
+
+    /// Tabs to write to the new session (empty unless collapsing)
+    pub fn tabs_to_save(&self) -> Vec<SavedTab> {
+        if self.session.is_none() {
+            return Vec::new();
+        }
+        let saved: Vec<TabInfo> = self
+            .items
+            .iter()
+            .filter(|item| item.included && item.reason == PlanReason::SaveToSession)
+            .map(|item| item.tab.clone())
+            .collect();
+        saved_tabs_for(&saved)
+    }
+
+    /// The session entry for review, e.g. `Save "Research" with 12 tabs`
+    pub fn session_description(&self) -> Option<String> {
+        let session = self.session.as_ref()?;
+        let count = self.tabs_to_save().len();
+        Some(format!(
+            "Save \"{}\" with {} tab{}",
+            session.name,
+            count,
+            if count == 1 { "" } else { "s" }
+        ))
+    }
+}
+
+/// Plan closing every duplicate tab, with `policy` choosing the survivors
+pub fn plan_remove_duplicates(tabs: &[TabInfo], normalizer: &UrlNormalizer, policy: KeepPolicy) -> OperationPlan {
+    let result = find_duplicates(tabs, normalizer, policy);
+
+    let mut items: Vec<PlanItem> = result
+        .clusters
+        .into_iter()
+        .flat_map(|cluster| {
+            let reason = PlanReason::DuplicateOf {
+                kept_id: cluster.kept.id,
+                kept_title: cluster.kept.title,
+            };
+            cluster.closed.into_iter().map(move |tab| PlanItem {
+                tab,
+                reason: reason.clone(),
+                included: true,
+            })
+        })
+        .collect();
+
+    // Review the tabs in window order rather than cluster by cluster
+    items.sort_by_key(|item| item.tab.index);
+
+    OperationPlan {
+        operation: PlannedOperation::RemoveDuplicates,
+        items,
+        session: None,
+    }
+}
+
+/// Plan collapsing the window into a session named `session_name`
+///
+/// Algorithm:
+/// 1. Sort by domain so the session is grouped like the viewer shows it
+/// 2. Keep one tab of each duplicate URL, chosen by `policy` (so a pinned
+///    or grouped copy can be the one saved, with that metadata); it is
+///    saved and then closed
+/// 3. List the other copies right after it; they are never saved, and are
+///    closed too only when `close_duplicates` is set (or the user checks
+///    them during review)
+pub fn plan_collapse(
+    tabs: &[TabInfo],
+    normalizer: &UrlNormalizer,
+    policy: KeepPolicy,
+    placement: NoDomainPlacement,
+    close_duplicates: bool,
+    session_name: &str,
+) -> OperationPlan {
+    let sorted = sort_tabs_by_domain_with(tabs, placement);
+    let result = find_duplicates(&sorted, normalizer, policy);
+
+    let mut items = Vec::new();
+    for tab in result.keep {
+        let copies: Vec<PlanItem> = result
+            .clusters
+            .iter()
+            .filter(|cluster| cluster.kept.id == tab.id)
+            .flat_map(|cluster| {
+                let reason = PlanReason::DuplicateOf {
+                    kept_id: cluster.kept.id,
+                    kept_title: cluster.kept.title.clone(),
+                };
+                cluster.closed.iter().map(move |copy| PlanItem {
+                    tab: copy.clone(),
+                    reason: reason.clone(),
+                    included: close_duplicates,
+                })
+            })
+            .collect();
+
+        items.push(PlanItem {
+            tab,
+            reason: PlanReason::SaveToSession,
+            included: true,
+        });
+        items.extend(copies);
+    }
+
+    OperationPlan {
+        operation: PlannedOperation::Collapse,
+        items,
+        session: Some(PlannedSession {
+            name: session_name.to_string(),
+        }),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::extract_domain;
 
     fn create_test_tab(id: i32, url: &str, title: &str) -> TabInfo {
+        TabInfo::new(id, url.to_string(), title.to_string(), false, id)
+    }
+
+    fn create_grouped_tab(id: i32, url: &str, group_id: i32) -> TabInfo {
         TabInfo {
-            id,
-            url: url.to_string(),
-            title: title.to_string(),
-            pinned: false,
-            index: id,
+            group_id: Some(group_id),
+            ..create_test_tab(id, url, url)
         }
     }
 
+    fn titles(tabs: &[TabInfo]) -> Vec<&str> {
+        tabs.iter().map(|t| t.title.as_str()).collect()
+    }
+
     #[test]
     fn test_sort_tabs_by_domain() {
         let tabs = vec![
@@ -69,6 +942,301 @@ mod tests {
         assert_eq!(extract_domain(&sorted[3].url), Some("microsoft.com".to_string()));
     }
 
+    #[test]
+    fn test_sort_tabs_by_domain_keeps_special_urls() {
+        let tabs = vec![
+            create_test_tab(1, "chrome://settings", "Settings"),
+            create_test_tab(2, "https://github.com/rust", "GitHub Rust"),
+            create_test_tab(3, "file:///tmp/notes.txt", "notes.txt"),
+            create_test_tab(4, "http://[::1]:3000", "Dev server"),
+            create_test_tab(5, "chrome://history", "History"),
+            create_test_tab(6, "data:text/plain,hi", "Data"),
+        ];
+
+        let sorted = sort_tabs_by_domain(&tabs);
+        let ids: Vec<i32> = sorted.iter().map(|t| t.id).collect();
+
+        // Domain-like hosts first ("[::1]" < "github.com"), then one block per kind
+        assert_eq!(ids, vec![4, 2, 3, 5, 1, 6]);
+    }
+
+    fn mixed_tabs() -> Vec<TabInfo> {
+        vec![
+            create_test_tab(1, "chrome://settings", "Settings"),
+            create_test_tab(2, "https://github.com/rust", "GitHub Rust"),
+            create_test_tab(3, "", "Loading"),
+            create_test_tab(4, "file:///tmp/notes.txt", "notes.txt"),
+            create_test_tab(5, "https://www.google.com", "Google"),
+            create_test_tab(6, "javascript:void(0)", "Bookmarklet"),
+            create_test_tab(7, "http://localhost:3000", "Dev server"),
+            create_test_tab(8, "chrome-extension://abc/options.html", "Options"),
+            create_test_tab(9, "about:blank", "Blank"),
+            create_test_tab(10, "https://", "Broken"),
+            create_test_tab(11, "data:text/plain,hi", "Data"),
+            create_test_tab(12, "https://github.com/rust", "GitHub Rust again"),
+        ]
+    }
+
+    fn sorted_ids(tabs: &[TabInfo]) -> Vec<i32> {
+        let mut ids: Vec<i32> = tabs.iter().map(|t| t.id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn test_sort_never_loses_tabs() {
+        let tabs = mixed_tabs();
+
+        for placement in [NoDomainPlacement::Start, NoDomainPlacement::End] {
+            let sorted = sort_tabs_by_domain_with(&tabs, placement);
+            assert_eq!(sorted_ids(&sorted), sorted_ids(&tabs));
+        }
+    }
+
+    #[test]
+    fn test_sort_no_domain_placement() {
+        let tabs = mixed_tabs();
+
+        let end = sort_tabs_by_domain_with(&tabs, NoDomainPlacement::End);
+        assert!(end[..4].iter().all(|t| classify_url(&t.url).has_domain()));
+        assert!(end[4..].iter().all(|t| !classify_url(&t.url).has_domain()));
+
+        let start = sort_tabs_by_domain_with(&tabs, NoDomainPlacement::Start);
+        assert!(start[..8].iter().all(|t| !classify_url(&t.url).has_domain()));
+        assert!(start[8..].iter().all(|t| classify_url(&t.url).has_domain()));
+    }
+
+    #[test]
+    fn test_saved_tabs_cover_every_collapsed_tab() {
+        let tabs = mixed_tabs();
+        let (unique, _) = make_tabs_unique(&sort_tabs_by_domain(&tabs));
+
+        let saved = saved_tabs_for(&unique);
+
+        assert_eq!(saved.len(), unique.len());
+        for (tab, saved_tab) in unique.iter().zip(&saved) {
+            assert_eq!(saved_tab.url, tab.url);
+            assert!(!saved_tab.domain.is_empty());
+        }
+        assert_eq!(saved[0].domain, "github.com");
+        assert!(saved.iter().any(|t| t.domain == "Browser pages"));
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("Part 2", "Part 10"), Ordering::Less);
+        assert_eq!(natural_cmp("part 10", "Part 9"), Ordering::Greater);
+        assert_eq!(natural_cmp("v07", "v7"), Ordering::Equal);
+        assert_eq!(natural_cmp("abc", "abcd"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_sort_strategy_natural_title() {
+        let tabs = vec![
+            create_test_tab(1, "https://a.com", "Chapter 10"),
+            create_test_tab(2, "https://b.com", "chapter 2"),
+            create_test_tab(3, "https://c.com", "Chapter 1"),
+        ];
+
+        let sorted = SortStrategy::new().then_by(SortKey::NaturalTitle).sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["Chapter 1", "chapter 2", "Chapter 10"]);
+
+        let sorted = SortStrategy::new().then_by(SortKey::Title).sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["Chapter 1", "Chapter 10", "chapter 2"]);
+    }
+
+    #[test]
+    fn test_sort_strategy_group_count_desc() {
+        let tabs = vec![
+            create_test_tab(1, "https://a.com/1", "a1"),
+            create_test_tab(2, "https://b.com/1", "b1"),
+            create_test_tab(3, "https://b.com/2", "b2"),
+            create_test_tab(4, "https://c.com/1", "c1"),
+            create_test_tab(5, "https://b.com/3", "b3"),
+            create_test_tab(6, "https://c.com/2", "c2"),
+        ];
+
+        let sorted = SortPreset::BiggestDomainsFirst.strategy().sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["b1", "b2", "b3", "c1", "c2", "a1"]);
+    }
+
+    #[test]
+    fn test_sort_strategy_subdomain_and_path() {
+        let tabs = vec![
+            create_test_tab(1, "https://www.github.com/b", "www b"),
+            create_test_tab(2, "https://docs.github.com/z", "docs z"),
+            create_test_tab(3, "https://github.com/a", "bare a"),
+            create_test_tab(4, "https://docs.github.com/a", "docs a"),
+        ];
+
+        let sorted = SortStrategy::new()
+            .then_by(SortKey::Domain)
+            .then_by(SortKey::Subdomain)
+            .then_by(SortKey::UrlPath)
+            .sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["bare a", "docs a", "docs z", "www b"]);
+
+        let sorted = SortStrategy::new().then_by(SortKey::Host).sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["docs z", "docs a", "bare a", "www b"]);
+    }
+
+    #[test]
+    fn test_sort_strategy_last_accessed_and_open_order() {
+        let mut tabs = vec![
+            create_test_tab(3, "https://a.com", "old"),
+            create_test_tab(1, "https://b.com", "new"),
+            create_test_tab(2, "https://c.com", "unknown"),
+        ];
+        tabs[0].last_accessed = Some(1000.0);
+        tabs[1].last_accessed = Some(5000.0);
+
+        let sorted = SortPreset::RecentlyUsedFirst.strategy().sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["new", "old", "unknown"]);
+
+        let sorted = SortPreset::OpenOrder.strategy().sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["new", "unknown", "old"]);
+
+        let sorted = SortPreset::OpenOrder.strategy().reversed().sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["old", "unknown", "new"]);
+    }
+
+    #[test]
+    fn test_sort_strategy_is_stable() {
+        let tabs = vec![
+            create_test_tab(1, "https://a.com/x", "first"),
+            create_test_tab(2, "https://b.com", "other"),
+            create_test_tab(3, "https://a.com/y", "second"),
+            create_test_tab(4, "https://a.com/z", "third"),
+        ];
+
+        let sorted = SortStrategy::new().then_by(SortKey::Domain).sort(&tabs);
+        assert_eq!(titles(&sorted), vec!["first", "second", "third", "other"]);
+    }
+
+    #[test]
+    fn test_sort_presets_never_lose_tabs() {
+        let tabs = mixed_tabs();
+
+        for preset in SortPreset::ALL {
+            let sorted = preset.strategy().sort(&tabs);
+            assert_eq!(sorted_ids(&sorted), sorted_ids(&tabs), "{:?}", preset);
+        }
+    }
+
+    #[test]
+    fn test_plan_sort_leaves_pinned_tabs_in_place() {
+        let mut tabs = vec![
+            create_test_tab(1, "https://zeta.com", "Pinned Zeta"),
+            create_test_tab(2, "https://alpha.com", "Pinned Alpha"),
+            create_test_tab(3, "https://mike.com", "Mike"),
+            create_test_tab(4, "https://bravo.com", "Bravo"),
+        ];
+        tabs[0].pinned = true;
+        tabs[1].pinned = true;
+
+        let plan = plan_sort(&tabs, &SortStrategy::default(), GroupHandling::WithinGroups);
+
+        assert_eq!(plan.start_index, 2);
+        assert_eq!(plan.tab_order(), vec![4, 3]);
+    }
+
+    #[test]
+    fn test_plan_sort_within_groups() {
+        let tabs = vec![
+            create_test_tab(1, "https://zeta.com", "Zeta"),
+            create_grouped_tab(2, "https://yankee.com", 100),
+            create_grouped_tab(3, "https://bravo.com", 100),
+            create_test_tab(4, "https://alpha.com", "Alpha"),
+            create_grouped_tab(5, "https://xray.com", 200),
+            create_test_tab(6, "https://mike.com", "Mike"),
+        ];
+
+        let plan = plan_sort(&tabs, &SortStrategy::default(), GroupHandling::WithinGroups);
+
+        assert_eq!(
+            plan.units,
+            vec![
+                SortUnit { group_id: None, tab_ids: vec![4] },
+                SortUnit { group_id: Some(100), tab_ids: vec![3, 2] },
+                SortUnit { group_id: None, tab_ids: vec![6] },
+                SortUnit { group_id: Some(200), tab_ids: vec![5] },
+                SortUnit { group_id: None, tab_ids: vec![1] },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_sort_groups_as_units() {
+        let tabs = vec![
+            create_test_tab(1, "https://zeta.com", "Zeta"),
+            create_grouped_tab(2, "https://yankee.com", 100),
+            create_grouped_tab(3, "https://charlie.com", 100),
+            create_test_tab(4, "https://alpha.com", "Alpha"),
+            create_grouped_tab(5, "https://xray.com", 200),
+            create_test_tab(6, "https://mike.com", "Mike"),
+        ];
+
+        let plan = plan_sort(&tabs, &SortStrategy::default(), GroupHandling::GroupsAsUnits);
+
+        assert_eq!(plan.tab_order(), vec![4, 3, 2, 6, 5, 1]);
+        assert_eq!(plan.units[1], SortUnit { group_id: Some(100), tab_ids: vec![3, 2] });
+    }
+
+    #[test]
+    fn test_plan_sort_keeps_every_unpinned_tab() {
+        let mut tabs = mixed_tabs();
+        tabs[0].pinned = true;
+        tabs[3].group_id = Some(7);
+        tabs[8].group_id = Some(7);
+
+        for groups in [GroupHandling::WithinGroups, GroupHandling::GroupsAsUnits] {
+            let plan = plan_sort(&tabs, &SortStrategy::default(), groups);
+            let mut order = plan.tab_order();
+            order.sort_unstable();
+            let expected: Vec<i32> = sorted_ids(&tabs).into_iter().filter(|id| *id != 1).collect();
+            assert_eq!(order, expected);
+        }
+    }
+
+    #[test]
+    fn test_plan_current_order_restricted_to_open_tabs() {
+        let mut tabs = vec![
+            create_test_tab(1, "https://zeta.com", "Pinned"),
+            create_test_tab(2, "https://mike.com", "Mike"),
+            create_grouped_tab(3, "https://yankee.com", 100),
+            create_grouped_tab(4, "https://bravo.com", 100),
+            create_test_tab(5, "https://alpha.com", "Alpha"),
+        ];
+        tabs[0].pinned = true;
+
+        let before = plan_current_order(&tabs);
+        assert_eq!(before.start_index, 1);
+        assert_eq!(before.tab_order(), vec![2, 3, 4, 5]);
+        assert_eq!(before.units[1], SortUnit { group_id: Some(100), tab_ids: vec![3, 4] });
+
+        // Tab 2 was closed and tab 1 unpinned after the snapshot
+        tabs.remove(1);
+        tabs[0].pinned = false;
+        let restore = before.restricted_to(&tabs);
+        assert_eq!(restore.start_index, 0);
+        assert_eq!(restore.tab_order(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_make_tabs_unique_ignores_opaque_urls() {
+        let tabs = vec![
+            create_test_tab(1, "", "Loading"),
+            create_test_tab(2, "", "Loading"),
+            create_test_tab(3, "chrome://newtab/", "New Tab"),
+            create_test_tab(4, "chrome://newtab/", "New Tab"),
+        ];
+
+        let (keep, remove) = make_tabs_unique(&tabs);
+
+        assert_eq!(keep.len(), 3);
+        assert_eq!(remove, vec![4]);
+    }
+
     #[test]
     fn test_make_tabs_unique() {
         let tabs = vec![
@@ -87,6 +1255,189 @@ mod tests {
         assert!(remove.contains(&5));
     }
 
+    #[test]
+    fn test_make_tabs_unique_normalizes_urls() {
+        let tabs = vec![
+            create_test_tab(1, "https://x.com/a", "A"),
+            create_test_tab(2, "https://x.com/a/", "A slash"),
+            // Fragments can be whole pages in hash-routed apps
+            create_test_tab(3, "https://x.com/a#section", "A fragment"),
+            create_test_tab(4, "https://www.x.com/a?utm_source=foo", "A tracked"),
+            create_test_tab(5, "https://x.com/b", "B"),
+        ];
+
+        let (keep, remove) = make_tabs_unique(&tabs);
+        assert_eq!(keep.len(), 3);
+        assert_eq!(remove, vec![2, 4]);
+
+        let (keep, remove) = make_tabs_unique_with(&tabs, &UrlNormalizer::exact());
+        assert_eq!(keep.len(), 5);
+        assert!(remove.is_empty());
+    }
+
+    fn duplicate_tabs() -> Vec<TabInfo> {
+        let mut tabs = vec![
+            create_test_tab(1, "https://x.com/a", "leftmost"),
+            create_test_tab(2, "https://x.com/a?fbclid=1", "pinned"),
+            create_test_tab(3, "https://x.com/a/", "active"),
+            create_test_tab(4, "https://x.com/a?utm_source=x", "recent"),
+            create_grouped_tab(5, "https://www.x.com/a", 10),
+            create_test_tab(6, "https://other.com", "unique"),
+        ];
+        tabs[1].pinned = true;
+        tabs[2].active = true;
+        tabs[3].last_accessed = Some(9000.0);
+        tabs[0].last_accessed = Some(1000.0);
+        tabs
+    }
+
+    #[test]
+    fn test_find_duplicates_policies() {
+        let tabs = duplicate_tabs();
+        let normalizer = UrlNormalizer::default();
+
+        let kept_id = |policy| find_duplicates(&tabs, &normalizer, policy).clusters[0].kept.id;
+
+        assert_eq!(kept_id(KeepPolicy::Leftmost), 1);
+        assert_eq!(kept_id(KeepPolicy::PreferPinned), 2);
+        assert_eq!(kept_id(KeepPolicy::PreferActive), 3);
+        assert_eq!(kept_id(KeepPolicy::PreferMostRecent), 4);
+        assert_eq!(kept_id(KeepPolicy::PreferGrouped), 5);
+    }
+
+    #[test]
+    fn test_find_duplicates_reports_clusters() {
+        let tabs = duplicate_tabs();
+
+        let result = find_duplicates(&tabs, &UrlNormalizer::default(), KeepPolicy::PreferActive);
+
+        assert_eq!(result.clusters.len(), 1);
+        let cluster = &result.clusters[0];
+        assert_eq!(cluster.key, "https://x.com/a");
+        assert_eq!(cluster.kept.id, 3);
+        let closed: Vec<i32> = cluster.closed.iter().map(|t| t.id).collect();
+        assert_eq!(closed, vec![1, 2, 4, 5]);
+        assert_eq!(result.remove_ids, vec![1, 2, 4, 5]);
+        let kept: Vec<i32> = result.keep.iter().map(|t| t.id).collect();
+        assert_eq!(kept, vec![3, 6]);
+    }
+
+    #[test]
+    fn test_find_duplicates_falls_back_to_leftmost() {
+        let mut tabs = duplicate_tabs();
+        for tab in &mut tabs {
+            tab.pinned = false;
+        }
+        // Out of index order in the input
+        tabs.swap(0, 2);
+
+        let result = find_duplicates(&tabs, &UrlNormalizer::default(), KeepPolicy::PreferPinned);
+
+        assert_eq!(result.clusters[0].kept.id, 1);
+    }
+
+    #[test]
+    fn test_plan_remove_duplicates_lists_closed_tabs() {
+        let tabs = duplicate_tabs();
+
+        let mut plan = plan_remove_duplicates(&tabs, &UrlNormalizer::default(), KeepPolicy::PreferPinned);
+
+        assert_eq!(plan.operation, PlannedOperation::RemoveDuplicates);
+        assert_eq!(plan.tabs_to_close(), vec![1, 3, 4, 5]);
+        assert_eq!(
+            plan.items[0].reason,
+            PlanReason::DuplicateOf { kept_id: 2, kept_title: "pinned".to_string() }
+        );
+        assert!(plan.tabs_to_save().is_empty());
+
+        plan.toggle(3);
+        assert_eq!(plan.tabs_to_close(), vec![1, 4, 5]);
+        assert_eq!(plan.included_count(), 3);
+
+        plan.set_all(false);
+        assert!(plan.tabs_to_close().is_empty());
+        assert!(!plan.is_empty());
+    }
+
+    #[test]
+    fn test_plan_collapse_saves_only_included_tabs() {
+        let tabs = vec![
+            create_test_tab(1, "https://b.com", "B"),
+            create_test_tab(2, "https://a.com", "A"),
+            create_test_tab(3, "https://a.com/", "A again"),
+            create_test_tab(4, "https://c.com", "C"),
+        ];
+
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Session");
+
+        // Sorted by domain; the duplicate copy is listed but left open
+        assert_eq!(plan.items.len(), 4);
+        assert_eq!(plan.tabs_to_close(), vec![2, 1, 4]);
+
+        plan.toggle(1);
+        assert_eq!(plan.tabs_to_close(), vec![2, 4]);
+        let saved: Vec<String> = plan.tabs_to_save().into_iter().map(|t| t.url).collect();
+        assert_eq!(saved, vec!["https://a.com", "https://c.com"]);
+    }
+
+    #[test]
+    fn test_plan_collapse_closes_duplicates_of_saved_tabs() {
+        let tabs = vec![
+            create_test_tab(1, "https://b.com", "B"),
+            create_test_tab(2, "https://a.com", "A"),
+            create_test_tab(3, "https://a.com/", "A again"),
+        ];
+
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, true, "Session");
+
+        assert_eq!(plan.tabs_to_close(), vec![2, 3, 1]);
+        assert_eq!(
+            plan.items[1].reason,
+            PlanReason::DuplicateOf { kept_id: 2, kept_title: "A".to_string() }
+        );
+        // The copy is closed, never saved
+        assert_eq!(plan.tabs_to_save().len(), 2);
+
+        // Without its saved original, closing the copy would lose the page
+        plan.toggle(2);
+        assert_eq!(plan.tabs_to_close(), vec![1]);
+        assert_eq!(plan.included_count(), 2);
+    }
+
+    #[test]
+    fn test_plan_collapse_describes_session() {
+        let tabs = vec![
+            create_test_tab(1, "https://a.com", "A"),
+            create_test_tab(2, "https://b.com", "B"),
+        ];
+        let mut plan = plan_collapse(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen, NoDomainPlacement::End, false, "Research");
+
+        assert_eq!(plan.session_description().as_deref(), Some("Save \"Research\" with 2 tabs"));
+        plan.toggle(2);
+        assert_eq!(plan.session_description().as_deref(), Some("Save \"Research\" with 1 tab"));
+
+        let unique = plan_remove_duplicates(&tabs, &UrlNormalizer::default(), KeepPolicy::FirstSeen);
+        assert_eq!(unique.session_description(), None);
+        assert!(unique.tabs_to_save().is_empty());
+    }
+
+    #[test]
+    fn test_plan_collapse_follows_keep_policy() {
+        let mut tabs = vec![
+            create_test_tab(1, "https://a.com", "A"),
+            create_test_tab(2, "https://a.com/", "A pinned"),
+        ];
+        tabs[1].pinned = true;
+        let saved = |policy| {
+            let plan = plan_collapse(&tabs, &UrlNormalizer::default(), policy, NoDomainPlacement::End, false, "Session");
+            plan.tabs_to_save().iter().map(|tab| (tab.title.clone(), tab.pinned)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(saved(KeepPolicy::FirstSeen), vec![("A".to_string(), false)]);
+        // The pinned copy is saved, so a restore pins it again
+        assert_eq!(saved(KeepPolicy::PreferPinned), vec![("A pinned".to_string(), true)]);
+    }
+
     #[test]
     fn test_make_tabs_unique_no_duplicates() {
         let tabs = vec![
@@ -0,0 +1,248 @@
+//! Compiled-in Public Suffix List (https://publicsuffix.org)
+//!
+//! The list is embedded at build time from `public_suffix_list.dat` and parsed
+//! lazily on first use. Both the ICANN and the private sections are loaded;
+//! callers decide per lookup whether private suffixes (github.io,
+//! blogspot.com, s3.amazonaws.com, ...) should count.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+const PRIVATE_SECTION_MARKER: &str = "===BEGIN PRIVATE DOMAINS===";
+
+/// Which section of the list a rule came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Icann,
+    Private,
+}
+
+/// Whether private-section rules take part in a lookup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuffixMode {
+    /// Only ICANN suffixes: `foo.github.io` → `github.io`
+    IcannOnly,
+    /// ICANN and private suffixes: `foo.github.io` → `foo.github.io`
+    #[default]
+    IncludePrivate,
+}
+
+impl SuffixMode {
+    fn allows(self, section: Section) -> bool {
+        match self {
+            SuffixMode::IcannOnly => section == Section::Icann,
+            SuffixMode::IncludePrivate => true,
+        }
+    }
+}
+
+/// Parsed rule tables, keyed by the ASCII (punycode) form of each rule
+#[derive(Debug, Default)]
+pub struct PublicSuffixList {
+    /// Plain rules: `co.uk`
+    rules: HashMap<String, Section>,
+    /// Wildcard rules, keyed without the leading `*.`: `*.ck` → `ck`
+    wildcards: HashMap<String, Section>,
+    /// Exception rules, keyed without the leading `!`: `!www.ck` → `www.ck`
+    exceptions: HashMap<String, Section>,
+}
+
+impl PublicSuffixList {
+    /// Parse a list in the publicsuffix.org `.dat` format
+    ///
+    /// Each rule is the first whitespace-delimited token on a line; comments
+    /// start with `//`. Internationalized rules are stored in punycode so they
+    /// match hosts as returned by `url::Url::host_str`.
+    pub fn parse(text: &str) -> PublicSuffixList {
+        let mut list = PublicSuffixList::default();
+        let mut section = Section::Icann;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with("//") {
+                if line.contains(PRIVATE_SECTION_MARKER) {
+                    section = Section::Private;
+                }
+                continue;
+            }
+
+            let Some(rule) = line.split_whitespace().next() else {
+                continue;
+            };
+
+            if let Some(rest) = rule.strip_prefix('!') {
+                list.exceptions.insert(normalize_rule(rest), section);
+            } else if let Some(rest) = rule.strip_prefix("*.") {
+                list.wildcards.insert(normalize_rule(rest), section);
+            } else {
+                list.rules.insert(normalize_rule(rule), section);
+            }
+        }
+
+        list
+    }
+
+    /// Number of trailing labels of `host` that form its public suffix
+    ///
+    /// Algorithm (per publicsuffix.org):
+    /// 1. If an exception rule matches, the suffix is that rule minus its
+    ///    leftmost label
+    /// 2. Otherwise the longest matching plain or wildcard rule wins
+    /// 3. If nothing matches, the implicit `*` rule makes the TLD the suffix
+    pub fn suffix_len(&self, host: &str, mode: SuffixMode) -> usize {
+        let labels: Vec<&str> = host.split('.').collect();
+        let n = labels.len();
+
+        let allowed = |table: &HashMap<String, Section>, key: &str| {
+            table.get(key).is_some_and(|section| mode.allows(*section))
+        };
+
+        for i in 0..n {
+            if allowed(&self.exceptions, &labels[i..].join(".")) {
+                return n - i - 1;
+            }
+        }
+
+        for i in 0..n {
+            let candidate = labels[i..].join(".");
+            let wildcard_parent = (i + 1 < n).then(|| labels[i + 1..].join("."));
+
+            if allowed(&self.rules, &candidate)
+                || wildcard_parent.is_some_and(|parent| allowed(&self.wildcards, &parent))
+            {
+                return n - i;
+            }
+        }
+
+        1
+    }
+
+    /// The public suffix of `host` (e.g. `co.uk` for `news.bbc.co.uk`)
+    pub fn public_suffix<'a>(&self, host: &'a str, mode: SuffixMode) -> &'a str {
+        let len = self.suffix_len(host, mode);
+        last_labels(host, len)
+    }
+
+    /// The registrable domain (eTLD+1) of `host`, or `None` if `host` is
+    /// itself a public suffix
+    pub fn registrable_domain<'a>(&self, host: &'a str, mode: SuffixMode) -> Option<&'a str> {
+        let label_count = host.split('.').count();
+        let len = self.suffix_len(host, mode) + 1;
+        (len <= label_count).then(|| last_labels(host, len))
+    }
+}
+
+/// The embedded list, parsed once on first use
+pub fn public_suffix_list() -> &'static PublicSuffixList {
+    static LIST: OnceLock<PublicSuffixList> = OnceLock::new();
+    LIST.get_or_init(|| PublicSuffixList::parse(PUBLIC_SUFFIX_LIST))
+}
+
+fn normalize_rule(rule: &str) -> String {
+    if rule.is_ascii() {
+        rule.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii(rule).unwrap_or_else(|_| rule.to_lowercase())
+    }
+}
+
+fn last_labels(host: &str, count: usize) -> &str {
+    let mut start = host.len();
+    for _ in 0..count {
+        match host[..start].rfind('.') {
+            Some(pos) => start = pos,
+            None => return host,
+        }
+    }
+    &host[start + 1..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+// ===BEGIN ICANN DOMAINS===
+uk
+co.uk
+jp
+kawasaki.jp
+*.kawasaki.jp
+!city.kawasaki.jp
+ck
+*.ck
+!www.ck
+// ===END ICANN DOMAINS===
+// ===BEGIN PRIVATE DOMAINS===
+github.io
+// ===END PRIVATE DOMAINS===
+";
+
+    #[test]
+    fn test_parse_sections() {
+        let list = PublicSuffixList::parse(SAMPLE);
+
+        assert_eq!(list.rules.get("co.uk"), Some(&Section::Icann));
+        assert_eq!(list.rules.get("github.io"), Some(&Section::Private));
+        assert_eq!(list.wildcards.get("ck"), Some(&Section::Icann));
+        assert_eq!(list.exceptions.get("www.ck"), Some(&Section::Icann));
+    }
+
+    #[test]
+    fn test_wildcard_and_exception_rules() {
+        let list = PublicSuffixList::parse(SAMPLE);
+        let mode = SuffixMode::IncludePrivate;
+
+        assert_eq!(list.registrable_domain("a.b.ck", mode), Some("a.b.ck"));
+        assert_eq!(list.registrable_domain("www.ck", mode), Some("www.ck"));
+        assert_eq!(list.registrable_domain("shop.www.ck", mode), Some("www.ck"));
+        assert_eq!(list.registrable_domain("x.foo.kawasaki.jp", mode), Some("x.foo.kawasaki.jp"));
+        assert_eq!(list.registrable_domain("city.kawasaki.jp", mode), Some("city.kawasaki.jp"));
+    }
+
+    #[test]
+    fn test_private_mode() {
+        let list = PublicSuffixList::parse(SAMPLE);
+
+        assert_eq!(
+            list.registrable_domain("foo.github.io", SuffixMode::IncludePrivate),
+            Some("foo.github.io")
+        );
+        assert_eq!(
+            list.registrable_domain("foo.github.io", SuffixMode::IcannOnly),
+            Some("github.io")
+        );
+    }
+
+    #[test]
+    fn test_unlisted_tld_uses_default_rule() {
+        let list = PublicSuffixList::parse(SAMPLE);
+
+        assert_eq!(list.public_suffix("api.example.zz", SuffixMode::IncludePrivate), "zz");
+        assert_eq!(
+            list.registrable_domain("api.example.zz", SuffixMode::IncludePrivate),
+            Some("example.zz")
+        );
+    }
+
+    #[test]
+    fn test_host_that_is_a_suffix() {
+        let list = PublicSuffixList::parse(SAMPLE);
+
+        assert_eq!(list.registrable_domain("co.uk", SuffixMode::IncludePrivate), None);
+        assert_eq!(list.registrable_domain("github.io", SuffixMode::IncludePrivate), None);
+    }
+
+    #[test]
+    fn test_embedded_list_parses() {
+        let list = public_suffix_list();
+
+        assert!(list.rules.len() > 5000);
+        assert_eq!(list.rules.get("com"), Some(&Section::Icann));
+        assert_eq!(list.rules.get("blogspot.com"), Some(&Section::Private));
+        // Internationalized rules are stored in punycode
+        assert!(list.rules.contains_key("xn--fiqs8s"));
+    }
+}
@@ -0,0 +1,809 @@
+//! Search query language shared by the popup and the collapsed viewer
+//!
+//! ```text
+//! rust domain:github.com            both must match (AND is implied)
+//! title:"design doc" OR url:/issues/
+//! NOT is:pinned   -session:"Sprint 12"
+//! (docs OR wiki) age:>7d
+//! ```
+//!
+//! Bare words match the start of a word in the title, URL, domain or
+//! session name, the same way the viewer's full-text index does, so `ust`
+//! finds nothing with or without operators around it. Quoted phrases match
+//! the title, URL or domain as substrings. Field qualifiers restrict where
+//! the text must appear: `domain:` matches a domain or any of its
+//! subdomains, `title:`, `url:` and `session:` match substrings. `is:` tests a flag (`pinned`,
+//! `duplicate`, `muted`, `grouped`) and `age:` compares how long ago a tab
+//! was last used (open tabs) or saved (saved tabs) with a duration in
+//! minutes, hours, days or weeks. Any other `word:` (`localhost:3000`,
+//! `about:blank`) is plain text. `AND`, `OR` and `NOT` must be upper case;
+//! `OR` binds looser than `AND`, and `-` is short for `NOT`.
+
+use crate::domain::{display_domain, extract_domain, extract_host};
+use crate::search_index::tokens;
+use crate::tab_data::{CollapsedSession, SavedTab, TabInfo};
+use crate::url_normalizer::UrlNormalizer;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+const MINUTE_MS: f64 = 60_000.0;
+
+/// A field a term can be restricted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Domain,
+    Title,
+    Url,
+    Session,
+}
+
+/// A property tested with `is:`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Pinned,
+    Duplicate,
+    Muted,
+    Grouped,
+}
+
+/// `age:` comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    OlderThan,
+    AtLeast,
+    NewerThan,
+    AtMost,
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Every part matches; an empty list matches everything
+    All(Vec<Query>),
+    /// Any part matches
+    Any(Vec<Query>),
+    Not(Box<Query>),
+    /// A bare word (matching the start of a word in the title, URL, domain or
+    /// session name) or a quoted phrase (a substring of the title, URL or
+    /// domain)
+    Text(String),
+    Field(Field, String),
+    Is(Flag),
+    /// Age in milliseconds
+    Age(Comparison, f64),
+}
+
+/// Why a query could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Character position in the query, from 0
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+
+fn error<T>(message: impl Into<String>, position: usize) -> Result<T, ParseError> {
+    Err(ParseError {
+        message: message.into(),
+        position,
+    })
+}
+
+/// What a query is matched against: an open tab or a saved one
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub title: &'a str,
+    pub url: &'a str,
+    /// Registrable domain in ASCII form ("" when the URL has none)
+    pub domain: String,
+    pub pinned: bool,
+    pub muted: bool,
+    pub grouped: bool,
+    /// Whether another tab has the same normalized URL
+    pub duplicate: bool,
+    /// When the tab was last used (open tabs) or saved (saved tabs), in ms
+    /// since the epoch
+    pub time: Option<f64>,
+    /// Name of the session a saved tab belongs to
+    pub session: Option<&'a str>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn open_tab(tab: &'a TabInfo) -> Self {
+        Candidate {
+            title: &tab.title,
+            url: &tab.url,
+            domain: extract_domain(&tab.url).unwrap_or_default(),
+            pinned: tab.pinned,
+            muted: tab.muted,
+            grouped: tab.group_id.is_some(),
+            duplicate: false,
+            time: tab.last_accessed,
+            session: None,
+        }
+    }
+
+    pub fn saved_tab(tab: &'a SavedTab, session: &'a CollapsedSession) -> Self {
+        Candidate {
+            title: &tab.title,
+            url: &tab.url,
+            domain: tab.domain.clone(),
+            pinned: tab.pinned,
+            muted: tab.muted,
+            grouped: tab.group.is_some(),
+            duplicate: false,
+            time: tab.saved_at.or(Some(session.timestamp)),
+            session: Some(&session.name),
+        }
+    }
+
+    pub fn with_duplicate(self, duplicate: bool) -> Self {
+        Candidate { duplicate, ..self }
+    }
+}
+
+/// Normalized URLs that appear more than once among `urls`
+pub fn duplicate_urls<'a>(urls: impl IntoIterator<Item = &'a str>, normalizer: &UrlNormalizer) -> HashSet<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for url in urls {
+        *counts.entry(normalizer.normalize(url)).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(url, _)| url)
+        .collect()
+}
+
+/// How text terms are compared, and the time ages are measured from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchOptions {
+    pub case_sensitive: bool,
+    /// Current time in ms since the epoch
+    pub now: f64,
+}
+
+impl Query {
+    /// Parse a query; an empty or blank query matches everything
+    pub fn parse(input: &str) -> Result<Query, ParseError> {
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let query = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(Token { kind: TokenKind::Close, pos }) => error("Unmatched \")\"", *pos),
+            Some(token) => error("Unexpected input", token.pos),
+        }
+    }
+
+    /// Whether the query is only words to find, with no fields, flags or
+    /// operators (which lets the viewer use its full-text index)
+    pub fn plain_words(&self) -> Option<Vec<String>> {
+        match self {
+            Query::Text(word) if !word.contains(char::is_whitespace) => Some(vec![word.clone()]),
+            Query::All(parts) => parts.iter().map(|part| match part {
+                Query::Text(word) if !word.contains(char::is_whitespace) => Some(word.clone()),
+                _ => None,
+            })
+            .collect(),
+            _ => None,
+        }
+    }
+
+    /// Whether evaluating the query needs `Candidate::duplicate`
+    pub fn uses_duplicates(&self) -> bool {
+        match self {
+            Query::All(parts) | Query::Any(parts) => parts.iter().any(Query::uses_duplicates),
+            Query::Not(inner) => inner.uses_duplicates(),
+            Query::Is(flag) => *flag == Flag::Duplicate,
+            _ => false,
+        }
+    }
+
+    /// Text the query looks for in titles and URLs, for highlighting;
+    /// negated terms are left out
+    pub fn highlight_terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_highlights(&mut terms);
+        terms
+    }
+
+    fn collect_highlights(&self, terms: &mut Vec<String>) {
+        match self {
+            Query::All(parts) | Query::Any(parts) => {
+                for part in parts {
+                    part.collect_highlights(terms);
+                }
+            }
+            Query::Text(text) | Query::Field(Field::Title | Field::Url, text) => {
+                terms.extend(crate::search_index::query_terms(text));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn matches(&self, candidate: &Candidate, options: MatchOptions) -> bool {
+        match self {
+            Query::All(parts) => parts.iter().all(|part| part.matches(candidate, options)),
+            Query::Any(parts) => parts.iter().any(|part| part.matches(candidate, options)),
+            Query::Not(inner) => !inner.matches(candidate, options),
+            Query::Text(text) if text.contains(char::is_whitespace) => {
+                let found = |haystack: &str| contains(haystack, text, options.case_sensitive);
+                found(candidate.title)
+                    || found(candidate.url)
+                    || found(&candidate.domain)
+                    || (candidate.domain.contains("xn--") && found(&display_domain(&candidate.domain).unicode))
+            }
+            Query::Text(word) => word_matches(candidate, word, options.case_sensitive),
+            Query::Field(Field::Title, text) => contains(candidate.title, text, options.case_sensitive),
+            Query::Field(Field::Url, text) => contains(candidate.url, text, options.case_sensitive),
+            Query::Field(Field::Session, text) => candidate
+                .session
+                .is_some_and(|session| contains(session, text, options.case_sensitive)),
+            Query::Field(Field::Domain, domain) => domain_matches(candidate, domain),
+            Query::Is(Flag::Pinned) => candidate.pinned,
+            Query::Is(Flag::Duplicate) => candidate.duplicate,
+            Query::Is(Flag::Muted) => candidate.muted,
+            Query::Is(Flag::Grouped) => candidate.grouped,
+            Query::Age(comparison, age) => candidate.time.is_some_and(|time| {
+                let elapsed = options.now - time;
+                match comparison {
+                    Comparison::OlderThan => elapsed > *age,
+                    Comparison::AtLeast => elapsed >= *age,
+                    Comparison::NewerThan => elapsed < *age,
+                    Comparison::AtMost => elapsed <= *age,
+                }
+            }),
+        }
+    }
+}
+
+/// A bare word matches like the viewer's full-text index: each of its word
+/// tokens must start a word of the title, URL, domain or session name
+fn word_matches(candidate: &Candidate, word: &str, case_sensitive: bool) -> bool {
+    let unicode_domain = candidate
+        .domain
+        .contains("xn--")
+        .then(|| display_domain(&candidate.domain).unicode);
+    let fields = [
+        Some(candidate.title),
+        Some(candidate.url),
+        Some(candidate.domain.as_str()),
+        unicode_domain.as_deref(),
+        candidate.session,
+    ];
+    let words = |text: &str| -> Vec<String> {
+        tokens(text)
+            .map(|(range, lower)| if case_sensitive { text[range].to_string() } else { lower })
+            .collect()
+    };
+    let haystack: Vec<String> = fields.into_iter().flatten().flat_map(words).collect();
+    words(word)
+        .iter()
+        .all(|term| haystack.iter().any(|token| token.starts_with(term.as_str())))
+}
+
+fn contains(haystack: &str, needle: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        haystack.contains(needle)
+    } else {
+        haystack.to_lowercase().contains(&needle.to_lowercase())
+    }
+}
+
+/// `domain:github.com` matches github.com and its subdomains;
+/// `domain:github` also matches github.io
+fn domain_matches(candidate: &Candidate, wanted: &str) -> bool {
+    let wanted = wanted.trim_end_matches('.').to_lowercase();
+    let host = extract_host(candidate.url).unwrap_or_default().to_lowercase();
+    let unicode = display_domain(&candidate.domain).unicode;
+
+    let within = |name: &str| name == wanted || name.ends_with(&format!(".{}", wanted));
+    within(&host)
+        || within(&candidate.domain)
+        || within(&unicode)
+        || (!wanted.contains('.') && candidate.domain.split('.').next() == Some(wanted.as_str()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// `field:value`, or a bare value when `field` is `None`
+    Term { field: Option<String>, value: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    /// Character position in the query
+    pos: usize,
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        match c {
+            '(' => {
+                tokens.push(Token { kind: TokenKind::Open, pos: i });
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token { kind: TokenKind::Close, pos: i });
+                i += 1;
+            }
+            '-' if chars.get(i + 1).is_some_and(|next| !next.is_whitespace()) => {
+                tokens.push(Token { kind: TokenKind::Not, pos: i });
+                i += 1;
+            }
+            '"' => {
+                let (value, next) = read_quoted(&chars, i)?;
+                tokens.push(Token {
+                    kind: TokenKind::Term { field: None, value },
+                    pos: start,
+                });
+                i = next;
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let kind = match word.as_str() {
+                    "AND" => TokenKind::And,
+                    "OR" => TokenKind::Or,
+                    "NOT" => TokenKind::Not,
+                    _ => match word.split_once(':') {
+                        // field:"quoted value"
+                        Some((field, "")) if chars.get(i) == Some(&'"') && is_field_name(field) => {
+                            let (value, next) = read_quoted(&chars, i)?;
+                            i = next;
+                            TokenKind::Term {
+                                field: Some(field.to_string()),
+                                value,
+                            }
+                        }
+                        // Anything else before a colon ("localhost:3000",
+                        // "about:blank", "Re:") is text, not a field
+                        Some((field, value)) if is_field_name(field) => TokenKind::Term {
+                            field: Some(field.to_string()),
+                            value: value.to_string(),
+                        },
+                        _ => TokenKind::Term { field: None, value: word },
+                    },
+                };
+                tokens.push(Token { kind, pos: start });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Names that qualify a term when followed by a colon
+const FIELD_NAMES: [&str; 6] = ["domain", "title", "url", "session", "is", "age"];
+
+fn is_field_name(name: &str) -> bool {
+    FIELD_NAMES.iter().any(|field| field.eq_ignore_ascii_case(name))
+}
+
+/// Read a quoted string starting at `chars[start] == '"'`; returns it and
+/// the position after the closing quote
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    match chars[start + 1..].iter().position(|&c| c == '"') {
+        Some(len) => Ok((chars[start + 1..start + 1 + len].iter().collect(), start + len + 2)),
+        None => error("Missing closing quote", start),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the query, for errors at its end
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_pos(&self) -> usize {
+        self.peek().map_or(self.end, |token| token.pos)
+    }
+
+    /// or := and ("OR" and)*
+    fn parse_or(&mut self) -> Result<Query, ParseError> {
+        if let Some(Token { kind: TokenKind::Or, pos }) = self.peek() {
+            return error("Expected a search term before OR", *pos);
+        }
+        let mut parts = vec![self.parse_and()?];
+        while let Some(Token { kind: TokenKind::Or, .. }) = self.peek() {
+            self.pos += 1;
+            if self.at_term_end() {
+                return error("Expected a search term after OR", self.next_pos());
+            }
+            parts.push(self.parse_and()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { Query::Any(parts) })
+    }
+
+    /// and := unary (("AND")? unary)*
+    fn parse_and(&mut self) -> Result<Query, ParseError> {
+        let mut parts = Vec::new();
+        loop {
+            match self.peek().map(|token| &token.kind) {
+                None | Some(TokenKind::Or) | Some(TokenKind::Close) => break,
+                Some(TokenKind::And) => {
+                    let pos = self.next_pos();
+                    if parts.is_empty() {
+                        return error("Expected a search term before AND", pos);
+                    }
+                    self.pos += 1;
+                    if self.at_term_end() {
+                        return error("Expected a search term after AND", self.next_pos());
+                    }
+                }
+                _ => parts.push(self.parse_unary()?),
+            }
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { Query::All(parts) })
+    }
+
+    fn at_term_end(&self) -> bool {
+        matches!(
+            self.peek().map(|token| &token.kind),
+            None | Some(TokenKind::Or) | Some(TokenKind::And) | Some(TokenKind::Close)
+        )
+    }
+
+    /// unary := ("NOT" | "-") unary | "(" or ")" | term
+    fn parse_unary(&mut self) -> Result<Query, ParseError> {
+        let Some(token) = self.peek().cloned() else {
+            return error("Expected a search term", self.end);
+        };
+        self.pos += 1;
+
+        match token.kind {
+            TokenKind::Not => {
+                if self.at_term_end() {
+                    return error("Expected a search term after NOT", self.next_pos());
+                }
+                Ok(Query::Not(Box::new(self.parse_unary()?)))
+            }
+            TokenKind::Open => {
+                if let Some(Token { kind: TokenKind::Close, pos }) = self.peek() {
+                    return error("Empty parentheses", *pos);
+                }
+                let inner = self.parse_or()?;
+                match self.peek() {
+                    Some(Token { kind: TokenKind::Close, .. }) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    _ => error("Missing closing \")\"", token.pos),
+                }
+            }
+            TokenKind::Term { field: None, value } => Ok(Query::Text(value)),
+            TokenKind::Term { field: Some(field), value } => parse_field(&field, &value, token.pos),
+            TokenKind::Close | TokenKind::And | TokenKind::Or => error("Expected a search term", token.pos),
+        }
+    }
+}
+
+fn parse_field(field: &str, value: &str, pos: usize) -> Result<Query, ParseError> {
+    let field_lower = field.to_lowercase();
+    if value.is_empty() {
+        let example = match field_lower.as_str() {
+            "is" => "is:pinned",
+            "age" => "age:>7d",
+            "domain" => "domain:github.com",
+            _ => "title:\"design doc\"",
+        };
+        return error(format!("{}: needs a value, e.g. {}", field, example), pos);
+    }
+
+    let text_field = match field_lower.as_str() {
+        "domain" => Some(Field::Domain),
+        "title" => Some(Field::Title),
+        "url" => Some(Field::Url),
+        "session" => Some(Field::Session),
+        _ => None,
+    };
+    if let Some(text_field) = text_field {
+        return Ok(Query::Field(text_field, value.to_string()));
+    }
+
+    match field_lower.as_str() {
+        "is" => match value.to_lowercase().as_str() {
+            "pinned" => Ok(Query::Is(Flag::Pinned)),
+            "duplicate" | "dup" => Ok(Query::Is(Flag::Duplicate)),
+            "muted" => Ok(Query::Is(Flag::Muted)),
+            "grouped" => Ok(Query::Is(Flag::Grouped)),
+            _ => error(
+                format!("Unknown is:{}; use is:pinned, is:duplicate, is:muted or is:grouped", value),
+                pos,
+            ),
+        },
+        "age" => parse_age(value)
+            .ok_or(())
+            .or_else(|_| error("age: takes a comparison and a duration, e.g. age:>7d or age:<12h", pos)),
+        _ => unreachable!("lex only makes field terms from FIELD_NAMES"),
+    }
+}
+
+/// `>7d` → (OlderThan, 7 days in ms)
+fn parse_age(value: &str) -> Option<Query> {
+    let (comparison, rest) = [
+        (">=", Comparison::AtLeast),
+        ("<=", Comparison::AtMost),
+        (">", Comparison::OlderThan),
+        ("<", Comparison::NewerThan),
+    ]
+    .iter()
+    .find_map(|(prefix, comparison)| value.strip_prefix(prefix).map(|rest| (*comparison, rest)))?;
+
+    let unit_start = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let amount: f64 = rest[..unit_start].parse().ok()?;
+    let unit = match &rest[unit_start..] {
+        "m" | "min" => MINUTE_MS,
+        "h" => 60.0 * MINUTE_MS,
+        "d" => 24.0 * 60.0 * MINUTE_MS,
+        "w" => 7.0 * 24.0 * 60.0 * MINUTE_MS,
+        _ => return None,
+    };
+    Some(Query::Age(comparison, amount * unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1709370900000.0;
+    const DAY: f64 = 24.0 * 60.0 * MINUTE_MS;
+
+    fn options() -> MatchOptions {
+        MatchOptions {
+            case_sensitive: false,
+            now: NOW,
+        }
+    }
+
+    fn create_test_tabs() -> Vec<TabInfo> {
+        let mut tabs = vec![
+            TabInfo::new(1, "https://github.com/rust-lang/rust/issues/1".to_string(), "Tracking issue".to_string(), true, 0),
+            TabInfo::new(2, "https://gist.github.com/someone".to_string(), "Design doc draft".to_string(), false, 1),
+            TabInfo::new(3, "https://docs.rs/serde".to_string(), "serde - Rust".to_string(), false, 2),
+            TabInfo::new(4, "https://github.io/pages".to_string(), "Pages".to_string(), false, 3),
+        ];
+        tabs[0].last_accessed = Some(NOW - 10.0 * DAY);
+        tabs[1].last_accessed = Some(NOW - 60.0 * MINUTE_MS);
+        tabs[2].muted = true;
+        tabs
+    }
+
+    fn matching(query: &str) -> Vec<i32> {
+        let query = Query::parse(query).unwrap();
+        create_test_tabs()
+            .iter()
+            .filter(|tab| query.matches(&Candidate::open_tab(tab), options()))
+            .map(|tab| tab.id)
+            .collect()
+    }
+
+    fn parse_error(query: &str) -> String {
+        Query::parse(query).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let query = Query::parse("a b OR NOT c").unwrap();
+
+        assert_eq!(
+            query,
+            Query::Any(vec![
+                Query::All(vec![Query::Text("a".to_string()), Query::Text("b".to_string())]),
+                Query::Not(Box::new(Query::Text("c".to_string()))),
+            ])
+        );
+        assert_eq!(Query::parse("  ").unwrap(), Query::All(Vec::new()));
+    }
+
+    #[test]
+    fn test_parse_fields_and_quotes() {
+        assert_eq!(
+            Query::parse("title:\"design doc\" -session:\"Sprint 12\"").unwrap(),
+            Query::All(vec![
+                Query::Field(Field::Title, "design doc".to_string()),
+                Query::Not(Box::new(Query::Field(Field::Session, "Sprint 12".to_string()))),
+            ])
+        );
+        assert_eq!(Query::parse("url:/issues/").unwrap(), Query::Field(Field::Url, "/issues/".to_string()));
+        assert_eq!(Query::parse("age:>7d").unwrap(), Query::Age(Comparison::OlderThan, 7.0 * DAY));
+        // A bare URL is text, not a field
+        assert_eq!(
+            Query::parse("https://docs.rs").unwrap(),
+            Query::Text("https://docs.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_text_and_fields() {
+        assert_eq!(matching("rust"), vec![1, 3]);
+        assert_eq!(matching("RUST issue"), vec![1]);
+        assert_eq!(matching("title:\"design doc\""), vec![2]);
+        assert_eq!(matching("url:/issues/"), vec![1]);
+        assert_eq!(matching("docs OR pages"), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_words_match_word_starts() {
+        // As in the viewer's index, a word must start a word of the tab
+        assert_eq!(matching("ust"), Vec::<i32>::new());
+        assert_eq!(matching("ust OR pages"), vec![4]);
+        assert_eq!(matching("rust-lang iss"), vec![1]);
+        // Quoted phrases are still substrings
+        assert_eq!(matching("\"ign doc\""), vec![2]);
+    }
+
+    #[test]
+    fn test_unknown_prefixes_are_text() {
+        for text in ["localhost:3000", "about:blank", "mailto:x", "tilte:rust"] {
+            assert_eq!(Query::parse(text).unwrap(), Query::Text(text.to_string()));
+        }
+        assert_eq!(
+            Query::parse("Re: meeting").unwrap(),
+            Query::All(vec![Query::Text("Re:".to_string()), Query::Text("meeting".to_string())])
+        );
+        assert_eq!(Query::parse("URL:/issues/").unwrap(), Query::Field(Field::Url, "/issues/".to_string()));
+
+        let tabs = [
+            TabInfo::new(1, "http://localhost:3000/".to_string(), "Dev server".to_string(), false, 0),
+            TabInfo::new(2, "about:blank".to_string(), "".to_string(), false, 1),
+        ];
+        let found = |query: &str| -> Vec<i32> {
+            let query = Query::parse(query).unwrap();
+            tabs.iter()
+                .filter(|tab| query.matches(&Candidate::open_tab(tab), options()))
+                .map(|tab| tab.id)
+                .collect()
+        };
+        assert_eq!(found("localhost:3000"), vec![1]);
+        assert_eq!(found("about:blank"), vec![2]);
+    }
+
+    #[test]
+    fn test_domain_matches_subdomains() {
+        assert_eq!(matching("domain:github.com"), vec![1, 2]);
+        assert_eq!(matching("domain:github"), vec![1, 2, 4]);
+        assert_eq!(matching("domain:hub.com"), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_flags_and_age() {
+        assert_eq!(matching("is:pinned"), vec![1]);
+        assert_eq!(matching("NOT is:pinned is:muted"), vec![3]);
+        assert_eq!(matching("age:>7d"), vec![1]);
+        // Tabs without a last-used time have no age
+        assert_eq!(matching("age:<2h"), vec![2]);
+        assert_eq!(matching("(domain:github.com OR is:muted) -age:>1d"), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_duplicates_and_sessions() {
+        let session = CollapsedSession {
+            id: "s1".to_string(),
+            name: "Sprint 12".to_string(),
+            timestamp: NOW - 3.0 * DAY,
+            tabs: vec![
+                SavedTab {
+                    url: "https://docs.rs/serde/".to_string(),
+                    title: "serde".to_string(),
+                    domain: "docs.rs".to_string(),
+                    ..SavedTab::default()
+                },
+                SavedTab {
+                    url: "https://docs.rs/serde".to_string(),
+                    title: "serde".to_string(),
+                    domain: "docs.rs".to_string(),
+                    ..SavedTab::default()
+                },
+                SavedTab {
+                    url: "https://docs.rs/tokio".to_string(),
+                    title: "tokio".to_string(),
+                    domain: "docs.rs".to_string(),
+                    ..SavedTab::default()
+                },
+            ],
+        };
+        let duplicates = duplicate_urls(session.tabs.iter().map(|tab| tab.url.as_str()), &UrlNormalizer::default());
+        let query = Query::parse("session:\"sprint 12\" is:duplicate age:>2d").unwrap();
+        assert!(query.uses_duplicates());
+
+        let found: Vec<&str> = session
+            .tabs
+            .iter()
+            .filter(|tab| {
+                let duplicate = duplicates.contains(&UrlNormalizer::default().normalize(&tab.url));
+                query.matches(&Candidate::saved_tab(tab, &session).with_duplicate(duplicate), options())
+            })
+            .map(|tab| tab.url.as_str())
+            .collect();
+
+        assert_eq!(found, vec!["https://docs.rs/serde/", "https://docs.rs/serde"]);
+    }
+
+    #[test]
+    fn test_case_sensitive_text() {
+        let tabs = create_test_tabs();
+        let query = Query::parse("Rust").unwrap();
+        let case_sensitive = MatchOptions {
+            case_sensitive: true,
+            ..options()
+        };
+
+        let found: Vec<i32> = tabs
+            .iter()
+            .filter(|tab| query.matches(&Candidate::open_tab(tab), case_sensitive))
+            .map(|tab| tab.id)
+            .collect();
+
+        assert_eq!(found, vec![3]);
+    }
+
+    #[test]
+    fn test_plain_words_and_highlights() {
+        assert_eq!(
+            Query::parse("rust serde").unwrap().plain_words(),
+            Some(vec!["rust".to_string(), "serde".to_string()])
+        );
+        assert_eq!(Query::parse("rust is:pinned").unwrap().plain_words(), None);
+        assert_eq!(Query::parse("\"design doc\"").unwrap().plain_words(), None);
+        assert_eq!(
+            Query::parse("title:Design -draft OR url:/issues/").unwrap().highlight_terms(),
+            vec!["design".to_string(), "issues".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_error("title:\"design doc"), "Missing closing quote (at character 7)");
+        assert_eq!(parse_error("(rust OR go"), "Missing closing \")\" (at character 1)");
+        assert_eq!(parse_error("rust)"), "Unmatched \")\" (at character 5)");
+        assert_eq!(parse_error("rust OR"), "Expected a search term after OR (at character 8)");
+        assert_eq!(parse_error("OR rust"), "Expected a search term before OR (at character 1)");
+        assert_eq!(parse_error("rust AND OR go"), "Expected a search term after AND (at character 10)");
+        assert_eq!(parse_error("NOT"), "Expected a search term after NOT (at character 4)");
+        assert_eq!(parse_error("()"), "Empty parentheses (at character 2)");
+        assert_eq!(
+            parse_error("is:open"),
+            "Unknown is:open; use is:pinned, is:duplicate, is:muted or is:grouped (at character 1)"
+        );
+        assert_eq!(
+            parse_error("age:7d"),
+            "age: takes a comparison and a duration, e.g. age:>7d or age:<12h (at character 1)"
+        );
+        assert_eq!(parse_error("domain:"), "domain: needs a value, e.g. domain:github.com (at character 1)");
+    }
+}
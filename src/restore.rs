@@ -0,0 +1,334 @@
+//! Restoring saved tabs
+//!
+//! `plan_restore` turns saved tabs and the restore preferences into a
+//! `RestorePlan`: the windows to fill, the tabs of each in their original
+//! order, and the groups to recreate. `TabBackend::restore_tabs` carries it
+//! out (`restoreTabs` in tabs.js for the real browser).
+
+use crate::tab_data::{SavedTab, TabGroupInfo};
+use serde::{Deserialize, Serialize};
+
+/// Where restored tabs open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RestoreTarget {
+    /// At the end of the window the viewer is in
+    #[default]
+    CurrentWindow,
+    /// All together in one new window
+    NewWindow,
+    /// One new window per window the tabs were saved from
+    OriginalWindows,
+}
+
+impl RestoreTarget {
+    /// Targets offered in the viewer
+    pub const CHOICES: [RestoreTarget; 3] = [
+        RestoreTarget::CurrentWindow,
+        RestoreTarget::NewWindow,
+        RestoreTarget::OriginalWindows,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RestoreTarget::CurrentWindow => "Restore into this window",
+            RestoreTarget::NewWindow => "Restore into a new window",
+            RestoreTarget::OriginalWindows => "Restore original windows",
+        }
+    }
+}
+
+/// Restore preferences, stored as `restore_options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreOptions {
+    pub target: RestoreTarget,
+    /// Put tabs back in tab groups with their saved titles and colors
+    pub recreate_groups: bool,
+    /// Open tabs discarded, so they only load when first focused
+    pub lazy: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            target: RestoreTarget::CurrentWindow,
+            recreate_groups: true,
+            lazy: false,
+        }
+    }
+}
+
+/// A tab to open
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestoreTab {
+    pub url: String,
+    pub pinned: bool,
+    pub muted: bool,
+    /// Index into the window's `groups`
+    pub group: Option<usize>,
+}
+
+/// Tabs to open in one window, left to right
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestoreWindow {
+    /// Open a new window for these tabs instead of using the current one
+    pub new_window: bool,
+    pub tabs: Vec<RestoreTab>,
+    pub groups: Vec<TabGroupInfo>,
+}
+
+/// Everything a restore opens, as consumed by `restoreTabs` in tabs.js
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestorePlan {
+    pub windows: Vec<RestoreWindow>,
+    /// Discard every tab that is not active right after opening it
+    pub discard: bool,
+}
+
+impl RestorePlan {
+    pub fn tab_count(&self) -> usize {
+        self.windows.iter().map(|window| window.tabs.len()).sum()
+    }
+}
+
+/// Saved tabs in the order they had in their windows
+///
+/// Tabs saved before positions were recorded keep their stored order.
+pub fn restore_order(tabs: &[SavedTab]) -> Vec<&SavedTab> {
+    let mut ordered: Vec<&SavedTab> = tabs.iter().collect();
+    ordered.sort_by_key(|tab| (tab.window_id, tab.index));
+    ordered
+}
+
+/// Plan how to reopen `tabs`
+///
+/// Groups are recognized by position: saved tabs of one window that are
+/// next to each other and have the same group details were in the same
+/// group, since Chrome keeps a group's tabs together. Pinned tabs are never
+/// grouped.
+pub fn plan_restore(tabs: &[SavedTab], options: RestoreOptions) -> RestorePlan {
+    let ordered = restore_order(tabs);
+
+    let mut windows: Vec<Vec<&SavedTab>> = Vec::new();
+    for tab in ordered {
+        match windows.last_mut() {
+            Some(window)
+                if options.target != RestoreTarget::OriginalWindows
+                    || window[0].window_id == tab.window_id =>
+            {
+                window.push(tab)
+            }
+            _ => windows.push(vec![tab]),
+        }
+    }
+
+    RestorePlan {
+        windows: windows
+            .into_iter()
+            .map(|tabs| plan_window(&tabs, options))
+            .collect(),
+        discard: options.lazy,
+    }
+}
+
+fn plan_window(tabs: &[&SavedTab], options: RestoreOptions) -> RestoreWindow {
+    let mut groups: Vec<TabGroupInfo> = Vec::new();
+    let mut planned: Vec<RestoreTab> = Vec::with_capacity(tabs.len());
+
+    for (i, tab) in tabs.iter().enumerate() {
+        let group = match &tab.group {
+            Some(info) if options.recreate_groups && !tab.pinned => {
+                let previous = i.checked_sub(1).map(|p| (tabs[p], planned[p].group));
+                match previous {
+                    Some((prev, Some(index)))
+                        if prev.window_id == tab.window_id && prev.group.as_ref() == Some(info) =>
+                    {
+                        Some(index)
+                    }
+                    _ => {
+                        groups.push(info.clone());
+                        Some(groups.len() - 1)
+                    }
+                }
+            }
+            _ => None,
+        };
+        planned.push(RestoreTab {
+            url: tab.url.clone(),
+            pinned: tab.pinned,
+            muted: tab.muted,
+            group,
+        });
+    }
+
+    RestoreWindow {
+        new_window: options.target != RestoreTarget::CurrentWindow,
+        tabs: planned,
+        groups,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tab_data::TabInfo;
+    use crate::tabs::{MemoryBrowser, TabBackend};
+    use crate::test_support::block_on;
+
+    fn create_saved_tab(url: &str, window_id: i32, index: i32) -> SavedTab {
+        SavedTab {
+            url: url.to_string(),
+            title: url.to_string(),
+            domain: "example.com".to_string(),
+            window_id: Some(window_id),
+            index: Some(index),
+            ..SavedTab::default()
+        }
+    }
+
+    fn group(title: &str, color: &str) -> Option<TabGroupInfo> {
+        Some(TabGroupInfo {
+            title: title.to_string(),
+            color: color.to_string(),
+            collapsed: false,
+        })
+    }
+
+    /// Two windows, saved in domain order rather than window order:
+    /// window 1 is a | b, c (group "Docs") | d; window 2 is e, f (group "Docs")
+    fn create_saved_tabs() -> Vec<SavedTab> {
+        vec![
+            SavedTab { group: group("Docs", "blue"), ..create_saved_tab("f", 2, 1) },
+            create_saved_tab("d", 1, 3),
+            SavedTab { group: group("Docs", "blue"), ..create_saved_tab("c", 1, 2) },
+            SavedTab { pinned: true, ..create_saved_tab("a", 1, 0) },
+            SavedTab { group: group("Docs", "blue"), ..create_saved_tab("e", 2, 0) },
+            SavedTab { group: group("Docs", "blue"), muted: true, ..create_saved_tab("b", 1, 1) },
+        ]
+    }
+
+    fn urls(window: &RestoreWindow) -> Vec<&str> {
+        window.tabs.iter().map(|tab| tab.url.as_str()).collect()
+    }
+
+    fn groups_of(window: &RestoreWindow) -> Vec<Option<usize>> {
+        window.tabs.iter().map(|tab| tab.group).collect()
+    }
+
+    #[test]
+    fn test_current_window_keeps_original_order() {
+        let plan = plan_restore(&create_saved_tabs(), RestoreOptions::default());
+
+        assert_eq!(plan.windows.len(), 1);
+        let window = &plan.windows[0];
+        assert!(!window.new_window);
+        assert_eq!(urls(window), vec!["a", "b", "c", "d", "e", "f"]);
+        assert!(window.tabs[0].pinned);
+        assert!(window.tabs[1].muted);
+        // Same details, but from different windows: two groups
+        assert_eq!(groups_of(window), vec![None, Some(0), Some(0), None, Some(1), Some(1)]);
+        assert_eq!(window.groups.len(), 2);
+        assert_eq!(window.groups[0].title, "Docs");
+    }
+
+    #[test]
+    fn test_original_windows_are_split() {
+        let options = RestoreOptions {
+            target: RestoreTarget::OriginalWindows,
+            ..RestoreOptions::default()
+        };
+
+        let plan = plan_restore(&create_saved_tabs(), options);
+
+        assert_eq!(plan.windows.len(), 2);
+        assert!(plan.windows.iter().all(|window| window.new_window));
+        assert_eq!(urls(&plan.windows[0]), vec!["a", "b", "c", "d"]);
+        assert_eq!(urls(&plan.windows[1]), vec!["e", "f"]);
+        assert_eq!(groups_of(&plan.windows[1]), vec![Some(0), Some(0)]);
+        assert_eq!(plan.tab_count(), 6);
+    }
+
+    #[test]
+    fn test_new_window_without_groups() {
+        let options = RestoreOptions {
+            target: RestoreTarget::NewWindow,
+            recreate_groups: false,
+            lazy: true,
+        };
+
+        let plan = plan_restore(&create_saved_tabs(), options);
+
+        assert_eq!(plan.windows.len(), 1);
+        assert!(plan.windows[0].new_window);
+        assert!(plan.windows[0].groups.is_empty());
+        assert!(groups_of(&plan.windows[0]).iter().all(Option::is_none));
+        assert!(plan.discard);
+    }
+
+    #[test]
+    fn test_adjacent_groups_with_different_details() {
+        let tabs = vec![
+            SavedTab { group: group("Docs", "blue"), ..create_saved_tab("a", 1, 0) },
+            SavedTab { group: group("Issues", "red"), ..create_saved_tab("b", 1, 1) },
+            SavedTab { group: group("Docs", "blue"), ..create_saved_tab("c", 1, 2) },
+        ];
+
+        let plan = plan_restore(&tabs, RestoreOptions::default());
+
+        assert_eq!(groups_of(&plan.windows[0]), vec![Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_restore_original_windows_lazily() {
+        let browser = MemoryBrowser::new();
+        browser.open("chrome-extension://viewer/collapsed.html", "Collapsed Tabs");
+        let options = RestoreOptions {
+            target: RestoreTarget::OriginalWindows,
+            recreate_groups: true,
+            lazy: true,
+        };
+
+        block_on(browser.restore_tabs(&plan_restore(&create_saved_tabs(), options), |_| {})).unwrap();
+
+        assert_eq!(browser.window_ids(), vec![1, 2, 3]);
+        assert_eq!(browser.window_tabs(1).len(), 1);
+        let first = browser.window_tabs(2);
+        let second = browser.window_tabs(3);
+        let urls_of = |tabs: &[TabInfo]| tabs.iter().map(|tab| tab.url.clone()).collect::<Vec<_>>();
+        assert_eq!(urls_of(&first), vec!["a", "b", "c", "d"]);
+        assert_eq!(urls_of(&second), vec!["e", "f"]);
+
+        // Only each window's first tab loads
+        assert!(first[0].active && !first[0].discarded);
+        assert!(first[1..].iter().all(|tab| tab.discarded));
+        assert!(first[0].pinned);
+        assert!(first[1].muted);
+
+        // Groups come back per window, with their details
+        assert!(first[1].group_id.is_some() && first[1].group_id == first[2].group_id);
+        assert_eq!(first[1].group, group("Docs", "blue"));
+        assert_eq!(first[3].group_id, None);
+        assert!(second[0].group_id.is_some() && second[0].group_id == second[1].group_id);
+        assert_ne!(first[1].group_id, second[0].group_id);
+    }
+
+    #[test]
+    fn test_tabs_without_positions_keep_stored_order() {
+        let tabs: Vec<SavedTab> = ["z", "y", "x"]
+            .iter()
+            .map(|url| SavedTab {
+                url: url.to_string(),
+                ..SavedTab::default()
+            })
+            .collect();
+        let options = RestoreOptions {
+            target: RestoreTarget::OriginalWindows,
+            ..RestoreOptions::default()
+        };
+
+        let plan = plan_restore(&tabs, options);
+
+        assert_eq!(plan.windows.len(), 1);
+        assert_eq!(urls(&plan.windows[0]), vec!["z", "y", "x"]);
+    }
+}
@@ -0,0 +1,511 @@
+//! Full-text index over collapsed sessions
+//!
+//! Tab titles, URLs, domains and session names are split into lowercase
+//! word tokens and kept in an inverted index: each token maps to the tabs
+//! containing it and the fields it appears in. Query words match tokens by
+//! prefix, so results update as a word is typed, and every query word must
+//! match somewhere in a tab for the tab to be a hit.
+//!
+//! The index is kept in memory and brought up to date with `sync`, which
+//! only re-indexes sessions that were added or changed.
+
+use crate::domain::display_domain;
+use crate::storage::StorageData;
+use crate::tab_data::{CollapsedSession, SavedTab};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+/// URL tokens too common to be worth indexing
+const URL_NOISE: [&str; 3] = ["http", "https", "www"];
+
+/// Score multiplier for a query word that is only a prefix of the token
+const PREFIX_MATCH: f64 = 0.6;
+
+/// Part of a saved tab a token came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Url,
+    Domain,
+    Session,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Title, Field::Url, Field::Domain, Field::Session];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// How much a match in this field counts towards a hit's score
+    fn weight(self) -> f64 {
+        match self {
+            Field::Title => 3.0,
+            Field::Domain => 2.0,
+            Field::Session => 1.5,
+            Field::Url => 1.0,
+        }
+    }
+}
+
+/// A matching tab, identified by its session and position in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub tab_index: usize,
+    pub score: f64,
+}
+
+/// The hits of one session, best first
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionHits {
+    pub session_id: String,
+    /// Score of the session's best hit
+    pub best: f64,
+    /// Positions of the matching tabs in the session, best first
+    pub tabs: Vec<usize>,
+}
+
+/// Group ranked hits by session, keeping the rank order of both
+pub fn group_by_session(hits: &[SearchHit]) -> Vec<SessionHits> {
+    let mut grouped: Vec<SessionHits> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+
+    for hit in hits {
+        match positions.get(hit.session_id.as_str()) {
+            Some(&pos) => grouped[pos].tabs.push(hit.tab_index),
+            None => {
+                positions.insert(&hit.session_id, grouped.len());
+                grouped.push(SessionHits {
+                    session_id: hit.session_id.clone(),
+                    best: hit.score,
+                    tabs: vec![hit.tab_index],
+                });
+            }
+        }
+    }
+
+    grouped
+}
+
+/// Lowercase alphanumeric words of `text` with their byte ranges in it
+pub(crate) fn tokens(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| match (c.is_alphanumeric(), start) {
+            (true, None) => {
+                start = Some(i);
+                None
+            }
+            (false, Some(s)) => {
+                start = None;
+                Some((s..i, text[s..i].to_lowercase()))
+            }
+            _ => None,
+        })
+}
+
+/// The words of a search query, as matched against the index
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = tokens(query).map(|(_, token)| token).collect();
+    terms.dedup();
+    terms
+}
+
+/// Byte ranges of `text` to highlight for `terms`: the start of every word
+/// that a term is a prefix of
+pub fn highlight_ranges(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    tokens(text)
+        .filter_map(|(range, token)| {
+            let longest = terms
+                .iter()
+                .filter(|term| token.starts_with(term.as_str()))
+                .map(|term| term.chars().count())
+                .max()?;
+            let word = &text[range.clone()];
+            let end = word
+                .char_indices()
+                .nth(longest)
+                .map_or(range.end, |(offset, _)| range.start + offset);
+            Some(range.start..end)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Doc {
+    session_id: String,
+    tab_index: usize,
+    terms: Vec<String>,
+}
+
+#[derive(Debug)]
+struct IndexedSession {
+    fingerprint: (String, f64, usize),
+    docs: Vec<u32>,
+    /// Position in the stored sessions, as the viewer lists them; breaks
+    /// score ties
+    order: usize,
+}
+
+/// Inverted index of saved tabs
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    docs: HashMap<u32, Doc>,
+    next_doc: u32,
+    /// Token → (doc → fields the token appears in)
+    terms: BTreeMap<String, BTreeMap<u32, u8>>,
+    sessions: HashMap<String, IndexedSession>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index every session in `data`
+    pub fn build(data: &StorageData) -> Self {
+        let mut index = Self::new();
+        index.sync(data);
+        index
+    }
+
+    /// Number of tabs indexed
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Bring the index up to date with `data`; returns how many sessions
+    /// were (re-)indexed
+    ///
+    /// Saved tabs are never edited in place, only removed, so a session
+    /// whose name, timestamp and tab count are unchanged is skipped.
+    pub fn sync(&mut self, data: &StorageData) -> usize {
+        let mut reindexed = 0;
+
+        let current: HashMap<&str, usize> = data
+            .sessions
+            .iter()
+            .enumerate()
+            .map(|(order, session)| (session.id.as_str(), order))
+            .collect();
+        let removed: Vec<String> = self
+            .sessions
+            .keys()
+            .filter(|id| !current.contains_key(id.as_str()))
+            .cloned()
+            .collect();
+        for id in removed {
+            self.remove_session(&id);
+        }
+
+        for (order, session) in data.sessions.iter().enumerate() {
+            let fingerprint = (session.name.clone(), session.timestamp, session.tabs.len());
+            match self.sessions.get_mut(&session.id) {
+                Some(indexed) if indexed.fingerprint == fingerprint => indexed.order = order,
+                _ => {
+                    self.remove_session(&session.id);
+                    self.add_session(session, fingerprint, order);
+                    reindexed += 1;
+                }
+            }
+        }
+
+        reindexed
+    }
+
+    fn add_session(&mut self, session: &CollapsedSession, fingerprint: (String, f64, usize), order: usize) {
+        let docs = session
+            .tabs
+            .iter()
+            .enumerate()
+            .map(|(tab_index, tab)| self.add_tab(&session.id, &session.name, tab_index, tab))
+            .collect();
+        self.sessions.insert(
+            session.id.clone(),
+            IndexedSession {
+                fingerprint,
+                docs,
+                order,
+            },
+        );
+    }
+
+    fn add_tab(&mut self, session_id: &str, session_name: &str, tab_index: usize, tab: &SavedTab) -> u32 {
+        let doc = self.next_doc;
+        self.next_doc += 1;
+
+        let mut fields: HashMap<String, u8> = HashMap::new();
+        let mut add = |text: &str, field: Field| {
+            for (_, token) in tokens(text) {
+                if field == Field::Url && URL_NOISE.contains(&token.as_str()) {
+                    continue;
+                }
+                *fields.entry(token).or_default() |= field.bit();
+            }
+        };
+        add(&tab.title, Field::Title);
+        add(&tab.url, Field::Url);
+        add(&tab.domain, Field::Domain);
+        if tab.domain.contains("xn--") {
+            add(&display_domain(&tab.domain).unicode, Field::Domain);
+        }
+        add(session_name, Field::Session);
+
+        for (token, mask) in &fields {
+            self.terms.entry(token.clone()).or_default().insert(doc, *mask);
+        }
+        self.docs.insert(
+            doc,
+            Doc {
+                session_id: session_id.to_string(),
+                tab_index,
+                terms: fields.into_keys().collect(),
+            },
+        );
+        doc
+    }
+
+    fn remove_session(&mut self, session_id: &str) {
+        let Some(indexed) = self.sessions.remove(session_id) else {
+            return;
+        };
+        for doc_id in indexed.docs {
+            let Some(doc) = self.docs.remove(&doc_id) else {
+                continue;
+            };
+            for token in doc.terms {
+                if let Some(postings) = self.terms.get_mut(&token) {
+                    postings.remove(&doc_id);
+                    if postings.is_empty() {
+                        self.terms.remove(&token);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Best score of each tab matching one query word
+    fn term_scores(&self, term: &str) -> HashMap<u32, f64> {
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        let matches = self
+            .terms
+            .range(term.to_string()..)
+            .take_while(|(token, _)| token.starts_with(term));
+
+        for (token, postings) in matches {
+            let factor = if token == term { 1.0 } else { PREFIX_MATCH };
+            for (&doc, &mask) in postings {
+                let weight: f64 = Field::ALL
+                    .iter()
+                    .filter(|field| mask & field.bit() != 0)
+                    .map(|field| field.weight())
+                    .sum();
+                let score = scores.entry(doc).or_default();
+                *score = score.max(weight * factor);
+            }
+        }
+
+        scores
+    }
+
+    /// Tabs matching every word of `query`, best first
+    ///
+    /// Ties keep the stored order of sessions, then of tabs.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms = query_terms(query);
+        let Some((first, rest)) = terms.split_first() else {
+            return Vec::new();
+        };
+
+        let mut scores = self.term_scores(first);
+        for term in rest {
+            if scores.is_empty() {
+                break;
+            }
+            let term_scores = self.term_scores(term);
+            scores.retain(|doc, score| match term_scores.get(doc) {
+                Some(term_score) => {
+                    *score += term_score;
+                    true
+                }
+                None => false,
+            });
+        }
+
+        let mut hits: Vec<(usize, SearchHit)> = scores
+            .into_iter()
+            .filter_map(|(doc_id, score)| {
+                let doc = self.docs.get(&doc_id)?;
+                let order = self.sessions.get(&doc.session_id)?.order;
+                Some((
+                    order,
+                    SearchHit {
+                        session_id: doc.session_id.clone(),
+                        tab_index: doc.tab_index,
+                        score,
+                    },
+                ))
+            })
+            .collect();
+        hits.sort_by(|(a_order, a), (b_order, b)| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a_order.cmp(b_order))
+                .then(a.tab_index.cmp(&b.tab_index))
+        });
+        hits.into_iter().map(|(_, hit)| hit).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_saved_tab(url: &str, title: &str) -> SavedTab {
+        SavedTab {
+            url: url.to_string(),
+            title: title.to_string(),
+            domain: crate::domain::group_key(url).1,
+            ..SavedTab::default()
+        }
+    }
+
+    fn create_test_data() -> StorageData {
+        let mut data = StorageData::new();
+        data.add_session(CollapsedSession {
+            id: "rust".to_string(),
+            name: "Rust research".to_string(),
+            timestamp: 1000.0,
+            tabs: vec![
+                create_saved_tab("https://github.com/rust-lang/rust/issues/1", "Tracking issue for async closures"),
+                create_saved_tab("https://doc.rust-lang.org/book/", "The Rust Programming Language"),
+                create_saved_tab("https://news.ycombinator.com/item?id=1", "Show HN: A tab manager"),
+            ],
+        });
+        data.add_session(CollapsedSession {
+            id: "recipes".to_string(),
+            name: "Dinner".to_string(),
+            timestamp: 2000.0,
+            tabs: vec![
+                create_saved_tab("https://www.seriouseats.com/rust-free-cast-iron", "Keeping cast iron pans rust free"),
+                create_saved_tab("https://en.wikipedia.org/wiki/Risotto", "Risotto - Wikipedia"),
+            ],
+        });
+        data
+    }
+
+    fn found(hits: &[SearchHit]) -> Vec<(&str, usize)> {
+        hits.iter().map(|hit| (hit.session_id.as_str(), hit.tab_index)).collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens: Vec<(Range<usize>, String)> = tokens("GitHub · rust-lang/Rust").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0..6, "github".to_string()),
+                (10..14, "rust".to_string()),
+                (15..19, "lang".to_string()),
+                (20..24, "rust".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_every_word_must_match() {
+        let index = SearchIndex::build(&create_test_data());
+
+        assert_eq!(found(&index.search("rust issue")), vec![("rust", 0)]);
+        assert_eq!(found(&index.search("cast iron")), vec![("recipes", 0)]);
+        assert!(index.search("rust risotto").is_empty());
+        assert!(index.search("").is_empty());
+    }
+
+    #[test]
+    fn test_title_matches_rank_first() {
+        let index = SearchIndex::build(&create_test_data());
+
+        let hits = index.search("rust");
+
+        // Both tabs with "rust" in the title outrank the ones that only
+        // match through their URL or session name
+        let top: Vec<(&str, usize)> = found(&hits)[..3].to_vec();
+        assert!(top.contains(&("rust", 1)));
+        assert!(top.contains(&("recipes", 0)));
+        assert_eq!(hits.len(), 4);
+        assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn test_prefix_matches() {
+        let index = SearchIndex::build(&create_test_data());
+
+        assert_eq!(found(&index.search("wiki")), vec![("recipes", 1)]);
+        assert_eq!(found(&index.search("ycomb")), vec![("rust", 2)]);
+
+        // A whole word outranks a word it is only the start of
+        let exact = index.search("programming");
+        let prefix = index.search("program");
+        assert!(exact[0].score > prefix[0].score);
+    }
+
+    #[test]
+    fn test_url_noise_is_not_indexed() {
+        let index = SearchIndex::build(&create_test_data());
+
+        assert!(index.search("https").is_empty());
+        assert_eq!(found(&index.search("www seriouseats")), Vec::<(&str, usize)>::new());
+    }
+
+    #[test]
+    fn test_sync_reindexes_only_changed_sessions() {
+        let mut data = create_test_data();
+        let mut index = SearchIndex::build(&data);
+        assert_eq!(index.len(), 5);
+
+        assert_eq!(index.sync(&data), 0);
+
+        data.update_session_name("recipes", "Weeknight dinners".to_string());
+        data.sessions[0].tabs.remove(2);
+        assert_eq!(index.sync(&data), 2);
+        assert_eq!(index.len(), 4);
+        assert_eq!(found(&index.search("weeknight risotto")), vec![("recipes", 1)]);
+        assert!(index.search("ycombinator").is_empty());
+
+        data.remove_session("rust");
+        index.sync(&data);
+        assert_eq!(index.len(), 2);
+        assert!(index.search("closures").is_empty());
+        assert!(!index.terms.contains_key("closures"));
+    }
+
+    #[test]
+    fn test_group_by_session() {
+        let index = SearchIndex::build(&create_test_data());
+
+        let grouped = group_by_session(&index.search("rust"));
+
+        assert_eq!(grouped.len(), 2);
+        assert!(grouped[0].best >= grouped[1].best);
+        let rust = grouped.iter().find(|group| group.session_id == "rust").unwrap();
+        assert_eq!(rust.tabs.len(), 3);
+    }
+
+    #[test]
+    fn test_highlight_ranges() {
+        let terms = query_terms("rust LANG");
+        let text = "GitHub · rust-lang/Rustacean";
+
+        let ranges = highlight_ranges(text, &terms);
+
+        let marked: Vec<&str> = ranges.iter().map(|range| &text[range.clone()]).collect();
+        assert_eq!(marked, vec!["rust", "lang", "Rust"]);
+    }
+}
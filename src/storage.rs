@@ -1,21 +1,319 @@
-/// Storage serialization utilities for chrome.storage.local
+//! Storage serialization utilities for chrome.storage.local
+//!
+//! Sessions are stored one per key (`tab_hoarder_session_<id>`), with an
+//! index under `tab_hoarder_data` listing them in order, so a collapse,
+//! rename or delete only rewrites what changed. Older builds wrote every
+//! session into `tab_hoarder_data` itself.
+//!
+//! Every write bumps a `revision` counter in the index and only applies if
+//! the stored revision is still the one the data was loaded at
+//! (compare-and-swap). A writer that loses the race reloads, merges its
+//! edits into the fresh data with `StorageData::merge`, and tries again.
+//!
+//! Session values may be stored compressed (see `compression`); they are
+//! decoded on load, so callers only ever see plain sessions.
+//!
+//! The data carries a schema `version`. Data written by older builds is
+//! upgraded on load by running each step of `MIGRATIONS` in turn; callers
+//! back up the original blob while it is replaced, when there is room.
 
-use crate::tab_data::CollapsedSession;
+pub mod chrome;
+pub mod compression;
+pub mod keys;
+pub mod memory;
+pub mod quota;
+mod repository;
+
+pub use chrome::ChromeStorage;
+pub use compression::{CompressionStats, SessionEncoding};
+pub use memory::MemoryStorage;
+pub use quota::{QuotaPolicy, QuotaReport, RoomPlan, StorageUsage};
+pub use repository::{Key, StorageRepository};
+
+use crate::domain::group_key_with_mode;
+use crate::public_suffix::SuffixMode;
+use crate::tab_data::{CollapsedSession, SavedTab};
+use crate::url_normalizer::UrlNormalizer;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+/// chrome.storage key holding the session index (or, before version 2,
+/// every session)
+pub const STORAGE_KEY: &str = "tab_hoarder_data";
+
+/// Compare-and-swap attempts before a save gives up
+pub const MAX_WRITE_ATTEMPTS: usize = 5;
+
+/// Prefix of the per-session chrome.storage keys
+pub const SESSION_KEY_PREFIX: &str = "tab_hoarder_session_";
+
+/// Schema version written by this build
+///
+/// History:
+/// - 0: `{ sessions }` with no version field; saved tab domains were the
+///   last two host labels, or three after `co`/`com` and a two-letter TLD
+///   (`github.io` for `foo.github.io`, `ac.uk` for `www.ox.ac.uk`,
+///   `extensions` for `chrome://extensions`)
+/// - 1: `{ version, sessions }`; saved tab domains are Public Suffix List
+///   registrable domains, or a label such as "Browser pages"
+/// - 2: same data, stored as a `StorageIndex` plus one key per session
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// One upgrade step, rewriting the raw blob in place
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Upgrade steps; `MIGRATIONS[n]` turns version `n` into version `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0_to_v1, migrate_v1_to_v2];
 
 /// Root storage structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StorageData {
+    /// Schema version; absent in version 0 blobs
+    #[serde(default)]
+    pub version: u32,
+    /// Revision the data was loaded at; 0 before the first write
+    #[serde(default)]
+    pub revision: u64,
     pub sessions: Vec<CollapsedSession>,
 }
 
+/// Contents of `STORAGE_KEY` from version 2 on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageIndex {
+    pub version: u32,
+    /// Bumped by every write
+    #[serde(default)]
+    pub revision: u64,
+    /// Session IDs in display order
+    pub session_ids: Vec<String>,
+}
+
+/// chrome.storage key of one session
+pub fn session_key(session_id: &str) -> String {
+    format!("{}{}", SESSION_KEY_PREFIX, session_id)
+}
+
+/// Per-session keys to fetch before `StorageData::from_stored` can load
+/// `root` (the value under `STORAGE_KEY`); empty for older single-blob data
+pub fn session_keys_for(root: &Value) -> Vec<String> {
+    root.get("session_ids")
+        .and_then(Value::as_array)
+        .map(|ids| ids.iter().filter_map(Value::as_str).map(session_key).collect())
+        .unwrap_or_default()
+}
+
+/// Revision of the value under `STORAGE_KEY` (0 if it has none)
+pub fn stored_revision(root: &Value) -> u64 {
+    root.get("revision").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Result of loading stored data
+#[derive(Debug, Clone)]
+pub struct LoadedStorage {
+    pub data: StorageData,
+    /// Version the data was stored with
+    pub from_version: u32,
+    /// The original data, when a migration changed it
+    pub backup: Option<Value>,
+    /// Sessions listed in the index whose key was missing
+    pub missing_sessions: Vec<String>,
+}
+
+impl LoadedStorage {
+    /// Whether the upgraded data should be written back in full
+    pub fn migrated(&self) -> bool {
+        self.backup.is_some()
+    }
+
+    /// chrome.storage key for the pre-migration backup
+    pub fn backup_key(&self) -> String {
+        backup_key(self.from_version)
+    }
+}
+
+/// chrome.storage key for the backup of a version `version` blob
+pub fn backup_key(version: u32) -> String {
+    format!("{}_backup_v{}", STORAGE_KEY, version)
+}
+
 impl StorageData {
     pub fn new() -> Self {
         StorageData {
+            version: SCHEMA_VERSION,
+            revision: 0,
             sessions: Vec::new(),
         }
     }
 
+    /// Load stored data of any known version, upgrading it to
+    /// `SCHEMA_VERSION`
+    ///
+    /// `root` is the value under `STORAGE_KEY`; `items` holds the keys
+    /// named by `session_keys_for(root)`.
+    ///
+    /// Algorithm:
+    /// 1. Null means nothing stored yet: return empty data
+    /// 2. An index is expanded into `{ version, sessions }`, skipping (and
+    ///    reporting) sessions whose key is missing
+    /// 3. Read `version` (missing = 0); refuse versions newer than this
+    ///    build so they are never overwritten with a downgrade
+    /// 4. Run each migration from that version up to `SCHEMA_VERSION`
+    /// 5. Deserialize the result
+    pub fn from_stored(root: Value, items: &Map<String, Value>) -> Result<LoadedStorage, String> {
+        if root.is_null() {
+            return Ok(LoadedStorage {
+                data: StorageData::new(),
+                from_version: SCHEMA_VERSION,
+                backup: None,
+                missing_sessions: Vec::new(),
+            });
+        }
+
+        let mut missing_sessions = Vec::new();
+        let value = match root.get("session_ids").and_then(Value::as_array) {
+            Some(ids) => {
+                let mut sessions = Vec::new();
+                for id in ids.iter().filter_map(Value::as_str) {
+                    match items.get(&session_key(id)) {
+                        Some(session) => sessions.push(compression::decode_session(session.clone())?),
+                        None => missing_sessions.push(id.to_string()),
+                    }
+                }
+                let mut blob = Map::new();
+                for field in ["version", "revision"] {
+                    if let Some(value) = root.get(field) {
+                        blob.insert(field.to_string(), value.clone());
+                    }
+                }
+                blob.insert("sessions".to_string(), Value::Array(sessions));
+                Value::Object(blob)
+            }
+            None => root,
+        };
+
+        let from_version = stored_version(&value)?;
+        if from_version > SCHEMA_VERSION {
+            return Err(format!(
+                "Storage schema version {} is newer than this version of Tab Hoarder supports ({})",
+                from_version, SCHEMA_VERSION
+            ));
+        }
+
+        let backup = (from_version < SCHEMA_VERSION).then(|| value.clone());
+        let mut value = value;
+        for migration in &MIGRATIONS[from_version as usize..] {
+            migration(&mut value)?;
+        }
+
+        let data = serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse storage: {:?}", e))?;
+
+        Ok(LoadedStorage {
+            data,
+            from_version,
+            backup,
+            missing_sessions,
+        })
+    }
+
+    fn session_ids(&self) -> Vec<String> {
+        self.sessions.iter().map(|s| s.id.clone()).collect()
+    }
+
+    /// Every key needed to store this data from scratch
+    pub fn full_write(&self) -> StorageWrite {
+        StorageWrite {
+            expected_revision: self.revision,
+            index: self.next_index_for(self),
+            sessions: self.sessions.clone(),
+            removed_keys: Vec::new(),
+        }
+    }
+
+    /// Keys that changed between `previous` (what was loaded) and `self`,
+    /// or `None` if nothing changed
+    ///
+    /// Only new or modified sessions are rewritten. The small index is
+    /// always rewritten, since it carries the revision.
+    pub fn write_since(&self, previous: &StorageData) -> Option<StorageWrite> {
+        let sessions: Vec<CollapsedSession> = self
+            .sessions
+            .iter()
+            .filter(|session| !previous.sessions.contains(session))
+            .cloned()
+            .collect();
+
+        let removed_keys: Vec<String> = previous
+            .sessions
+            .iter()
+            .filter(|old| !self.sessions.iter().any(|session| session.id == old.id))
+            .map(|old| session_key(&old.id))
+            .collect();
+
+        let unchanged = sessions.is_empty()
+            && removed_keys.is_empty()
+            && previous.version == SCHEMA_VERSION
+            && previous.session_ids() == self.session_ids();
+        if unchanged {
+            return None;
+        }
+
+        Some(StorageWrite {
+            expected_revision: previous.revision,
+            index: previous.next_index_for(self),
+            sessions,
+            removed_keys,
+        })
+    }
+
+    /// Index listing `updated`'s sessions, one revision after `self`
+    fn next_index_for(&self, updated: &StorageData) -> StorageIndex {
+        StorageIndex {
+            version: SCHEMA_VERSION,
+            revision: self.revision + 1,
+            session_ids: updated.session_ids(),
+        }
+    }
+
+    /// Three-way merge of concurrent edits, one session at a time
+    ///
+    /// `base` is what `mine` was edited from; `theirs` is what is stored
+    /// now. The result is at `theirs.revision`.
+    ///
+    /// Algorithm:
+    /// 1. Start from `theirs`
+    /// 2. Drop the sessions `mine` deleted from `base`
+    /// 3. Replace the sessions `mine` changed with our version; if `theirs`
+    ///    deleted one of them, the deletion wins
+    /// 4. Append the sessions `mine` added
+    pub fn merge(base: &StorageData, mine: &StorageData, theirs: &StorageData) -> StorageData {
+        let in_base = |id: &str| base.sessions.iter().any(|s| s.id == id);
+        let in_mine = |id: &str| mine.sessions.iter().find(|s| s.id == id);
+
+        let mut merged = theirs.clone();
+        merged
+            .sessions
+            .retain(|session| !in_base(&session.id) || in_mine(&session.id).is_some());
+
+        for session in &mut merged.sessions {
+            if let Some(ours) = in_mine(&session.id)
+                && !base.sessions.contains(ours)
+            {
+                *session = ours.clone();
+            }
+        }
+
+        for session in &mine.sessions {
+            let already_there = merged.sessions.iter().any(|s| s.id == session.id);
+            if !in_base(&session.id) && !already_there {
+                merged.sessions.push(session.clone());
+            }
+        }
+
+        merged
+    }
+
     pub fn add_session(&mut self, session: CollapsedSession) {
         self.sessions.push(session);
     }
@@ -26,6 +324,27 @@ impl StorageData {
         self.sessions.len() < original_len
     }
 
+    /// Remove one saved tab per URL in `urls` from a session (tabs that are
+    /// open again), dropping the session if that empties it; returns how
+    /// many tabs were removed
+    pub fn remove_reopened_tabs(&mut self, session_id: &str, urls: &[&str]) -> usize {
+        let Some(pos) = self.sessions.iter().position(|s| s.id == session_id) else {
+            return 0;
+        };
+        let session = &mut self.sessions[pos];
+        let original_len = session.tabs.len();
+        for url in urls {
+            if let Some(i) = session.tabs.iter().position(|tab| tab.url == *url) {
+                session.tabs.remove(i);
+            }
+        }
+        let removed = original_len - session.tabs.len();
+        if removed > 0 && session.tabs.is_empty() {
+            self.sessions.remove(pos);
+        }
+        removed
+    }
+
     pub fn update_session_name(&mut self, session_id: &str, new_name: String) -> bool {
         self.sessions
             .iter_mut()
@@ -35,6 +354,132 @@ impl StorageData {
             })
             .is_some()
     }
+
+    /// Remove duplicate tabs inside every session; returns how many tabs
+    /// were removed
+    ///
+    /// Each session keeps one copy of every page, so none ends up empty,
+    /// and sessions that were already empty are left alone.
+    pub fn dedup_session_tabs(&mut self, normalizer: &UrlNormalizer) -> usize {
+        self.sessions
+            .iter_mut()
+            .map(|session| session.dedup_tabs(normalizer))
+            .sum()
+    }
+
+    /// The saved tabs `dedup_session_tabs` would remove, with their sessions
+    pub fn session_duplicates(&self, normalizer: &UrlNormalizer) -> Vec<(&CollapsedSession, &SavedTab)> {
+        self.sessions
+            .iter()
+            .flat_map(|session| {
+                session
+                    .duplicate_tabs(normalizer)
+                    .into_iter()
+                    .map(move |tab| (session, tab))
+            })
+            .collect()
+    }
+
+    /// Remove saved tabs whose exact URL is also in `newer` or in a more
+    /// recent session, dropping sessions that end up empty; returns how
+    /// many tabs were removed
+    pub fn drop_saved_duplicates(&mut self, newer: &CollapsedSession) -> usize {
+        let mut seen_urls: HashSet<String> = newer.tabs.iter().map(|tab| tab.url.clone()).collect();
+        let mut newest_first: Vec<&mut CollapsedSession> = self.sessions.iter_mut().collect();
+        newest_first.sort_by(|a, b| b.timestamp.total_cmp(&a.timestamp));
+
+        let mut removed = 0;
+        for session in newest_first {
+            let original_len = session.tabs.len();
+            session.tabs.retain(|tab| seen_urls.insert(tab.url.clone()));
+            removed += original_len - session.tabs.len();
+        }
+        self.sessions.retain(|session| !session.tabs.is_empty());
+        removed
+    }
+}
+
+/// An incremental, compare-and-swap update to chrome.storage
+///
+/// Applies only if the stored index is still at `expected_revision`. Set
+/// `items()` with a single `chrome.storage.local.set`, then remove
+/// `removed_keys`, so the index never names a session key that is gone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageWrite {
+    pub expected_revision: u64,
+    /// New index, at `expected_revision + 1`
+    pub index: StorageIndex,
+    /// Sessions to (re)write under their own keys
+    pub sessions: Vec<CollapsedSession>,
+    pub removed_keys: Vec<String>,
+}
+
+impl StorageWrite {
+    /// Whether this write may replace the value under `STORAGE_KEY`
+    pub fn applies_to(&self, root: &Value) -> bool {
+        stored_revision(root) == self.expected_revision
+    }
+
+    /// Keys and values to set, with sessions stored as `encoding`
+    pub fn items(&self, encoding: SessionEncoding) -> Result<Map<String, Value>, String> {
+        let mut items = Map::new();
+        for session in &self.sessions {
+            let value = serde_json::to_value(session)
+                .map_err(|e| format!("Failed to serialize session: {:?}", e))?;
+            items.insert(session_key(&session.id), compression::encode_session(value, encoding)?);
+        }
+        let value = serde_json::to_value(&self.index)
+            .map_err(|e| format!("Failed to serialize index: {:?}", e))?;
+        items.insert(STORAGE_KEY.to_string(), value);
+        Ok(items)
+    }
+}
+
+fn stored_version(value: &Value) -> Result<u32, String> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("Invalid storage schema version: {}", version)),
+    }
+}
+
+/// Version 0 → 1: stamp the version and recompute each saved tab's domain
+/// with the Public Suffix List
+///
+/// Always counts private suffixes (the default) rather than following the
+/// popup's current setting, so the same blob always migrates the same way.
+fn migrate_v0_to_v1(value: &mut Value) -> Result<(), String> {
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| "Failed to migrate storage: root is not an object".to_string())?;
+
+    let sessions = root
+        .get_mut("sessions")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| "Failed to migrate storage: missing sessions".to_string())?;
+
+    for tab in sessions
+        .iter_mut()
+        .filter_map(|session| session.get_mut("tabs").and_then(Value::as_array_mut))
+        .flatten()
+    {
+        if let Some(url) = tab.get("url").and_then(Value::as_str) {
+            let domain = group_key_with_mode(url, SuffixMode::IncludePrivate).1;
+            tab["domain"] = Value::String(domain);
+        }
+    }
+
+    root.insert("version".to_string(), Value::from(1));
+    Ok(())
+}
+
+/// Version 1 → 2: the data is unchanged; only the storage layout (index
+/// plus per-session keys) differs, and that is written by `full_write`
+fn migrate_v1_to_v2(value: &mut Value) -> Result<(), String> {
+    value["version"] = Value::from(2);
+    Ok(())
 }
 
 impl Default for StorageData {
@@ -47,20 +492,7 @@ impl Default for StorageData {
 mod tests {
     use super::*;
     use crate::tab_data::SavedTab;
-
-    fn create_test_session(id: &str, name: &str) -> CollapsedSession {
-        CollapsedSession {
-            id: id.to_string(),
-            name: name.to_string(),
-            timestamp: 1698508200000.0,
-            tabs: vec![SavedTab {
-                url: "https://google.com".to_string(),
-                title: "Google".to_string(),
-                domain: "google.com".to_string(),
-                pinned: false,
-            }],
-        }
-    }
+    use crate::test_support::TestSession;
 
     #[test]
     fn test_storage_data_new() {
@@ -71,7 +503,7 @@ mod tests {
     #[test]
     fn test_add_session() {
         let mut storage = StorageData::new();
-        let session = create_test_session("session-1", "Test Session");
+        let session = TestSession::new("session-1").name("Test Session").build();
 
         storage.add_session(session);
 
@@ -82,8 +514,8 @@ mod tests {
     #[test]
     fn test_remove_session() {
         let mut storage = StorageData::new();
-        storage.add_session(create_test_session("session-1", "Session 1"));
-        storage.add_session(create_test_session("session-2", "Session 2"));
+        storage.add_session(TestSession::new("session-1").name("Session 1").build());
+        storage.add_session(TestSession::new("session-2").name("Session 2").build());
 
         let removed = storage.remove_session("session-1");
 
@@ -95,7 +527,7 @@ mod tests {
     #[test]
     fn test_remove_nonexistent_session() {
         let mut storage = StorageData::new();
-        storage.add_session(create_test_session("session-1", "Session 1"));
+        storage.add_session(TestSession::new("session-1").name("Session 1").build());
 
         let removed = storage.remove_session("nonexistent");
 
@@ -106,7 +538,7 @@ mod tests {
     #[test]
     fn test_update_session_name() {
         let mut storage = StorageData::new();
-        storage.add_session(create_test_session("session-1", "Old Name"));
+        storage.add_session(TestSession::new("session-1").name("Old Name").build());
 
         let updated = storage.update_session_name("session-1", "New Name".to_string());
 
@@ -114,15 +546,264 @@ mod tests {
         assert_eq!(storage.sessions[0].name, "New Name");
     }
 
+    #[test]
+    fn test_dedup_session_tabs() {
+        let mut storage = StorageData::new();
+        let mut session = TestSession::new("session-1").name("Session 1").build();
+        session.tabs.push(SavedTab {
+            url: "https://www.google.com/?utm_source=mail".to_string(),
+            title: "Google again".to_string(),
+            domain: "google.com".to_string(),
+            pinned: false,
+            ..SavedTab::default()
+        });
+        storage.add_session(session);
+        storage.add_session(TestSession::new("session-2").name("Session 2").build());
+        storage.add_session(CollapsedSession {
+            tabs: Vec::new(),
+            ..TestSession::new("session-3").name("Already empty").build()
+        });
+
+        let duplicates = storage.session_duplicates(&UrlNormalizer::default());
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].0.id.as_str(), duplicates[0].1.title.as_str()), ("session-1", "Google again"));
+
+        let removed = storage.dedup_session_tabs(&UrlNormalizer::default());
+
+        // Duplicates are only removed within a session
+        assert_eq!(removed, 1);
+        assert_eq!(storage.sessions[0].tabs.len(), 1);
+        assert_eq!(storage.sessions[1].tabs.len(), 1);
+        // Sessions dedup did not empty are kept
+        assert_eq!(storage.sessions.len(), 3);
+    }
+
+    /// chrome.storage dumps written by each schema version
+    const FIXTURES: [(u32, &str); 3] = [
+        (0, include_str!("../tests/fixtures/storage/v0.json")),
+        (1, include_str!("../tests/fixtures/storage/v1.json")),
+        (2, include_str!("../tests/fixtures/storage/v2.json")),
+    ];
+
+    /// Version 2 as written once writes carried a revision
+    const REVISIONED: &str = include_str!("../tests/fixtures/storage/v2-revision.json");
+
+    /// Load a dump the way the UIs do: root key first, then session keys
+    fn load_items(items: &Map<String, Value>) -> Result<LoadedStorage, String> {
+        let root = items.get(STORAGE_KEY).cloned().unwrap_or(Value::Null);
+        let session_items: Map<String, Value> = session_keys_for(&root)
+            .into_iter()
+            .filter_map(|key| items.get(&key).map(|value| (key, value.clone())))
+            .collect();
+        StorageData::from_stored(root, &session_items)
+    }
+
+    fn load_fixture(text: &str) -> LoadedStorage {
+        let items: Map<String, Value> = serde_json::from_str(text).unwrap();
+        load_items(&items).unwrap()
+    }
+
+    /// Apply a write to a dump, as the compareAndSetStorage bridge would
+    fn apply(items: &mut Map<String, Value>, write: &StorageWrite) -> bool {
+        let root = items.get(STORAGE_KEY).cloned().unwrap_or(Value::Null);
+        if !write.applies_to(&root) {
+            return false;
+        }
+        items.extend(write.items(SessionEncoding::Plain).unwrap());
+        for key in &write.removed_keys {
+            items.remove(key);
+        }
+        true
+    }
+
+    #[test]
+    fn test_every_fixture_loads_at_current_version() {
+        assert_eq!(FIXTURES.len(), SCHEMA_VERSION as usize + 1);
+
+        for (version, text) in FIXTURES {
+            let loaded = load_fixture(text);
+
+            assert_eq!(loaded.from_version, version);
+            assert_eq!(loaded.data.version, SCHEMA_VERSION);
+            assert!(!loaded.data.sessions.is_empty());
+            assert!(loaded.missing_sessions.is_empty());
+            assert_eq!(loaded.migrated(), version < SCHEMA_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_v0_migration_recomputes_domains() {
+        let loaded = load_fixture(FIXTURES[0].1);
+
+        let domains: Vec<&str> = loaded.data.sessions[0].tabs.iter().map(|t| t.domain.as_str()).collect();
+        assert_eq!(domains, vec!["bbc.co.uk", "docs.rs", "ox.ac.uk", "Browser pages"]);
+        assert_eq!(loaded.data.sessions[1].tabs[0].domain, "hughdbrown.github.io");
+        assert!(loaded.data.sessions[0].tabs[1].pinned);
+        assert_eq!(loaded.data.sessions[1].name, "Reading list");
+    }
+
+    #[test]
+    fn test_migration_keeps_original_as_backup() {
+        let items: Map<String, Value> = serde_json::from_str(FIXTURES[0].1).unwrap();
+
+        let loaded = load_items(&items).unwrap();
+
+        assert_eq!(loaded.backup.as_ref(), items.get(STORAGE_KEY));
+        assert_eq!(loaded.backup_key(), "tab_hoarder_data_backup_v0");
+    }
+
+    #[test]
+    fn test_single_blob_is_split_into_session_keys() {
+        let mut items: Map<String, Value> = serde_json::from_str(FIXTURES[1].1).unwrap();
+        let loaded = load_items(&items).unwrap();
+
+        assert!(apply(&mut items, &loaded.data.full_write()));
+
+        let index: StorageIndex = serde_json::from_value(items[STORAGE_KEY].clone()).unwrap();
+        assert_eq!(index.version, SCHEMA_VERSION);
+        assert_eq!(index.session_ids, vec!["0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70"]);
+        assert!(items.contains_key("tab_hoarder_session_0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70"));
+
+        let reloaded = load_items(&items).unwrap();
+        assert!(!reloaded.migrated());
+        assert_eq!(reloaded.data.sessions, loaded.data.sessions);
+    }
+
+    #[test]
+    fn test_write_since_only_touches_changed_sessions() {
+        let mut items: Map<String, Value> = serde_json::from_str(REVISIONED).unwrap();
+        let before = load_items(&items).unwrap().data;
+        assert_eq!(before.revision, 4);
+
+        // Rename: one session key plus the index
+        let mut renamed = before.clone();
+        renamed.update_session_name("5d2e8f90-1c47-4b3e-8e2a-6a7b9c0d1e2f", "Later".to_string());
+        let write = renamed.write_since(&before).unwrap();
+        assert_eq!(write.sessions.len(), 1);
+        assert_eq!(write.index.revision, 5);
+        assert_eq!(write.items(SessionEncoding::Plain).unwrap().len(), 2);
+        assert!(apply(&mut items, &write));
+
+        // Add and remove: new key, index, and the removed key
+        let renamed = load_items(&items).unwrap().data;
+        let mut changed = renamed.clone();
+        changed.remove_session("0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70");
+        changed.add_session(TestSession::new("session-3").name("New").build());
+        let write = changed.write_since(&renamed).unwrap();
+        assert_eq!(write.sessions.len(), 1);
+        assert_eq!(write.removed_keys, vec!["tab_hoarder_session_0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70"]);
+        assert!(apply(&mut items, &write));
+
+        let reloaded = load_items(&items).unwrap().data;
+        let names: Vec<&str> = reloaded.sessions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Later", "New"]);
+        assert_eq!(reloaded.revision, 6);
+
+        assert!(reloaded.write_since(&reloaded).is_none());
+    }
+
+    #[test]
+    fn test_stale_write_is_rejected() {
+        let mut items: Map<String, Value> = serde_json::from_str(REVISIONED).unwrap();
+        let popup_copy = load_items(&items).unwrap().data;
+        let viewer_copy = popup_copy.clone();
+
+        let mut collapsed = popup_copy.clone();
+        collapsed.add_session(TestSession::new("session-3").name("Collapsed").build());
+        assert!(apply(&mut items, &collapsed.write_since(&popup_copy).unwrap()));
+
+        let mut deleted = viewer_copy.clone();
+        deleted.remove_session("0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70");
+        assert!(!apply(&mut items, &deleted.write_since(&viewer_copy).unwrap()));
+
+        // The viewer reloads, merges its delete, and retries
+        let current = load_items(&items).unwrap().data;
+        let merged = StorageData::merge(&viewer_copy, &deleted, &current);
+        assert!(apply(&mut items, &merged.write_since(&current).unwrap()));
+
+        let reloaded = load_items(&items).unwrap().data;
+        let ids: Vec<&str> = reloaded.sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["5d2e8f90-1c47-4b3e-8e2a-6a7b9c0d1e2f", "session-3"]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base = StorageData::new();
+        base.add_session(TestSession::new("a").name("A").build());
+        base.add_session(TestSession::new("b").name("B").build());
+        base.add_session(TestSession::new("c").name("C").build());
+
+        let mut mine = base.clone();
+        mine.remove_session("a");
+        mine.update_session_name("b", "Mine".to_string());
+        mine.update_session_name("c", "Mine too".to_string());
+        mine.add_session(TestSession::new("d").name("D").build());
+
+        let mut theirs = base.clone();
+        theirs.revision = 9;
+        theirs.update_session_name("b", "Theirs".to_string());
+        theirs.remove_session("c");
+        theirs.add_session(TestSession::new("e").name("E").build());
+
+        let merged = StorageData::merge(&base, &mine, &theirs);
+
+        let names: Vec<&str> = merged.sessions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Mine", "E", "D"]);
+        assert_eq!(merged.revision, 9);
+    }
+
+    #[test]
+    fn test_index_without_revision_starts_at_zero() {
+        let mut items: Map<String, Value> = serde_json::from_str(FIXTURES[2].1).unwrap();
+        let before = load_items(&items).unwrap().data;
+        assert_eq!(before.revision, 0);
+
+        let mut renamed = before.clone();
+        renamed.update_session_name("5d2e8f90-1c47-4b3e-8e2a-6a7b9c0d1e2f", "Later".to_string());
+        let write = renamed.write_since(&before).unwrap();
+        assert!(apply(&mut items, &write));
+
+        let reloaded = load_items(&items).unwrap().data;
+        assert_eq!(reloaded.revision, 1);
+        assert_eq!(reloaded.sessions[1].name, "Later");
+    }
+
+    #[test]
+    fn test_missing_session_key_is_reported() {
+        let mut items: Map<String, Value> = serde_json::from_str(FIXTURES[2].1).unwrap();
+        items.remove("tab_hoarder_session_0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70");
+
+        let loaded = load_items(&items).unwrap();
+
+        assert_eq!(loaded.data.sessions.len(), 1);
+        assert_eq!(loaded.missing_sessions, vec!["0b9c4d1e-7f3a-4a55-9a61-2f3c1d5e8b70"]);
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let value = serde_json::json!({ "version": SCHEMA_VERSION + 1, "session_ids": [] });
+
+        assert!(StorageData::from_stored(value, &Map::new()).is_err());
+    }
+
+    #[test]
+    fn test_nothing_stored() {
+        let loaded = StorageData::from_stored(Value::Null, &Map::new()).unwrap();
+
+        assert!(loaded.data.sessions.is_empty());
+        assert!(!loaded.migrated());
+    }
+
     #[test]
     fn test_serialization() {
         let mut storage = StorageData::new();
-        storage.add_session(create_test_session("session-1", "Test"));
+        storage.add_session(TestSession::new("session-1").name("Test").build());
 
         let json = serde_json::to_string(&storage).unwrap();
         let deserialized: StorageData = serde_json::from_str(&json).unwrap();
 
         assert_eq!(deserialized.sessions.len(), 1);
         assert_eq!(deserialized.sessions[0].id, "session-1");
+        assert_eq!(deserialized.version, SCHEMA_VERSION);
     }
 }
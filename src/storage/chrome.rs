@@ -0,0 +1,110 @@
+//! chrome.storage.local backend, through the storage.js bridge
+
+use super::{StorageRepository, StorageUsage, STORAGE_KEY};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(module = "/storage.js")]
+extern "C" {
+    #[wasm_bindgen(catch)]
+    async fn getStorage(key: &str) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn setStorage(key: &str, value: JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn removeStorage(keys: JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn getStorageItems(keys: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn compareAndSetStorage(
+        index_key: &str,
+        expected_revision: f64,
+        items: JsValue,
+        removed_keys: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn getStorageUsage() -> Result<JsValue, JsValue>;
+
+    fn onStorageChanged(key: &str, callback: &js_sys::Function) -> js_sys::Function;
+}
+
+/// chrome.storage.local, shared by every extension page
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChromeStorage;
+
+impl ChromeStorage {
+    /// Call `callback` with the new value whenever any page writes `key`;
+    /// returns a function that stops listening
+    pub fn on_changed(&self, key: &str, callback: &js_sys::Function) -> js_sys::Function {
+        onStorageChanged(key, callback)
+    }
+}
+
+/// Convert to a plain JS value (objects, not `Map`s) that chrome.storage accepts
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, String> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| format!("Failed to serialize: {:?}", e))
+}
+
+impl StorageRepository for ChromeStorage {
+    async fn get_raw(&self, key: &str) -> Result<Option<Value>, String> {
+        let value_js = getStorage(key)
+            .await
+            .map_err(|e| format!("Failed to get {}: {:?}", key, e))?;
+
+        if value_js.is_null() || value_js.is_undefined() {
+            Ok(None)
+        } else {
+            serde_wasm_bindgen::from_value(value_js)
+                .map(Some)
+                .map_err(|e| format!("Failed to parse {}: {:?}", key, e))
+        }
+    }
+
+    async fn get_many_raw(&self, keys: &[String]) -> Result<Map<String, Value>, String> {
+        let items_js = getStorageItems(to_js(&keys)?)
+            .await
+            .map_err(|e| format!("Failed to get storage: {:?}", e))?;
+
+        serde_wasm_bindgen::from_value(items_js).map_err(|e| format!("Failed to parse storage: {:?}", e))
+    }
+
+    async fn set_raw(&self, key: &str, value: Value) -> Result<(), String> {
+        setStorage(key, to_js(&value)?)
+            .await
+            .map_err(|e| format!("Failed to save {}: {:?}", key, e))
+    }
+
+    async fn remove_raw(&self, keys: &[String]) -> Result<(), String> {
+        removeStorage(to_js(&keys)?)
+            .await
+            .map_err(|e| format!("Failed to remove {:?}: {:?}", keys, e))
+    }
+
+    async fn compare_and_set(
+        &self,
+        expected_revision: u64,
+        items: Map<String, Value>,
+        removed_keys: &[String],
+    ) -> Result<bool, String> {
+        let applied = compareAndSetStorage(STORAGE_KEY, expected_revision as f64, to_js(&items)?, to_js(&removed_keys)?)
+            .await
+            .map_err(|e| format!("Failed to save storage: {:?}", e))?;
+
+        Ok(applied.as_bool().unwrap_or(false))
+    }
+
+    async fn usage(&self) -> Result<StorageUsage, String> {
+        let usage_js = getStorageUsage()
+            .await
+            .map_err(|e| format!("Failed to get storage usage: {:?}", e))?;
+
+        serde_wasm_bindgen::from_value(usage_js).map_err(|e| format!("Failed to parse storage usage: {:?}", e))
+    }
+}
@@ -0,0 +1,378 @@
+//! Compressed encoding for stored sessions
+//!
+//! Saved tabs repeat the same few origins, domains and favicons over and
+//! over. A compressed session moves those into dictionaries (each tab keeps
+//! an index plus the rest of its URL), deflates the resulting JSON and stores
+//! it as base64:
+//!
+//! ```json
+//! {"encoding": "dict-deflate-v1", "data": "<base64>"}
+//! ```
+//!
+//! Plain sessions are ordinary JSON objects, so both forms load side by
+//! side: `decode_session` tells them apart by the `encoding` field. Which
+//! form new writes use is the `session_encoding` preference.
+
+use super::quota::format_bytes;
+use super::SESSION_KEY_PREFIX;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Tag of the dictionary + deflate encoding
+pub const DICT_DEFLATE_V1: &str = "dict-deflate-v1";
+
+/// Largest session a compressed payload may inflate to
+const MAX_DECODED_BYTES: usize = 64 * 1024 * 1024;
+
+/// How new session writes are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SessionEncoding {
+    /// Plain JSON, readable by every version of Tab Hoarder
+    #[default]
+    Plain,
+    /// `dict-deflate-v1`, when it is smaller than the plain form
+    Compressed,
+}
+
+/// Encode a serialized session for storage
+///
+/// Falls back to the plain form when compressing would not save space,
+/// which is typical for sessions of one or two tabs.
+pub fn encode_session(session: Value, encoding: SessionEncoding) -> Result<Value, String> {
+    if encoding == SessionEncoding::Plain {
+        return Ok(session);
+    }
+
+    let plain_len = json_len(&session);
+    let packed = serde_json::to_vec(&pack(session.clone()))
+        .map_err(|e| format!("Failed to serialize session: {:?}", e))?;
+    let compressed = miniz_oxide::deflate::compress_to_vec(&packed, 9);
+
+    let mut encoded = Map::new();
+    encoded.insert("encoding".to_string(), Value::from(DICT_DEFLATE_V1));
+    encoded.insert("data".to_string(), Value::from(BASE64.encode(compressed)));
+    let encoded = Value::Object(encoded);
+
+    Ok(if json_len(&encoded) < plain_len { encoded } else { session })
+}
+
+/// Turn a stored session value back into the plain session JSON
+pub fn decode_session(stored: Value) -> Result<Value, String> {
+    let Some(encoding) = stored.get("encoding").and_then(Value::as_str) else {
+        return Ok(stored);
+    };
+    if encoding != DICT_DEFLATE_V1 {
+        return Err(format!(
+            "Session uses encoding {} which this version of Tab Hoarder does not support",
+            encoding
+        ));
+    }
+
+    let data = stored
+        .get("data")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "Failed to decode session: missing data".to_string())?;
+    let compressed = BASE64
+        .decode(data)
+        .map_err(|e| format!("Failed to decode session: {:?}", e))?;
+    let packed = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_DECODED_BYTES)
+        .map_err(|e| format!("Failed to decompress session: {:?}", e))?;
+    let packed: Value =
+        serde_json::from_slice(&packed).map_err(|e| format!("Failed to parse session: {:?}", e))?;
+
+    unpack(packed)
+}
+
+/// How much space compression saves across the stored sessions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub sessions: usize,
+    pub compressed_sessions: usize,
+    /// Size the sessions would take as plain JSON
+    pub plain_bytes: usize,
+    /// Size the sessions take as stored
+    pub stored_bytes: usize,
+}
+
+impl CompressionStats {
+    /// Plain size over stored size, e.g. 4.0 for a quarter of the space
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            1.0
+        } else {
+            self.plain_bytes as f64 / self.stored_bytes as f64
+        }
+    }
+
+    /// e.g. "3 of 4 sessions compressed, 412 KB → 96 KB (4.3×)"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} sessions compressed, {} → {} ({:.1}×)",
+            self.compressed_sessions,
+            self.sessions,
+            format_bytes(self.plain_bytes),
+            format_bytes(self.stored_bytes),
+            self.ratio()
+        )
+    }
+}
+
+/// Measure the session keys among chrome.storage `items`
+pub fn measure(items: &Map<String, Value>) -> Result<CompressionStats, String> {
+    let mut stats = CompressionStats::default();
+
+    for (key, stored) in items {
+        if !key.starts_with(SESSION_KEY_PREFIX) {
+            continue;
+        }
+        let plain = decode_session(stored.clone())?;
+
+        stats.sessions += 1;
+        if stored.get("encoding").is_some() {
+            stats.compressed_sessions += 1;
+        }
+        stats.plain_bytes += json_len(&plain);
+        stats.stored_bytes += json_len(stored);
+    }
+
+    Ok(stats)
+}
+
+/// Length of `value` serialized as JSON, which is what chrome.storage
+/// counts against the quota
+pub(super) fn json_len(value: &Value) -> usize {
+    serde_json::to_string(value).map(|json| json.len()).unwrap_or(0)
+}
+
+/// `https://docs.rs/serde/latest` → (`https://docs.rs`, `/serde/latest`)
+fn split_origin(url: &str) -> (&str, &str) {
+    let Some(scheme_end) = url.find("://") else {
+        return ("", url);
+    };
+    let host_start = scheme_end + 3;
+    match url[host_start..].find(['/', '?', '#']) {
+        Some(offset) => url.split_at(host_start + offset),
+        None => (url, ""),
+    }
+}
+
+/// Interns strings into a dictionary, returning each one's position
+#[derive(Default)]
+struct Dictionary {
+    entries: Vec<String>,
+    positions: HashMap<String, usize>,
+}
+
+impl Dictionary {
+    fn intern(&mut self, entry: &str) -> usize {
+        if let Some(&position) = self.positions.get(entry) {
+            return position;
+        }
+        self.entries.push(entry.to_string());
+        self.positions.insert(entry.to_string(), self.entries.len() - 1);
+        self.entries.len() - 1
+    }
+}
+
+/// Replace each tab's `url`, `domain` and `fav_icon_url` with dictionary
+/// references
+///
+/// Algorithm:
+/// 1. Split every URL into origin and remainder
+/// 2. Intern origins, domains and favicons in order of first appearance
+/// 3. Store `o` (origin index), `p` (remainder), `d` (domain index) and
+///    `f` (favicon index) in place of `url`, `domain` and `fav_icon_url`;
+///    other fields are kept as they are
+fn pack(mut session: Value) -> Value {
+    let mut origins = Dictionary::default();
+    let mut domains = Dictionary::default();
+    let mut favicons = Dictionary::default();
+
+    if let Some(tabs) = session.get_mut("tabs").and_then(Value::as_array_mut) {
+        for tab in tabs.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(Value::String(url)) = tab.remove("url") {
+                let (origin, rest) = split_origin(&url);
+                tab.insert("o".to_string(), Value::from(origins.intern(origin)));
+                tab.insert("p".to_string(), Value::from(rest));
+            }
+            if let Some(Value::String(domain)) = tab.remove("domain") {
+                tab.insert("d".to_string(), Value::from(domains.intern(&domain)));
+            }
+            if let Some(Value::String(favicon)) = tab.remove("fav_icon_url") {
+                tab.insert("f".to_string(), Value::from(favicons.intern(&favicon)));
+            }
+        }
+    }
+
+    let mut packed = Map::new();
+    packed.insert("origins".to_string(), Value::from(origins.entries));
+    packed.insert("domains".to_string(), Value::from(domains.entries));
+    // Sessions packed before favicons were saved have no `favicons` entry
+    if !favicons.entries.is_empty() {
+        packed.insert("favicons".to_string(), Value::from(favicons.entries));
+    }
+    packed.insert("session".to_string(), session);
+    Value::Object(packed)
+}
+
+/// Inverse of `pack`
+fn unpack(packed: Value) -> Result<Value, String> {
+    let dictionary = |name: &str| -> Vec<String> {
+        packed
+            .get(name)
+            .and_then(Value::as_array)
+            .map(|entries| entries.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+    let origins = dictionary("origins");
+    let domains = dictionary("domains");
+    let favicons = dictionary("favicons");
+    let lookup = |entries: &[String], index: Option<u64>| -> Result<String, String> {
+        index
+            .and_then(|index| entries.get(index as usize))
+            .cloned()
+            .ok_or_else(|| "Failed to decode session: bad dictionary reference".to_string())
+    };
+
+    let mut session = packed
+        .get("session")
+        .cloned()
+        .ok_or_else(|| "Failed to decode session: missing session".to_string())?;
+
+    if let Some(tabs) = session.get_mut("tabs").and_then(Value::as_array_mut) {
+        for tab in tabs.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(origin) = tab.remove("o") {
+                let rest = tab.remove("p");
+                let origin = lookup(&origins, origin.as_u64())?;
+                let rest = rest.as_ref().and_then(Value::as_str).unwrap_or("");
+                tab.insert("url".to_string(), Value::from(origin + rest));
+            }
+            if let Some(domain) = tab.remove("d") {
+                tab.insert("domain".to_string(), Value::from(lookup(&domains, domain.as_u64())?));
+            }
+            if let Some(favicon) = tab.remove("f") {
+                tab.insert("fav_icon_url".to_string(), Value::from(lookup(&favicons, favicon.as_u64())?));
+            }
+        }
+    }
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::session_key;
+    use crate::tab_data::CollapsedSession;
+    use crate::test_support::TestSession;
+
+    fn to_value(session: &CollapsedSession) -> Value {
+        serde_json::to_value(session).unwrap()
+    }
+
+    #[test]
+    fn test_split_origin() {
+        assert_eq!(split_origin("https://docs.rs/serde"), ("https://docs.rs", "/serde"));
+        assert_eq!(split_origin("https://example.com?q=1"), ("https://example.com", "?q=1"));
+        assert_eq!(split_origin("https://example.com"), ("https://example.com", ""));
+        assert_eq!(split_origin("about:blank"), ("", "about:blank"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let session = TestSession::new("s1").issue_tabs(200).build();
+
+        let encoded = encode_session(to_value(&session), SessionEncoding::Compressed).unwrap();
+        assert_eq!(encoded["encoding"], DICT_DEFLATE_V1);
+
+        let decoded: CollapsedSession = serde_json::from_value(decode_session(encoded).unwrap()).unwrap();
+        assert_eq!(decoded, session);
+    }
+
+    #[test]
+    fn test_round_trip_with_tab_metadata() {
+        let mut session = TestSession::new("s1").issue_tabs(40).build();
+        for (i, tab) in session.tabs.iter_mut().enumerate() {
+            tab.fav_icon_url = Some(format!("https://{}/favicon.ico", tab.domain));
+            tab.saved_at = Some(1709370900000.0);
+            tab.index = Some(i as i32);
+            tab.muted = i % 5 == 0;
+        }
+
+        let encoded = encode_session(to_value(&session), SessionEncoding::Compressed).unwrap();
+        let packed = pack(to_value(&session));
+        let decoded: CollapsedSession = serde_json::from_value(decode_session(encoded).unwrap()).unwrap();
+
+        // One favicon per site, however many tabs share it
+        assert_eq!(packed["favicons"].as_array().unwrap().len(), 4);
+        assert_eq!(decoded, session);
+    }
+
+    #[test]
+    fn test_large_session_compresses_well() {
+        let session = to_value(&TestSession::new("s1").issue_tabs(500).build());
+        let encoded = encode_session(session.clone(), SessionEncoding::Compressed).unwrap();
+
+        let mut items = Map::new();
+        items.insert(session_key("s1"), encoded);
+        let stats = measure(&items).unwrap();
+
+        assert_eq!(stats.plain_bytes, json_len(&session));
+        assert_eq!(stats.compressed_sessions, 1);
+        assert!(stats.ratio() > 4.0, "ratio was {:.2}", stats.ratio());
+    }
+
+    #[test]
+    fn test_small_session_stays_plain() {
+        let session = to_value(&TestSession::new("s1").issue_tabs(1).build());
+
+        let encoded = encode_session(session.clone(), SessionEncoding::Compressed).unwrap();
+
+        assert_eq!(encoded, session);
+    }
+
+    #[test]
+    fn test_plain_encoding_is_untouched() {
+        let session = to_value(&TestSession::new("s1").issue_tabs(50).build());
+
+        assert_eq!(encode_session(session.clone(), SessionEncoding::Plain).unwrap(), session);
+        assert_eq!(decode_session(session.clone()).unwrap(), session);
+    }
+
+    #[test]
+    fn test_unknown_encoding_is_rejected() {
+        let stored = serde_json::json!({"encoding": "zstd-v9", "data": ""});
+
+        let error = decode_session(stored).unwrap_err();
+
+        assert!(error.contains("zstd-v9"));
+    }
+
+    #[test]
+    fn test_corrupt_data_is_rejected() {
+        let stored = serde_json::json!({"encoding": DICT_DEFLATE_V1, "data": "bm90IGRlZmxhdGU="});
+
+        assert!(decode_session(stored).is_err());
+    }
+
+    #[test]
+    fn test_measure_mixed_items() {
+        let plain = to_value(&TestSession::new("s1").issue_tabs(3).build());
+        let large = to_value(&TestSession::new("s1").issue_tabs(300).build());
+        let compressed = encode_session(large, SessionEncoding::Compressed).unwrap();
+
+        let mut items = Map::new();
+        items.insert(session_key("a"), plain.clone());
+        items.insert(session_key("b"), compressed);
+        items.insert("search_query".to_string(), Value::from("ignored"));
+        let stats = measure(&items).unwrap();
+
+        assert_eq!(stats.sessions, 2);
+        assert_eq!(stats.compressed_sessions, 1);
+        assert!(stats.stored_bytes < stats.plain_bytes);
+        assert!(stats.summary().starts_with("1 of 2 sessions compressed"));
+    }
+}
@@ -0,0 +1,42 @@
+//! Typed keys for every preference and record in chrome.storage
+//!
+//! Sessions are not listed here: they live under `STORAGE_KEY` and the
+//! per-session keys, and are read through `StorageRepository::load_sessions`.
+
+use super::{Key, QuotaPolicy, SessionEncoding};
+use crate::operations::{KeepPolicy, SortPreset};
+use crate::restore::RestoreOptions;
+use crate::tab_data::PendingCollapse;
+use crate::undo::UndoJournal;
+use crate::url_normalizer::UrlNormalizer;
+
+pub const SEARCH_QUERY: Key<String> = Key::new("search_query");
+pub const SEARCH_USE_REGEX: Key<bool> = Key::new("search_use_regex");
+pub const SEARCH_CASE_INSENSITIVE: Key<bool> = Key::new("search_case_insensitive");
+
+pub const DOMAIN_INCLUDE_PRIVATE_SUFFIXES: Key<bool> = Key::new("domain_include_private_suffixes");
+
+pub const SORT_NO_DOMAIN_FIRST: Key<bool> = Key::new("sort_no_domain_first");
+pub const SORT_PRESET: Key<SortPreset> = Key::new("sort_preset");
+pub const SORT_REVERSE: Key<bool> = Key::new("sort_reverse");
+pub const SORT_GROUPS_AS_UNITS: Key<bool> = Key::new("sort_groups_as_units");
+
+/// Whether Collapse Tabs also closes the duplicate copies it does not save
+pub const COLLAPSE_CLOSE_DUPLICATES: Key<bool> = Key::new("collapse_close_duplicates");
+
+/// Duplicate-matching rules, shared by the popup and the viewer
+pub const URL_NORMALIZER: Key<UrlNormalizer> = Key::new("url_normalizer");
+pub const DEDUP_KEEP_POLICY: Key<KeepPolicy> = Key::new("dedup_keep_policy");
+
+/// How sessions are written; existing sessions keep their form until
+/// rewritten
+pub const SESSION_ENCODING: Key<SessionEncoding> = Key::new("session_encoding");
+/// What a collapse does when the new session would not fit
+pub const QUOTA_POLICY: Key<QuotaPolicy> = Key::new("quota_policy");
+
+/// Where and how the viewer reopens saved tabs
+pub const RESTORE_OPTIONS: Key<RestoreOptions> = Key::new("restore_options");
+
+pub const UNDO_JOURNAL: Key<UndoJournal> = Key::new("undo_journal");
+/// Recovery marker of the collapse in progress (null when none is)
+pub const PENDING_COLLAPSE: Key<Option<PendingCollapse>> = Key::new("pending_collapse");
@@ -0,0 +1,296 @@
+//! In-memory storage backend for native tests
+
+use super::compression::json_len;
+use super::quota::{StorageUsage, DEFAULT_QUOTA_BYTES};
+use super::{stored_revision, StorageRepository, STORAGE_KEY};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+
+/// chrome.storage stand-in holding every key in a map
+///
+/// Like chrome.storage, a write that would take the store past its quota
+/// fails and changes nothing.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    items: RefCell<Map<String, Value>>,
+    quota_bytes: usize,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        MemoryStorage {
+            items: RefCell::new(Map::new()),
+            quota_bytes: DEFAULT_QUOTA_BYTES,
+        }
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from a chrome.storage dump (key → value)
+    pub fn from_items(items: Map<String, Value>) -> Self {
+        MemoryStorage {
+            items: RefCell::new(items),
+            ..Self::default()
+        }
+    }
+
+    /// Use a smaller quota, to exercise a full store
+    pub fn with_quota(self, quota_bytes: usize) -> Self {
+        MemoryStorage { quota_bytes, ..self }
+    }
+
+    /// Copy of everything stored
+    pub fn items(&self) -> Map<String, Value> {
+        self.items.borrow().clone()
+    }
+
+    /// Store `items` in place of the current ones if they fit the quota
+    fn replace_within_quota(&self, items: Map<String, Value>) -> Result<(), String> {
+        if bytes_in_use(&items) > self.quota_bytes {
+            return Err("QUOTA_BYTES quota exceeded".to_string());
+        }
+        *self.items.borrow_mut() = items;
+        Ok(())
+    }
+}
+
+/// Counted the way chrome.storage does: key plus JSON value
+fn bytes_in_use(items: &Map<String, Value>) -> usize {
+    items.iter().map(|(key, value)| key.len() + json_len(value)).sum()
+}
+
+impl StorageRepository for MemoryStorage {
+    async fn get_raw(&self, key: &str) -> Result<Option<Value>, String> {
+        Ok(self.items.borrow().get(key).cloned())
+    }
+
+    async fn get_many_raw(&self, keys: &[String]) -> Result<Map<String, Value>, String> {
+        let items = self.items.borrow();
+        Ok(keys
+            .iter()
+            .filter_map(|key| items.get(key).map(|value| (key.clone(), value.clone())))
+            .collect())
+    }
+
+    async fn set_raw(&self, key: &str, value: Value) -> Result<(), String> {
+        let mut items = self.items();
+        items.insert(key.to_string(), value);
+        self.replace_within_quota(items)
+    }
+
+    async fn remove_raw(&self, keys: &[String]) -> Result<(), String> {
+        let mut items = self.items.borrow_mut();
+        for key in keys {
+            items.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn compare_and_set(
+        &self,
+        expected_revision: u64,
+        items: Map<String, Value>,
+        removed_keys: &[String],
+    ) -> Result<bool, String> {
+        let mut stored = self.items();
+        let root = stored.get(STORAGE_KEY).cloned().unwrap_or(Value::Null);
+        if stored_revision(&root) != expected_revision {
+            return Ok(false);
+        }
+
+        stored.extend(items);
+        for key in removed_keys {
+            stored.remove(key);
+        }
+        self.replace_within_quota(stored)?;
+        Ok(true)
+    }
+
+    async fn usage(&self) -> Result<StorageUsage, String> {
+        Ok(StorageUsage {
+            bytes_in_use: bytes_in_use(&self.items.borrow()),
+            quota_bytes: self.quota_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::KeepPolicy;
+    use crate::storage::{backup_key, keys, session_key, SessionEncoding, StorageData, SCHEMA_VERSION};
+    use crate::tab_data::SavedTab;
+    use crate::test_support::{block_on, TestSession};
+
+    fn fixture(text: &str) -> MemoryStorage {
+        MemoryStorage::from_items(serde_json::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn test_typed_keys_round_trip() {
+        let storage = MemoryStorage::new();
+
+        assert_eq!(block_on(storage.get(keys::DEDUP_KEEP_POLICY)), Ok(None));
+
+        block_on(storage.set(keys::DEDUP_KEEP_POLICY, &KeepPolicy::PreferPinned)).unwrap();
+        block_on(storage.set(keys::SEARCH_QUERY, &"docs".to_string())).unwrap();
+
+        assert_eq!(
+            block_on(storage.get(keys::DEDUP_KEEP_POLICY)),
+            Ok(Some(KeepPolicy::PreferPinned))
+        );
+        assert_eq!(block_on(storage.get(keys::SEARCH_QUERY)), Ok(Some("docs".to_string())));
+        assert_eq!(storage.items()["dedup_keep_policy"], Value::from("PreferPinned"));
+    }
+
+    #[test]
+    fn test_unreadable_value_reads_as_unset() {
+        let storage = MemoryStorage::new();
+        block_on(storage.set_raw("sort_reverse", Value::from("yes"))).unwrap();
+
+        assert_eq!(block_on(storage.get(keys::SORT_REVERSE)), Ok(None));
+    }
+
+    #[test]
+    fn test_load_sessions_migrates_and_drops_backup() {
+        let storage = fixture(include_str!("../../tests/fixtures/storage/v0.json"));
+
+        let data = block_on(storage.load_sessions()).unwrap();
+        let items = storage.items();
+
+        assert_eq!(data.version, SCHEMA_VERSION);
+        // The backup is deleted once the migrated data reads back
+        assert!(!items.contains_key(&backup_key(0)));
+        for session in &data.sessions {
+            assert!(items.contains_key(&session_key(&session.id)));
+        }
+        assert_eq!(stored_revision(&items[STORAGE_KEY]), data.revision);
+
+        // Loading again finds current data and writes nothing
+        let again = block_on(storage.load_sessions()).unwrap();
+        assert_eq!(again, data);
+        assert_eq!(storage.items(), items);
+    }
+
+    #[test]
+    fn test_migration_near_quota_skips_backup() {
+        let text = include_str!("../../tests/fixtures/storage/v1.json");
+        let expected = block_on(fixture(text).load_sessions()).unwrap();
+        let in_use = block_on(fixture(text).usage()).unwrap().bytes_in_use;
+        // Room for the migrated data, but not for a second copy beside it
+        let storage = fixture(text).with_quota(in_use * 3 / 2);
+
+        let data = block_on(storage.load_sessions()).unwrap();
+
+        assert_eq!(data, expected);
+        assert!(!storage.items().contains_key(&backup_key(1)));
+        assert!(block_on(storage.usage()).unwrap().bytes_in_use <= in_use * 3 / 2);
+    }
+
+    #[test]
+    fn test_write_past_quota_changes_nothing() {
+        let storage = MemoryStorage::new().with_quota(64);
+        block_on(storage.set_raw("small", Value::from("x"))).unwrap();
+
+        assert!(block_on(storage.set_raw("big", Value::from("x".repeat(100)))).is_err());
+        assert_eq!(storage.items().len(), 1);
+    }
+
+    #[test]
+    fn test_load_sessions_with_nothing_stored() {
+        let storage = MemoryStorage::new();
+
+        let data = block_on(storage.load_sessions()).unwrap();
+
+        assert!(data.sessions.is_empty());
+        assert!(storage.items().is_empty());
+    }
+
+    #[test]
+    fn test_update_sessions() {
+        let storage = MemoryStorage::new();
+
+        let first = TestSession::new("a").name("First").build();
+        let saved = block_on(storage.update_sessions(|data| data.add_session(first))).unwrap();
+
+        assert_eq!(saved.revision, 1);
+        assert_eq!(block_on(storage.load_sessions()).unwrap(), saved);
+    }
+
+    #[test]
+    fn test_concurrent_saves_are_merged() {
+        let storage = fixture(include_str!("../../tests/fixtures/storage/v2.json"));
+        let popup_view = block_on(storage.load_sessions()).unwrap();
+        let viewer_view = popup_view.clone();
+        let renamed_id = viewer_view.sessions[0].id.clone();
+
+        // The popup collapses tabs into a new session
+        let mut popup_edit = popup_view.clone();
+        popup_edit.add_session(TestSession::new("new").name("Collapsed").build());
+        block_on(storage.save_sessions(&popup_view, &popup_edit)).unwrap();
+
+        // The viewer renames a session, still holding the old revision
+        let mut viewer_edit = viewer_view.clone();
+        viewer_edit.update_session_name(&renamed_id, "Renamed".to_string());
+        let saved = block_on(storage.save_sessions(&viewer_view, &viewer_edit)).unwrap();
+
+        let stored: StorageData = block_on(storage.load_sessions()).unwrap();
+        assert_eq!(stored, saved);
+        assert_eq!(stored.sessions.len(), popup_view.sessions.len() + 1);
+        assert!(stored.sessions.iter().any(|s| s.id == "new"));
+        assert!(stored.sessions.iter().any(|s| s.id == renamed_id && s.name == "Renamed"));
+    }
+
+    #[test]
+    fn test_stale_compare_and_set_is_rejected() {
+        let storage = fixture(include_str!("../../tests/fixtures/storage/v2.json"));
+        let data = block_on(storage.load_sessions()).unwrap();
+
+        let mut edit = data.clone();
+        edit.remove_session(&data.sessions[0].id);
+        let write = edit.write_since(&data).unwrap();
+
+        assert_eq!(block_on(storage.apply(&write)), Ok(true));
+        assert_eq!(block_on(storage.apply(&write)), Ok(false));
+    }
+
+    #[test]
+    fn test_compressed_sessions_load_transparently() {
+        let storage = fixture(include_str!("../../tests/fixtures/storage/v2.json"));
+        let plain = block_on(storage.load_sessions()).unwrap();
+        let mut big = TestSession::new("big").name("Big").build();
+        big.tabs = (0..200)
+            .map(|i| SavedTab {
+                url: format!("https://github.com/rust-lang/rust/issues/{}", i),
+                title: format!("Issue #{}", i),
+                domain: "github.com".to_string(),
+                pinned: false,
+                ..SavedTab::default()
+            })
+            .collect();
+
+        block_on(storage.set(keys::SESSION_ENCODING, &SessionEncoding::Compressed)).unwrap();
+        let saved = block_on(storage.update_sessions(|data| data.add_session(big))).unwrap();
+
+        // Only the new session was written, and it was written compressed
+        let items = storage.items();
+        assert!(items[&session_key("big")].get("encoding").is_some());
+        assert!(items[&session_key(&plain.sessions[0].id)].get("encoding").is_none());
+        assert_eq!(block_on(storage.load_sessions()).unwrap(), saved);
+
+        let stats = block_on(storage.compression_stats()).unwrap();
+        assert_eq!(stats.sessions, 3);
+        assert_eq!(stats.compressed_sessions, 1);
+        assert!(stats.ratio() > 1.0);
+
+        // Turning compression off and rewriting restores plain JSON
+        block_on(storage.set(keys::SESSION_ENCODING, &SessionEncoding::Plain)).unwrap();
+        let rewritten = block_on(storage.rewrite_sessions()).unwrap();
+        assert_eq!(rewritten.sessions, saved.sessions);
+        assert_eq!(block_on(storage.compression_stats()).unwrap().compressed_sessions, 0);
+    }
+}
@@ -0,0 +1,512 @@
+//! Storage quota: where the space goes, and making room for a collapse
+//!
+//! chrome.storage.local charges every key for its name plus its value's
+//! JSON, against a fixed quota (10 MB without `unlimitedStorage`).
+//! `QuotaReport` breaks the stored sessions down by session and by domain;
+//! `plan_room` predicts whether a new session fits before any tab is
+//! closed and, when it does not, applies the user's `QuotaPolicy`.
+
+use super::compression::{encode_session, json_len, SessionEncoding};
+use super::{session_key, StorageData};
+use crate::tab_data::CollapsedSession;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// chrome.storage.local quota when the browser does not report one
+pub const DEFAULT_QUOTA_BYTES: usize = 10 * 1024 * 1024;
+
+/// Share of the quota (in percent) a collapse may fill before the
+/// `QuotaPolicy` applies; the rest is headroom for preferences and the
+/// undo journal
+pub const NEARLY_FULL_PERCENT: usize = 90;
+
+/// Bytes in use and the quota, as chrome.storage reports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub bytes_in_use: usize,
+    pub quota_bytes: usize,
+}
+
+impl StorageUsage {
+    pub fn percent_used(&self) -> usize {
+        if self.quota_bytes == 0 {
+            100
+        } else {
+            (self.bytes_in_use * 100).div_ceil(self.quota_bytes)
+        }
+    }
+
+    /// Bytes sessions may fill up to
+    pub fn limit(&self) -> usize {
+        self.quota_bytes / 100 * NEARLY_FULL_PERCENT
+    }
+
+    /// Bytes left before `limit`
+    pub fn available(&self) -> usize {
+        self.limit().saturating_sub(self.bytes_in_use)
+    }
+
+    pub fn nearly_full(&self) -> bool {
+        self.bytes_in_use >= self.limit()
+    }
+
+    /// e.g. "2.1 MB of 10.0 MB used (21%)"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} used ({}%)",
+            format_bytes(self.bytes_in_use),
+            format_bytes(self.quota_bytes),
+            self.percent_used()
+        )
+    }
+}
+
+/// What a collapse does when the new session would not fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum QuotaPolicy {
+    /// Leave every tab open and report the shortfall
+    #[default]
+    Refuse,
+    /// Download the oldest sessions as a file, then delete them
+    ArchiveOldest,
+    /// Delete saved tabs whose exact URL is also saved in a newer session
+    DropDuplicates,
+}
+
+impl QuotaPolicy {
+    /// Policies offered in the Archive pane
+    pub const CHOICES: [QuotaPolicy; 3] = [
+        QuotaPolicy::Refuse,
+        QuotaPolicy::ArchiveOldest,
+        QuotaPolicy::DropDuplicates,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            QuotaPolicy::Refuse => "When full: refuse to collapse",
+            QuotaPolicy::ArchiveOldest => "When full: archive oldest sessions to a file",
+            QuotaPolicy::DropDuplicates => "When full: drop tabs saved twice",
+        }
+    }
+}
+
+/// Space one stored session takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionUsage {
+    pub id: String,
+    pub name: String,
+    pub tabs: usize,
+    pub bytes: usize,
+}
+
+/// Space the saved tabs of one domain take, across every session
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainUsage {
+    pub domain: String,
+    pub tabs: usize,
+    pub bytes: usize,
+}
+
+/// Where the stored space goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaReport {
+    pub usage: StorageUsage,
+    /// Largest first
+    pub sessions: Vec<SessionUsage>,
+    /// Largest first
+    pub domains: Vec<DomainUsage>,
+}
+
+impl QuotaReport {
+    /// Break `data`'s sessions down by session and by domain
+    ///
+    /// `items` holds the stored session keys, so compressed sessions are
+    /// measured at their stored size. A compressed session's bytes are
+    /// shared among its domains in proportion to their plain size.
+    pub fn new(usage: StorageUsage, data: &StorageData, items: &Map<String, Value>) -> Result<QuotaReport, String> {
+        let mut sessions = Vec::new();
+        let mut domains: HashMap<&str, (usize, f64)> = HashMap::new();
+
+        for session in &data.sessions {
+            let bytes = match items.get(&session_key(&session.id)) {
+                Some(stored) => session_bytes(&session.id, stored),
+                None => stored_session_size(session, SessionEncoding::Plain)?,
+            };
+            sessions.push(SessionUsage {
+                id: session.id.clone(),
+                name: session.name.clone(),
+                tabs: session.tabs.len(),
+                bytes,
+            });
+
+            let tab_sizes: Vec<usize> = session
+                .tabs
+                .iter()
+                .map(|tab| serde_json::to_value(tab).map(|value| json_len(&value)).unwrap_or(0))
+                .collect();
+            let plain_total = tab_sizes.iter().sum::<usize>().max(1) as f64;
+            for (tab, size) in session.tabs.iter().zip(tab_sizes) {
+                let entry = domains.entry(&tab.domain).or_default();
+                entry.0 += 1;
+                entry.1 += bytes as f64 * size as f64 / plain_total;
+            }
+        }
+
+        let mut domains: Vec<DomainUsage> = domains
+            .into_iter()
+            .map(|(domain, (tabs, bytes))| DomainUsage {
+                domain: domain.to_string(),
+                tabs,
+                bytes: bytes.round() as usize,
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.bytes));
+        domains.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.domain.cmp(&b.domain)));
+
+        Ok(QuotaReport { usage, sessions, domains })
+    }
+
+    /// Stored size of one session (0 if it is not in the report)
+    pub fn session_bytes(&self, session_id: &str) -> usize {
+        self.sessions
+            .iter()
+            .find(|session| session.id == session_id)
+            .map(|session| session.bytes)
+            .unwrap_or(0)
+    }
+}
+
+/// How a new session will be made to fit
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomPlan {
+    /// There is room already
+    Fits,
+    /// Export these sessions to a file, then delete them
+    Archive(Vec<CollapsedSession>),
+    /// Delete `tabs` saved tabs that a newer session also holds
+    DropDuplicates { tabs: usize },
+}
+
+impl RoomPlan {
+    /// Make the room in `data`, before `incoming` is added
+    pub fn apply(&self, data: &mut StorageData, incoming: &CollapsedSession) {
+        match self {
+            RoomPlan::Fits => {}
+            RoomPlan::Archive(sessions) => {
+                for session in sessions {
+                    data.remove_session(&session.id);
+                }
+            }
+            RoomPlan::DropDuplicates { .. } => {
+                data.drop_saved_duplicates(incoming);
+            }
+        }
+    }
+
+    /// One line for the plan review, e.g. "Will archive 2 oldest sessions"
+    pub fn describe(&self) -> String {
+        match self {
+            RoomPlan::Fits => "Fits in storage".to_string(),
+            RoomPlan::Archive(sessions) => format!(
+                "Storage is nearly full: will archive {} oldest session{} to a file",
+                sessions.len(),
+                if sessions.len() == 1 { "" } else { "s" }
+            ),
+            RoomPlan::DropDuplicates { tabs } => format!(
+                "Storage is nearly full: will drop {} tab{} already saved in a newer session",
+                tabs,
+                if *tabs == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+/// Predict whether `incoming` fits and, if not, how `policy` makes room
+///
+/// Algorithm:
+/// 1. Size `incoming` as it will be stored (key, encoded value and index
+///    entry); if that is within `usage.available()`, it fits
+/// 2. Refuse: fail with the shortfall
+/// 3. ArchiveOldest: take sessions oldest first until they free the
+///    shortfall
+/// 4. DropDuplicates: drop repeated URLs, keeping the newest copy, and
+///    re-measure the sessions that changed
+///
+/// Fails when the policy cannot free enough, so no tab is closed for a
+/// session that would not be saved.
+pub fn plan_room(
+    report: &QuotaReport,
+    data: &StorageData,
+    incoming: &CollapsedSession,
+    encoding: SessionEncoding,
+    policy: QuotaPolicy,
+) -> Result<RoomPlan, String> {
+    let needed = stored_session_size(incoming, encoding)?;
+    let available = report.usage.available();
+    if needed <= available {
+        return Ok(RoomPlan::Fits);
+    }
+    let shortfall = needed - available;
+    let not_enough = |remedy: &str| {
+        format!(
+            "Not enough storage to collapse{}: the session needs {} but only {} is free. \
+             Export and delete old sessions, or turn on compression in the collapsed viewer.",
+            remedy,
+            format_bytes(needed),
+            format_bytes(available)
+        )
+    };
+
+    match policy {
+        QuotaPolicy::Refuse => Err(not_enough("")),
+        QuotaPolicy::ArchiveOldest => {
+            let mut oldest: Vec<&CollapsedSession> = data.sessions.iter().collect();
+            oldest.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+            let mut freed = 0;
+            let mut archived = Vec::new();
+            for session in oldest {
+                if freed >= shortfall {
+                    break;
+                }
+                freed += report.session_bytes(&session.id);
+                archived.push(session.clone());
+            }
+
+            if freed < shortfall {
+                return Err(not_enough(", even after archiving every session"));
+            }
+            Ok(RoomPlan::Archive(archived))
+        }
+        QuotaPolicy::DropDuplicates => {
+            let mut pruned = data.clone();
+            let tabs = pruned.drop_saved_duplicates(incoming);
+
+            let mut freed = 0;
+            for session in &data.sessions {
+                let before = report.session_bytes(&session.id);
+                match pruned.sessions.iter().find(|s| s.id == session.id) {
+                    None => freed += before,
+                    Some(after) if after != session => {
+                        freed += before.saturating_sub(stored_session_size(after, encoding)?);
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if freed < shortfall {
+                return Err(not_enough(", even after dropping duplicate tabs"));
+            }
+            Ok(RoomPlan::DropDuplicates { tabs })
+        }
+    }
+}
+
+/// Bytes `session` takes once stored as `encoding`
+pub fn stored_session_size(session: &CollapsedSession, encoding: SessionEncoding) -> Result<usize, String> {
+    let value = serde_json::to_value(session).map_err(|e| format!("Failed to serialize session: {:?}", e))?;
+    Ok(session_bytes(&session.id, &encode_session(value, encoding)?))
+}
+
+/// A session's key and stored value, plus its entry in the index
+fn session_bytes(session_id: &str, stored: &Value) -> usize {
+    // `"<id>",` in the index's session_ids
+    let index_entry = session_id.len() + 3;
+    session_key(session_id).len() + json_len(stored) + index_entry
+}
+
+/// e.g. "512 B", "96 KB", "2.1 MB"
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.0} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestSession;
+
+    fn create_test_data() -> StorageData {
+        let mut data = StorageData::new();
+        data.add_session(
+            TestSession::new("old")
+                .timestamp(1000.0)
+                .urls(&["https://docs.rs/serde", "https://github.com/serde-rs/serde", "https://docs.rs/tokio"])
+                .build(),
+        );
+        data.add_session(
+            TestSession::new("mid")
+                .timestamp(2000.0)
+                .urls(&["https://github.com/rust-lang/rust"])
+                .build(),
+        );
+        data.add_session(TestSession::new("new").timestamp(3000.0).urls(&["https://docs.rs/serde"]).build());
+        data
+    }
+
+    /// A report on `data` with `available` bytes left before the limit
+    fn create_test_report(data: &StorageData, available: usize) -> QuotaReport {
+        let usage = usage_with(900_000 - available, 1_000_000);
+        QuotaReport::new(usage, data, &Map::new()).unwrap()
+    }
+
+    fn usage_with(bytes_in_use: usize, quota_bytes: usize) -> StorageUsage {
+        StorageUsage {
+            bytes_in_use,
+            quota_bytes,
+        }
+    }
+
+    #[test]
+    fn test_usage_limits() {
+        let usage = usage_with(8_000, 10_000);
+
+        assert_eq!(usage.percent_used(), 80);
+        assert_eq!(usage.limit(), 9_000);
+        assert_eq!(usage.available(), 1_000);
+        assert!(!usage.nearly_full());
+        assert!(usage_with(9_500, 10_000).nearly_full());
+        assert_eq!(usage_with(9_500, 10_000).available(), 0);
+    }
+
+    #[test]
+    fn test_report_breaks_down_by_session_and_domain() {
+        let data = create_test_data();
+
+        let report = QuotaReport::new(usage_with(0, DEFAULT_QUOTA_BYTES), &data, &Map::new()).unwrap();
+
+        assert_eq!(report.sessions[0].id, "old");
+        assert_eq!(report.sessions[0].tabs, 3);
+        let session_total: usize = report.sessions.iter().map(|s| s.bytes).sum();
+        let domain_total: usize = report.domains.iter().map(|d| d.bytes).sum();
+        assert!(session_total.abs_diff(domain_total) <= report.domains.len());
+
+        let docs = report.domains.iter().find(|d| d.domain == "docs.rs").unwrap();
+        assert_eq!(docs.tabs, 3);
+        assert_eq!(report.domains[0].domain, "docs.rs");
+    }
+
+    #[test]
+    fn test_report_measures_compressed_sessions_as_stored() {
+        let urls: Vec<String> = (0..200).map(|i| format!("https://docs.rs/crate-{}/latest", i)).collect();
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+        let session = TestSession::new("big").timestamp(1000.0).urls(&urls).build();
+        let mut data = StorageData::new();
+        data.add_session(session.clone());
+        let mut items = Map::new();
+        let value = serde_json::to_value(&session).unwrap();
+        items.insert(session_key("big"), encode_session(value, SessionEncoding::Compressed).unwrap());
+
+        let report = QuotaReport::new(usage_with(0, DEFAULT_QUOTA_BYTES), &data, &items).unwrap();
+
+        let plain = stored_session_size(&session, SessionEncoding::Plain).unwrap();
+        assert!(report.session_bytes("big") < plain / 4);
+        assert!(report.domains[0].bytes.abs_diff(report.session_bytes("big")) <= 1);
+    }
+
+    #[test]
+    fn test_session_that_fits() {
+        let data = create_test_data();
+        let report = create_test_report(&data, 10_000);
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&["https://example.com/"]).build();
+
+        for policy in QuotaPolicy::CHOICES {
+            assert_eq!(
+                plan_room(&report, &data, &incoming, SessionEncoding::Plain, policy),
+                Ok(RoomPlan::Fits)
+            );
+        }
+    }
+
+    #[test]
+    fn test_refuse_reports_shortfall() {
+        let data = create_test_data();
+        let report = create_test_report(&data, 10);
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&["https://example.com/"]).build();
+
+        let error = plan_room(&report, &data, &incoming, SessionEncoding::Plain, QuotaPolicy::Refuse).unwrap_err();
+
+        assert!(error.starts_with("Not enough storage to collapse: the session needs"), "{}", error);
+        assert!(error.contains("only 10 B is free"), "{}", error);
+    }
+
+    #[test]
+    fn test_archive_oldest_takes_only_what_is_needed() {
+        let data = create_test_data();
+        let report = create_test_report(&data, 0);
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&["https://example.com/"]).build();
+
+        let plan = plan_room(&report, &data, &incoming, SessionEncoding::Plain, QuotaPolicy::ArchiveOldest).unwrap();
+
+        // The oldest session is the largest, so it alone makes room
+        let RoomPlan::Archive(sessions) = &plan else {
+            panic!("expected an archive plan, got {:?}", plan);
+        };
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "old");
+
+        let mut updated = data.clone();
+        plan.apply(&mut updated, &incoming);
+        let ids: Vec<&str> = updated.sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["mid", "new"]);
+    }
+
+    #[test]
+    fn test_archive_oldest_fails_when_everything_is_not_enough() {
+        let data = create_test_data();
+        let report = create_test_report(&data, 0);
+        let urls: Vec<String> = (0..100).map(|i| format!("https://example.com/{}", i)).collect();
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&urls).build();
+
+        let error =
+            plan_room(&report, &data, &incoming, SessionEncoding::Plain, QuotaPolicy::ArchiveOldest).unwrap_err();
+
+        assert!(error.contains("even after archiving every session"), "{}", error);
+    }
+
+    #[test]
+    fn test_drop_duplicates_keeps_newest_copy() {
+        let data = create_test_data();
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&["https://github.com/rust-lang/rust"]).build();
+        // Dropping the two repeated URLs frees more than one tab's worth
+        let report = create_test_report(&data, 0);
+
+        let plan = plan_room(&report, &data, &incoming, SessionEncoding::Plain, QuotaPolicy::DropDuplicates).unwrap();
+        assert_eq!(plan, RoomPlan::DropDuplicates { tabs: 2 });
+
+        let mut updated = data.clone();
+        plan.apply(&mut updated, &incoming);
+        // "mid" only held a URL the new session saves again, so it goes
+        let ids: Vec<&str> = updated.sessions.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["old", "new"]);
+        let old_urls: Vec<&str> = updated.sessions[0].tabs.iter().map(|t| t.url.as_str()).collect();
+        assert_eq!(old_urls, vec!["https://github.com/serde-rs/serde", "https://docs.rs/tokio"]);
+    }
+
+    #[test]
+    fn test_drop_duplicates_fails_without_duplicates() {
+        let mut data = StorageData::new();
+        data.add_session(TestSession::new("a").timestamp(1000.0).urls(&["https://docs.rs/serde"]).build());
+        let report = create_test_report(&data, 0);
+        let incoming = TestSession::new("x").timestamp(4000.0).urls(&["https://example.com/"]).build();
+
+        let error =
+            plan_room(&report, &data, &incoming, SessionEncoding::Plain, QuotaPolicy::DropDuplicates).unwrap_err();
+
+        assert!(error.contains("even after dropping duplicate tabs"), "{}", error);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(96 * 1024), "96 KB");
+        assert_eq!(format_bytes(2_202_010), "2.1 MB");
+    }
+}
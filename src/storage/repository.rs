@@ -0,0 +1,228 @@
+//! Storage repository: typed keys over a key-value backend
+//!
+//! Implementations only provide raw JSON access and the compare-and-swap
+//! write; loading, migrating and saving sessions are shared provided
+//! methods, so they behave the same against chrome.storage and the
+//! in-memory store used by tests.
+
+use super::compression::{self, json_len, CompressionStats};
+use super::quota::{QuotaReport, StorageUsage};
+use super::{backup_key, keys, session_keys_for, LoadedStorage, StorageData, StorageWrite, MAX_WRITE_ATTEMPTS, STORAGE_KEY};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::marker::PhantomData;
+
+/// A storage key and the type stored under it
+pub struct Key<T> {
+    pub name: &'static str,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Key<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Key {
+            name,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Key<T> {}
+
+/// Key-value store holding Tab Hoarder's data
+pub trait StorageRepository {
+    /// Value under `key`, or `None` if unset
+    async fn get_raw(&self, key: &str) -> Result<Option<Value>, String>;
+
+    /// Values under `keys`; unset keys are absent from the result
+    async fn get_many_raw(&self, keys: &[String]) -> Result<Map<String, Value>, String>;
+
+    async fn set_raw(&self, key: &str, value: Value) -> Result<(), String>;
+
+    async fn remove_raw(&self, keys: &[String]) -> Result<(), String>;
+
+    /// Set `items` and remove `removed_keys` only if the index under
+    /// `STORAGE_KEY` is still at `expected_revision`; false otherwise
+    async fn compare_and_set(
+        &self,
+        expected_revision: u64,
+        items: Map<String, Value>,
+        removed_keys: &[String],
+    ) -> Result<bool, String>;
+
+    /// Bytes in use across every key, and the quota they count against
+    async fn usage(&self) -> Result<StorageUsage, String>;
+
+    /// Typed value under `key`, or `None` if unset or unreadable
+    async fn get<T: DeserializeOwned>(&self, key: Key<T>) -> Result<Option<T>, String> {
+        Ok(self
+            .get_raw(key.name)
+            .await?
+            .and_then(|value| serde_json::from_value(value).ok()))
+    }
+
+    async fn set<T: Serialize>(&self, key: Key<T>, value: &T) -> Result<(), String> {
+        let value = serde_json::to_value(value)
+            .map_err(|e| format!("Failed to serialize {}: {:?}", key.name, e))?;
+        self.set_raw(key.name, value).await
+    }
+
+    /// Load the sessions, migrating older data in place
+    ///
+    /// Algorithm:
+    /// 1. Read the index, then the session keys it names
+    /// 2. Upgrade with `StorageData::from_stored`
+    /// 3. If a migration ran, back up the original data (when both copies
+    ///    fit below the quota limit) and write the upgraded data in full;
+    ///    if another page migrated first, reload
+    /// 4. Once the upgraded data reads back unchanged, delete the backup
+    async fn load_sessions(&self) -> Result<StorageData, String> {
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            let loaded = self.read_stored().await?;
+            for session_id in &loaded.missing_sessions {
+                log::warn!("Saved session {} is missing from storage", session_id);
+            }
+
+            let Some(backup) = &loaded.backup else {
+                return Ok(loaded.data);
+            };
+
+            // Keep the data as an older build wrote it while it is replaced,
+            // unless the copy would crowd out the upgraded data
+            let key = backup_key(loaded.from_version);
+            let usage = self.usage().await?;
+            let backed_up = if usage.bytes_in_use + key.len() + json_len(backup) <= usage.limit() {
+                self.set_raw(&key, backup.clone())
+                    .await
+                    .inspect_err(|e| log::warn!("Could not back up version {} data: {}", loaded.from_version, e))
+                    .is_ok()
+            } else {
+                log::warn!("No room to back up version {} data before migrating it", loaded.from_version);
+                false
+            };
+
+            let write = loaded.data.full_write();
+            let applied = self.apply(&write).await;
+            if applied != Ok(true) {
+                // The original data is still what is stored
+                if backed_up {
+                    self.remove_raw(&[key]).await?;
+                }
+                applied?;
+                continue;
+            }
+
+            let mut data = loaded.data;
+            data.revision = write.index.revision;
+            let stored = self.read_stored().await?;
+            if stored.backup.is_none() && stored.data == data {
+                if backed_up {
+                    self.remove_raw(&[key]).await?;
+                }
+            } else {
+                log::warn!("Migrated data did not read back unchanged; keeping the backup");
+            }
+            return Ok(data);
+        }
+
+        Err("Storage is busy, please try again".to_string())
+    }
+
+    /// The stored data, upgraded but not written back
+    async fn read_stored(&self) -> Result<LoadedStorage, String> {
+        let root = self.get_raw(STORAGE_KEY).await?.unwrap_or(Value::Null);
+        let keys = session_keys_for(&root);
+        let items = if keys.is_empty() {
+            Map::new()
+        } else {
+            self.get_many_raw(&keys).await?
+        };
+        StorageData::from_stored(root, &items)
+    }
+
+    /// Write `storage` over `previous` with compare-and-swap
+    ///
+    /// If another page wrote in between, reload, merge our edits into its
+    /// data and retry. Returns what ended up stored.
+    async fn save_sessions(&self, previous: &StorageData, storage: &StorageData) -> Result<StorageData, String> {
+        let mut previous = previous.clone();
+        let mut storage = storage.clone();
+
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            let Some(write) = storage.write_since(&previous) else {
+                return Ok(storage);
+            };
+            if self.apply(&write).await? {
+                storage.revision = write.index.revision;
+                return Ok(storage);
+            }
+
+            let current = self.load_sessions().await?;
+            storage = StorageData::merge(&previous, &storage, &current);
+            previous = current;
+        }
+
+        Err("Storage is busy, please try again".to_string())
+    }
+
+    /// Load, edit and save the sessions in one step
+    async fn update_sessions(&self, edit: impl FnOnce(&mut StorageData)) -> Result<StorageData, String> {
+        let previous = self.load_sessions().await?;
+        let mut storage = previous.clone();
+        edit(&mut storage);
+        self.save_sessions(&previous, &storage).await
+    }
+
+    /// Rewrite every session in the current `SESSION_ENCODING`, e.g. after
+    /// turning compression on or off
+    async fn rewrite_sessions(&self) -> Result<StorageData, String> {
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            let mut data = self.load_sessions().await?;
+            let write = data.full_write();
+            if self.apply(&write).await? {
+                data.revision = write.index.revision;
+                return Ok(data);
+            }
+        }
+
+        Err("Storage is busy, please try again".to_string())
+    }
+
+    /// Space the stored sessions take, compressed and plain
+    async fn compression_stats(&self) -> Result<CompressionStats, String> {
+        let root = self.get_raw(STORAGE_KEY).await?.unwrap_or(Value::Null);
+        let keys = session_keys_for(&root);
+        if keys.is_empty() {
+            return Ok(CompressionStats::default());
+        }
+        compression::measure(&self.get_many_raw(&keys).await?)
+    }
+
+    /// Where the space goes, for `data` as loaded by `load_sessions`
+    async fn quota_report(&self, data: &StorageData) -> Result<QuotaReport, String> {
+        let root = self.get_raw(STORAGE_KEY).await?.unwrap_or(Value::Null);
+        let keys = session_keys_for(&root);
+        let items = if keys.is_empty() {
+            Map::new()
+        } else {
+            self.get_many_raw(&keys).await?
+        };
+        QuotaReport::new(self.usage().await?, data, &items)
+    }
+
+    /// Apply a write; false if the stored revision no longer matches
+    ///
+    /// Sessions are encoded as the `SESSION_ENCODING` preference says.
+    async fn apply(&self, write: &StorageWrite) -> Result<bool, String> {
+        let encoding = self.get(keys::SESSION_ENCODING).await?.unwrap_or_default();
+        self.compare_and_set(write.expected_revision, write.items(encoding)?, &write.removed_keys)
+            .await
+    }
+}
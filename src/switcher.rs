@@ -0,0 +1,266 @@
+//! Keyboard control of the popup's search results
+//!
+//! The search box keeps focus while the arrow and page keys move a cursor
+//! through the results. Shift with one of them (or a click) selects the
+//! range from where the selection started; Enter activates the tab under
+//! the cursor, Ctrl+Enter moves the targets to a new window and Delete
+//! closes them. The targets are the selected tabs, or the tab under the
+//! cursor when nothing is selected. Row checkboxes and "select all
+//! matching" change the selection too, for the bulk actions.
+
+/// A key the switcher handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitcherKey {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    /// Ctrl+Enter (Cmd+Enter on macOS)
+    OpenInNewWindow,
+    Delete,
+    Escape,
+}
+
+impl SwitcherKey {
+    /// Map a `KeyboardEvent.key`; `ctrl` is Ctrl or Cmd being held
+    pub fn from_key(key: &str, ctrl: bool) -> Option<Self> {
+        match key {
+            "ArrowUp" => Some(SwitcherKey::Up),
+            "ArrowDown" => Some(SwitcherKey::Down),
+            "PageUp" => Some(SwitcherKey::PageUp),
+            "PageDown" => Some(SwitcherKey::PageDown),
+            "Enter" if ctrl => Some(SwitcherKey::OpenInNewWindow),
+            "Enter" => Some(SwitcherKey::Enter),
+            "Delete" => Some(SwitcherKey::Delete),
+            "Escape" => Some(SwitcherKey::Escape),
+            _ => None,
+        }
+    }
+}
+
+/// What the popup should do after a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitcherCommand {
+    /// Only the cursor or selection changed
+    Moved,
+    /// Nothing to do; let the browser handle the key
+    Ignored,
+    Activate(i32),
+    MoveToNewWindow(Vec<i32>),
+    Close(Vec<i32>),
+}
+
+/// Rows PageUp and PageDown move by
+const PAGE: usize = 10;
+
+/// Cursor and selection over the current results
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Switcher {
+    /// Position of the cursor in the results
+    pub cursor: usize,
+    /// Where a Shift selection started
+    anchor: Option<usize>,
+    /// Selected tab IDs
+    selected: Vec<i32>,
+}
+
+impl Switcher {
+    /// Start over, e.g. when the results change
+    pub fn reset(&mut self) {
+        *self = Switcher::default();
+    }
+
+    pub fn is_selected(&self, tab_id: i32) -> bool {
+        self.selected.contains(&tab_id)
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selected.len()
+    }
+
+    /// Whether every one of `results` is selected
+    pub fn all_selected(&self, results: &[i32]) -> bool {
+        !results.is_empty() && results.iter().all(|id| self.is_selected(*id))
+    }
+
+    /// Select or unselect one tab (its checkbox)
+    pub fn toggle(&mut self, tab_id: i32) {
+        self.anchor = None;
+        if let Some(pos) = self.selected.iter().position(|id| *id == tab_id) {
+            self.selected.remove(pos);
+        } else {
+            self.selected.push(tab_id);
+        }
+    }
+
+    /// Select every result, or nothing
+    pub fn select_all(&mut self, results: &[i32], selected: bool) {
+        self.anchor = None;
+        self.selected = if selected { results.to_vec() } else { Vec::new() };
+    }
+
+    /// Tabs an action applies to, in result order
+    pub fn targets(&self, results: &[i32]) -> Vec<i32> {
+        if self.selected.is_empty() {
+            results.get(self.cursor).copied().into_iter().collect()
+        } else {
+            results.iter().copied().filter(|id| self.is_selected(*id)).collect()
+        }
+    }
+
+    /// Handle a key over `results` (tab IDs, in displayed order)
+    pub fn key(&mut self, key: SwitcherKey, shift: bool, results: &[i32]) -> SwitcherCommand {
+        let last = results.len().saturating_sub(1);
+        self.cursor = self.cursor.min(last);
+
+        let target = match key {
+            SwitcherKey::Up => self.cursor.saturating_sub(1),
+            SwitcherKey::Down => (self.cursor + 1).min(last),
+            SwitcherKey::PageUp => self.cursor.saturating_sub(PAGE),
+            SwitcherKey::PageDown => (self.cursor + PAGE).min(last),
+            SwitcherKey::Escape if !self.selected.is_empty() => {
+                self.anchor = None;
+                self.selected.clear();
+                return SwitcherCommand::Moved;
+            }
+            SwitcherKey::Escape => return SwitcherCommand::Ignored,
+            SwitcherKey::Enter => {
+                return match results.get(self.cursor) {
+                    Some(&tab_id) => SwitcherCommand::Activate(tab_id),
+                    None => SwitcherCommand::Ignored,
+                };
+            }
+            SwitcherKey::OpenInNewWindow | SwitcherKey::Delete => {
+                let targets = self.targets(results);
+                if targets.is_empty() {
+                    return SwitcherCommand::Ignored;
+                }
+                self.anchor = None;
+                self.selected.clear();
+                return if key == SwitcherKey::Delete {
+                    SwitcherCommand::Close(targets)
+                } else {
+                    SwitcherCommand::MoveToNewWindow(targets)
+                };
+            }
+        };
+
+        if results.is_empty() {
+            return SwitcherCommand::Ignored;
+        }
+        self.move_to(target, shift, results);
+        SwitcherCommand::Moved
+    }
+
+    /// Put the cursor on row `index`; with `shift`, select the rows from
+    /// the anchor to it, otherwise clear the selection
+    pub fn move_to(&mut self, index: usize, shift: bool, results: &[i32]) {
+        let index = index.min(results.len().saturating_sub(1));
+        if shift {
+            let anchor = *self.anchor.get_or_insert(self.cursor);
+            let (from, to) = if anchor <= index { (anchor, index) } else { (index, anchor) };
+            self.selected = results.get(from..=to).unwrap_or_default().to_vec();
+        } else {
+            self.anchor = None;
+            self.selected.clear();
+        }
+        self.cursor = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULTS: [i32; 5] = [10, 20, 30, 40, 50];
+
+    #[test]
+    fn test_cursor_moves_within_results() {
+        let mut switcher = Switcher::default();
+
+        assert_eq!(switcher.key(SwitcherKey::Up, false, &RESULTS), SwitcherCommand::Moved);
+        assert_eq!(switcher.cursor, 0);
+        switcher.key(SwitcherKey::Down, false, &RESULTS);
+        switcher.key(SwitcherKey::Down, false, &RESULTS);
+        assert_eq!(switcher.key(SwitcherKey::Enter, false, &RESULTS), SwitcherCommand::Activate(30));
+        switcher.key(SwitcherKey::PageUp, false, &RESULTS);
+        assert_eq!(switcher.cursor, 0);
+
+        // Results shrank under the cursor
+        switcher.key(SwitcherKey::PageDown, false, &RESULTS);
+        assert_eq!(switcher.cursor, 4);
+        assert_eq!(switcher.key(SwitcherKey::Enter, false, &RESULTS[..2]), SwitcherCommand::Activate(20));
+        assert_eq!(switcher.key(SwitcherKey::Enter, false, &[]), SwitcherCommand::Ignored);
+    }
+
+    #[test]
+    fn test_shift_selects_a_range() {
+        let mut switcher = Switcher::default();
+        switcher.key(SwitcherKey::Down, false, &RESULTS);
+
+        switcher.key(SwitcherKey::Down, true, &RESULTS);
+        switcher.key(SwitcherKey::Down, true, &RESULTS);
+        assert_eq!(switcher.targets(&RESULTS), vec![20, 30, 40]);
+
+        // Moving back past the anchor selects the other side
+        switcher.key(SwitcherKey::PageUp, true, &RESULTS);
+        assert_eq!(switcher.targets(&RESULTS), vec![10, 20]);
+        assert!(switcher.is_selected(10) && !switcher.is_selected(30));
+
+        // Moving without Shift clears the selection
+        switcher.key(SwitcherKey::Down, false, &RESULTS);
+        assert_eq!(switcher.selected_count(), 0);
+        assert_eq!(switcher.targets(&RESULTS), vec![20]);
+    }
+
+    #[test]
+    fn test_actions_use_selection_or_cursor() {
+        let mut switcher = Switcher::default();
+        switcher.key(SwitcherKey::PageDown, false, &RESULTS);
+        assert_eq!(switcher.key(SwitcherKey::Delete, false, &RESULTS), SwitcherCommand::Close(vec![50]));
+
+        switcher.move_to(1, false, &RESULTS);
+        switcher.move_to(2, true, &RESULTS);
+        assert_eq!(
+            switcher.key(SwitcherKey::OpenInNewWindow, false, &RESULTS),
+            SwitcherCommand::MoveToNewWindow(vec![20, 30])
+        );
+        assert_eq!(switcher.selected_count(), 0);
+    }
+
+    #[test]
+    fn test_checkboxes_and_select_all() {
+        let mut switcher = Switcher::default();
+        switcher.toggle(40);
+        switcher.toggle(20);
+        assert_eq!(switcher.targets(&RESULTS), vec![20, 40]);
+        switcher.toggle(40);
+        assert_eq!(switcher.targets(&RESULTS), vec![20]);
+
+        switcher.select_all(&RESULTS, true);
+        assert!(switcher.all_selected(&RESULTS));
+        // Tabs no longer in the results are not targets
+        assert_eq!(switcher.targets(&RESULTS[1..3]), vec![20, 30]);
+        switcher.select_all(&RESULTS, false);
+        assert_eq!(switcher.selected_count(), 0);
+        assert!(!switcher.all_selected(&[]));
+    }
+
+    #[test]
+    fn test_escape_clears_selection_first() {
+        let mut switcher = Switcher::default();
+        switcher.key(SwitcherKey::Down, true, &RESULTS);
+
+        assert_eq!(switcher.key(SwitcherKey::Escape, false, &RESULTS), SwitcherCommand::Moved);
+        assert_eq!(switcher.selected_count(), 0);
+        assert_eq!(switcher.key(SwitcherKey::Escape, false, &RESULTS), SwitcherCommand::Ignored);
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(SwitcherKey::from_key("Enter", true), Some(SwitcherKey::OpenInNewWindow));
+        assert_eq!(SwitcherKey::from_key("Enter", false), Some(SwitcherKey::Enter));
+        assert_eq!(SwitcherKey::from_key("a", false), None);
+    }
+}
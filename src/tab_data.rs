@@ -1,4 +1,6 @@
-/// Data structures for Tab Hoarder
+//! Data structures for Tab Hoarder
+
+use crate::url_normalizer::UrlNormalizer;
 use serde::{Deserialize, Serialize};
 
 /// Information about a browser tab
@@ -9,6 +11,30 @@ pub struct TabInfo {
     pub title: String,
     pub pinned: bool,
     pub index: i32,
+    /// Last time the tab was focused, in ms since the epoch (if known)
+    #[serde(default)]
+    pub last_accessed: Option<f64>,
+    /// Chrome tab group the tab belongs to, if any
+    #[serde(default)]
+    pub group_id: Option<i32>,
+    /// Whether this is the window's active (focused) tab
+    #[serde(default)]
+    pub active: bool,
+    /// Favicon as Chrome reports it: a URL, or a data: URL
+    #[serde(default)]
+    pub fav_icon_url: Option<String>,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub incognito: bool,
+    #[serde(default)]
+    pub window_id: Option<i32>,
+    /// Title and color of the tab's group, if it has one
+    #[serde(default)]
+    pub group: Option<TabGroupInfo>,
+    /// Whether the tab is unloaded until it is next focused
+    #[serde(default)]
+    pub discarded: bool,
 }
 
 impl TabInfo {
@@ -19,10 +45,31 @@ impl TabInfo {
             title,
             pinned,
             index,
+            last_accessed: None,
+            group_id: None,
+            active: false,
+            fav_icon_url: None,
+            muted: false,
+            incognito: false,
+            window_id: None,
+            group: None,
+            discarded: false,
         }
     }
 }
 
+/// How a tab group looks, as chrome.tabGroups reports it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TabGroupInfo {
+    #[serde(default)]
+    pub title: String,
+    /// Chrome's color name ("grey", "blue", "red", ...)
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
 /// A collapsed tab session
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct CollapsedSession {
@@ -32,13 +79,75 @@ pub struct CollapsedSession {
     pub tabs: Vec<SavedTab>,
 }
 
-/// A saved tab within a collapsed session
+impl CollapsedSession {
+    /// Remove tabs whose normalized URL already appears earlier in the
+    /// session; returns how many were removed
+    pub fn dedup_tabs(&mut self, normalizer: &UrlNormalizer) -> usize {
+        let original_len = self.tabs.len();
+        let mut seen_urls = std::collections::HashSet::new();
+        self.tabs.retain(|tab| seen_urls.insert(normalizer.normalize(&tab.url)));
+        original_len - self.tabs.len()
+    }
+
+    /// The tabs `dedup_tabs` would remove, in session order
+    pub fn duplicate_tabs(&self, normalizer: &UrlNormalizer) -> Vec<&SavedTab> {
+        let mut seen_urls = std::collections::HashSet::new();
+        self.tabs
+            .iter()
+            .filter(|tab| !seen_urls.insert(normalizer.normalize(&tab.url)))
+            .collect()
+    }
+}
+
+/// A collapse in progress
+///
+/// Stored before the session is saved and cleared once its tabs are
+/// closed; one left behind means the collapse was interrupted.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingCollapse {
+    pub session_id: String,
+    pub session_name: String,
+    /// Tabs the collapse was going to close
+    pub tab_ids: Vec<i32>,
+    /// When the collapse started, in ms since the epoch
+    pub started: f64,
+}
+
+/// A saved tab within a collapsed session
+///
+/// Everything after `pinned` was added later: older sessions load with
+/// the defaults, and fields at their default are not written, so sessions
+/// with little metadata stay as small as before.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct SavedTab {
     pub url: String,
     pub title: String,
     pub domain: String,
     pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fav_icon_url: Option<String>,
+    /// Last time the tab was focused before it was saved, in ms since the
+    /// epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_accessed: Option<f64>,
+    /// When the tab was saved, in ms since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_at: Option<f64>,
+    /// Position in its window when it was saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_id: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<TabGroupInfo>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub muted: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub incognito: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
 }
 
 #[cfg(test)]
@@ -46,6 +155,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_tab_info_creation() {
         let tab = TabInfo::new(
             1,
@@ -62,6 +172,37 @@ mod tests {
         assert_eq!(tab.index, 0);
     }
 
+    fn saved_tab(url: &str) -> SavedTab {
+        SavedTab {
+            url: url.to_string(),
+            title: url.to_string(),
+            domain: "x.com".to_string(),
+            ..SavedTab::default()
+        }
+    }
+
+    #[test]
+    fn test_session_dedup_tabs() {
+        let mut session = CollapsedSession {
+            id: "test-123".to_string(),
+            name: "Test".to_string(),
+            timestamp: 1698508200000.0,
+            tabs: vec![
+                saved_tab("https://x.com/a"),
+                saved_tab("https://x.com/a/?utm_medium=email"),
+                saved_tab("https://x.com/b"),
+                saved_tab("https://www.x.com/a?fbclid=1"),
+            ],
+        };
+
+        let removed = session.dedup_tabs(&UrlNormalizer::default());
+
+        assert_eq!(removed, 2);
+        assert_eq!(session.tabs.len(), 2);
+        assert_eq!(session.tabs[0].url, "https://x.com/a");
+        assert_eq!(session.tabs[1].url, "https://x.com/b");
+    }
+
     #[test]
     fn test_serialization() {
         let session = CollapsedSession {
@@ -73,7 +214,7 @@ mod tests {
                     url: "https://google.com".to_string(),
                     title: "Google".to_string(),
                     domain: "google.com".to_string(),
-                    pinned: false,
+                    ..SavedTab::default()
                 },
             ],
         };
@@ -84,4 +225,44 @@ mod tests {
         assert_eq!(deserialized.id, "test-123");
         assert_eq!(deserialized.tabs.len(), 1);
     }
+
+    #[test]
+    fn test_saved_tab_without_metadata_loads() {
+        let json = r#"{"url":"https://google.com","title":"Google","domain":"google.com","pinned":true}"#;
+
+        let tab: SavedTab = serde_json::from_str(json).unwrap();
+
+        assert!(tab.pinned);
+        assert_eq!(tab.index, None);
+        assert_eq!(tab.group, None);
+        assert!(!tab.muted);
+        // Written back without the fields it never had
+        assert_eq!(serde_json::to_string(&tab).unwrap(), json);
+    }
+
+    #[test]
+    fn test_saved_tab_metadata_round_trip() {
+        let tab = SavedTab {
+            fav_icon_url: Some("https://x.com/favicon.ico".to_string()),
+            last_accessed: Some(1698508100000.0),
+            saved_at: Some(1698508200000.0),
+            index: Some(4),
+            window_id: Some(2),
+            group: Some(TabGroupInfo {
+                title: "Reading".to_string(),
+                color: "blue".to_string(),
+                collapsed: true,
+            }),
+            muted: true,
+            incognito: false,
+            ..saved_tab("https://x.com/a")
+        };
+
+        let json = serde_json::to_value(&tab).unwrap();
+        let deserialized: SavedTab = serde_json::from_value(json.clone()).unwrap();
+
+        assert_eq!(deserialized, tab);
+        assert_eq!(json["group"]["color"], "blue");
+        assert!(json.get("incognito").is_none());
+    }
 }
@@ -0,0 +1,265 @@
+//! Browser tab backend
+//!
+//! Every tab change goes through `TabBackend`. Implementations provide the
+//! primitive calls (query, move, remove, create, update, group, ...); the bulk
+//! operations the popup and viewer run are provided methods built on them.
+//! `ChromeTabs` overrides the bulk methods with the chunked JS bridge, and
+//! `MemoryBrowser` simulates a browser so the flows in `actions` can be
+//! tested natively.
+
+pub mod chrome;
+pub mod memory;
+
+pub use chrome::ChromeTabs;
+pub use memory::MemoryBrowser;
+
+use crate::operations::SortPlan;
+use crate::restore::RestorePlan;
+use crate::tab_data::{TabGroupInfo, TabInfo};
+use crate::undo::ClosedTab;
+
+/// A tab to open, as passed to chrome.tabs.create
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTab {
+    pub url: String,
+    pub pinned: bool,
+    /// Position in the window; `None` appends
+    pub index: Option<i32>,
+    pub active: bool,
+    /// Window to open the tab in; `None` is the current window
+    pub window_id: Option<i32>,
+}
+
+impl NewTab {
+    /// A background tab appended to the window
+    pub fn new(url: &str) -> Self {
+        NewTab {
+            url: url.to_string(),
+            pinned: false,
+            index: None,
+            active: false,
+            window_id: None,
+        }
+    }
+}
+
+/// Properties to change on an open tab; `None` leaves a property alone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TabUpdate {
+    pub active: Option<bool>,
+    pub pinned: Option<bool>,
+    pub muted: Option<bool>,
+}
+
+/// Tabs of the browser the extension runs in
+///
+/// Indices follow Chrome: pinned tabs always come first, and the tabs of a
+/// group are always next to each other.
+pub trait TabBackend {
+    /// Tabs of the current window, ordered by index
+    async fn query(&self) -> Result<Vec<TabInfo>, String>;
+
+    /// Move a tab to `index` in its window; it may join or leave a group
+    /// depending on where it lands
+    async fn move_tab(&self, tab_id: i32, index: i32) -> Result<(), String>;
+
+    /// Move a whole group so its first tab lands at `index`
+    async fn move_group(&self, group_id: i32, index: i32) -> Result<(), String>;
+
+    /// Close tabs; fails without closing anything if an ID is unknown
+    async fn remove(&self, tab_ids: &[i32]) -> Result<(), String>;
+
+    /// Open a tab in the current window, or in `tab.window_id`
+    async fn create(&self, tab: NewTab) -> Result<TabInfo, String>;
+
+    /// Open a new window holding `tab` and focus it; the returned tab has
+    /// the new window's ID
+    async fn create_window(&self, tab: NewTab) -> Result<TabInfo, String>;
+
+    /// Move tabs, in order, into a new focused window, reporting progress
+    /// (0-100); returns the window's ID. Moved tabs leave their groups.
+    async fn move_to_new_window(&self, tab_ids: &[i32], progress: impl Fn(u8) + 'static) -> Result<i32, String>;
+
+    async fn update(&self, tab_id: i32, update: TabUpdate) -> Result<(), String>;
+
+    /// Add tabs to `group_id`, or to a new group when `None`; returns the
+    /// group's ID
+    async fn group(&self, tab_ids: &[i32], group_id: Option<i32>) -> Result<i32, String>;
+
+    /// Set a group's title, color and collapsed state
+    async fn update_group(&self, group_id: i32, info: &TabGroupInfo) -> Result<(), String>;
+
+    /// Unload a tab until it is next focused; returns its ID afterwards.
+    /// The active tab cannot be discarded.
+    async fn discard(&self, tab_id: i32) -> Result<i32, String>;
+
+    /// Reorder tabs according to a sort plan
+    ///
+    /// Units are placed left to right from `plan.start_index`. A grouped
+    /// unit is moved as a group first, then its tabs are reordered inside
+    /// the group's range and re-attached in case a move ungrouped one. A
+    /// group that no longer exists is skipped and its tabs placed as they are.
+    async fn apply_sort_plan(&self, plan: &SortPlan, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let total = plan.tab_order().len().max(1);
+        let mut target_index = plan.start_index;
+        let mut processed = 0;
+
+        for unit in &plan.units {
+            let mut group_id = unit.group_id;
+            if let Some(id) = group_id
+                && self.move_group(id, target_index).await.is_err()
+            {
+                log::warn!("Tab group no longer exists: {}", id);
+                group_id = None;
+            }
+
+            // Moves must run in order: each one shifts the tabs after it
+            for (offset, &tab_id) in unit.tab_ids.iter().enumerate() {
+                self.move_tab(tab_id, target_index + offset as i32).await?;
+            }
+
+            if let Some(group_id) = group_id {
+                self.group(&unit.tab_ids, Some(group_id)).await?;
+            }
+
+            target_index += unit.tab_ids.len() as i32;
+            processed += unit.tab_ids.len();
+            progress((processed * 100 / total) as u8);
+        }
+
+        Ok(())
+    }
+
+    /// Close tabs, reporting progress (0-100)
+    async fn remove_tabs(&self, tab_ids: &[i32], progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        if tab_ids.is_empty() {
+            return Ok(());
+        }
+        self.remove(tab_ids).await?;
+        progress(100);
+        Ok(())
+    }
+
+    /// Apply the same update to several tabs, one after another in the
+    /// given order, reporting progress (0-100)
+    async fn update_tabs(&self, tab_ids: &[i32], update: TabUpdate, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let total = tab_ids.len().max(1);
+        for (i, &tab_id) in tab_ids.iter().enumerate() {
+            self.update(tab_id, update).await?;
+            progress(((i + 1) * 100 / total) as u8);
+        }
+        Ok(())
+    }
+
+    /// Put tabs in a new group with `info`'s title and color; returns the
+    /// group's ID
+    async fn create_group(
+        &self,
+        tab_ids: &[i32],
+        info: &TabGroupInfo,
+        progress: impl Fn(u8) + 'static,
+    ) -> Result<i32, String> {
+        let group_id = self.group(tab_ids, None).await?;
+        self.update_group(group_id, info).await?;
+        progress(100);
+        Ok(group_id)
+    }
+
+    /// Reopen closed tabs at their original positions (for undo)
+    ///
+    /// Tabs must be ordered by original index so each one lands where it
+    /// was. Tabs that cannot be reopened are skipped, and a tab whose group
+    /// no longer exists is left ungrouped. Returns the positions in `tabs`
+    /// of the ones reopened.
+    async fn reopen_tabs(&self, tabs: &[ClosedTab], progress: impl Fn(u8) + 'static) -> Result<Vec<usize>, String> {
+        let total = tabs.len().max(1);
+        let mut reopened = Vec::with_capacity(tabs.len());
+
+        for (i, tab) in tabs.iter().enumerate() {
+            let created = self
+                .create(NewTab {
+                    pinned: tab.pinned,
+                    index: Some(tab.index),
+                    ..NewTab::new(&tab.url)
+                })
+                .await;
+
+            match created {
+                Ok(created) => {
+                    reopened.push(i);
+                    if let Some(group_id) = tab.group_id
+                        && self.group(&[created.id], Some(group_id)).await.is_err()
+                    {
+                        log::warn!("Tab group no longer exists: {}", group_id);
+                    }
+                }
+                Err(e) => log::warn!("Failed to reopen tab {}: {}", tab.url, e),
+            }
+            progress(((i + 1) * 100 / total) as u8);
+        }
+
+        Ok(reopened)
+    }
+
+    /// Open saved tabs according to a restore plan, in the background
+    ///
+    /// Each planned window is filled left to right, at the end of the
+    /// current window or in a new one. A lazy plan discards each tab right
+    /// after opening it (except a new window's first, active tab), then the
+    /// window's groups are recreated.
+    async fn restore_tabs(&self, plan: &RestorePlan, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let total = plan.tab_count().max(1);
+        let mut processed = 0;
+
+        for window in &plan.windows {
+            let mut window_id = None;
+            let mut tab_ids = Vec::with_capacity(window.tabs.len());
+
+            for tab in &window.tabs {
+                let new_tab = NewTab {
+                    pinned: tab.pinned,
+                    window_id,
+                    ..NewTab::new(&tab.url)
+                };
+                let created = if window.new_window && window_id.is_none() {
+                    let created = self.create_window(new_tab).await?;
+                    window_id = created.window_id;
+                    created
+                } else {
+                    self.create(new_tab).await?
+                };
+
+                if tab.muted {
+                    self.update(created.id, TabUpdate { muted: Some(true), ..TabUpdate::default() })
+                        .await?;
+                }
+                let tab_id = if plan.discard && !created.active {
+                    self.discard(created.id).await?
+                } else {
+                    created.id
+                };
+                tab_ids.push(tab_id);
+
+                processed += 1;
+                progress((processed * 100 / total) as u8);
+            }
+
+            for (index, info) in window.groups.iter().enumerate() {
+                let members: Vec<i32> = window
+                    .tabs
+                    .iter()
+                    .zip(&tab_ids)
+                    .filter(|(tab, _)| tab.group == Some(index))
+                    .map(|(_, &tab_id)| tab_id)
+                    .collect();
+                if members.is_empty() {
+                    continue;
+                }
+                let group_id = self.group(&members, None).await?;
+                self.update_group(group_id, info).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
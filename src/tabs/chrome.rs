@@ -0,0 +1,256 @@
+//! chrome.tabs backend, through the tabs.js bridge
+//!
+//! The bulk operations call chunked bridge functions that batch Chrome
+//! calls and yield between chunks, instead of crossing into JS per tab.
+
+use super::{NewTab, TabBackend, TabUpdate};
+use crate::operations::SortPlan;
+use crate::restore::RestorePlan;
+use crate::tab_data::{TabGroupInfo, TabInfo};
+use crate::undo::ClosedTab;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(module = "/tabs.js")]
+extern "C" {
+    #[wasm_bindgen(catch)]
+    async fn getCurrentWindowTabs() -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn moveTab(tab_id: i32, index: i32) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn moveGroup(group_id: i32, index: i32) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn createTab(properties: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn createWindow(properties: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn moveToNewWindow(tab_ids: JsValue, progress_callback: &js_sys::Function) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn updateTab(tab_id: i32, properties: JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn updateGroup(group_id: i32, properties: JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn discardTab(tab_id: i32) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn groupTabs(tab_ids: JsValue, group_id: Option<i32>) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn applySortPlan(plan: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn removeTabs(tab_ids: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn updateTabs(tab_ids: JsValue, properties: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn createGroup(tab_ids: JsValue, info: JsValue, progress_callback: &js_sys::Function) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn reopenTabs(tabs: JsValue, progress_callback: &js_sys::Function) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch)]
+    async fn restoreTabs(plan: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
+}
+
+/// The browser's real tabs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChromeTabs;
+
+/// chrome.tabs.create / chrome.tabs.update properties
+#[derive(Serialize)]
+struct TabProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pinned: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    muted: Option<bool>,
+    #[serde(rename = "windowId", skip_serializing_if = "Option::is_none")]
+    window_id: Option<i32>,
+}
+
+impl TabProperties {
+    fn for_new_tab(tab: NewTab) -> Self {
+        TabProperties {
+            url: Some(tab.url),
+            pinned: Some(tab.pinned),
+            index: tab.index,
+            active: Some(tab.active),
+            muted: None,
+            window_id: tab.window_id,
+        }
+    }
+
+    fn for_update(update: TabUpdate) -> Self {
+        TabProperties {
+            url: None,
+            pinned: update.pinned,
+            index: None,
+            active: update.active,
+            muted: update.muted,
+            window_id: None,
+        }
+    }
+}
+
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, String> {
+    serde_wasm_bindgen::to_value(value).map_err(|e| format!("Failed to serialize: {:?}", e))
+}
+
+fn progress_callback(progress: impl Fn(u8) + 'static) -> Closure<dyn Fn(u8)> {
+    Closure::wrap(Box::new(progress) as Box<dyn Fn(u8)>)
+}
+
+impl TabBackend for ChromeTabs {
+    async fn query(&self) -> Result<Vec<TabInfo>, String> {
+        let tabs_js = getCurrentWindowTabs()
+            .await
+            .map_err(|e| format!("Failed to get tabs: {:?}", e))?;
+        let mut tabs: Vec<TabInfo> =
+            serde_wasm_bindgen::from_value(tabs_js).map_err(|e| format!("Failed to parse tabs: {:?}", e))?;
+        tabs.sort_by_key(|tab| tab.index);
+        Ok(tabs)
+    }
+
+    async fn move_tab(&self, tab_id: i32, index: i32) -> Result<(), String> {
+        moveTab(tab_id, index)
+            .await
+            .map_err(|e| format!("Failed to move tab: {:?}", e))
+    }
+
+    async fn move_group(&self, group_id: i32, index: i32) -> Result<(), String> {
+        moveGroup(group_id, index)
+            .await
+            .map_err(|e| format!("Failed to move group: {:?}", e))
+    }
+
+    async fn remove(&self, tab_ids: &[i32]) -> Result<(), String> {
+        self.remove_tabs(tab_ids, |_| {}).await
+    }
+
+    async fn create(&self, tab: NewTab) -> Result<TabInfo, String> {
+        let created_js = createTab(to_js(&TabProperties::for_new_tab(tab))?)
+            .await
+            .map_err(|e| format!("Failed to create tab: {:?}", e))?;
+        serde_wasm_bindgen::from_value(created_js).map_err(|e| format!("Failed to parse tab: {:?}", e))
+    }
+
+    async fn create_window(&self, tab: NewTab) -> Result<TabInfo, String> {
+        let created_js = createWindow(to_js(&TabProperties::for_new_tab(tab))?)
+            .await
+            .map_err(|e| format!("Failed to open window: {:?}", e))?;
+        serde_wasm_bindgen::from_value(created_js).map_err(|e| format!("Failed to parse tab: {:?}", e))
+    }
+
+    async fn move_to_new_window(&self, tab_ids: &[i32], progress: impl Fn(u8) + 'static) -> Result<i32, String> {
+        if tab_ids.is_empty() {
+            return Err("No tabs to move".to_string());
+        }
+        let callback = progress_callback(progress);
+        let window_js = moveToNewWindow(to_js(tab_ids)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Failed to move tabs to a new window: {:?}", e))?;
+        window_js
+            .as_f64()
+            .map(|id| id as i32)
+            .ok_or_else(|| "Failed to move tabs to a new window: no window ID".to_string())
+    }
+
+    async fn update(&self, tab_id: i32, update: TabUpdate) -> Result<(), String> {
+        updateTab(tab_id, to_js(&TabProperties::for_update(update))?)
+            .await
+            .map_err(|e| format!("Failed to update tab: {:?}", e))
+    }
+
+    async fn group(&self, tab_ids: &[i32], group_id: Option<i32>) -> Result<i32, String> {
+        let group_js = groupTabs(to_js(tab_ids)?, group_id)
+            .await
+            .map_err(|e| format!("Failed to group tabs: {:?}", e))?;
+        group_js
+            .as_f64()
+            .map(|id| id as i32)
+            .ok_or_else(|| "Failed to group tabs: no group ID".to_string())
+    }
+
+    async fn update_group(&self, group_id: i32, info: &TabGroupInfo) -> Result<(), String> {
+        updateGroup(group_id, to_js(info)?)
+            .await
+            .map_err(|e| format!("Failed to update group: {:?}", e))
+    }
+
+    async fn discard(&self, tab_id: i32) -> Result<i32, String> {
+        let discarded_js = discardTab(tab_id)
+            .await
+            .map_err(|e| format!("Failed to discard tab: {:?}", e))?;
+        discarded_js
+            .as_f64()
+            .map(|id| id as i32)
+            .ok_or_else(|| "Failed to discard tab: no tab ID".to_string())
+    }
+
+    async fn apply_sort_plan(&self, plan: &SortPlan, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let callback = progress_callback(progress);
+        applySortPlan(to_js(plan)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Sort failed: {:?}", e))
+    }
+
+    async fn remove_tabs(&self, tab_ids: &[i32], progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let callback = progress_callback(progress);
+        removeTabs(to_js(tab_ids)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Close failed: {:?}", e))
+    }
+
+    async fn update_tabs(&self, tab_ids: &[i32], update: TabUpdate, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let callback = progress_callback(progress);
+        updateTabs(to_js(tab_ids)?, to_js(&TabProperties::for_update(update))?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Failed to update tabs: {:?}", e))
+    }
+
+    async fn create_group(
+        &self,
+        tab_ids: &[i32],
+        info: &TabGroupInfo,
+        progress: impl Fn(u8) + 'static,
+    ) -> Result<i32, String> {
+        let callback = progress_callback(progress);
+        let group_js = createGroup(to_js(tab_ids)?, to_js(info)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Failed to group tabs: {:?}", e))?;
+        group_js
+            .as_f64()
+            .map(|id| id as i32)
+            .ok_or_else(|| "Failed to group tabs: no group ID".to_string())
+    }
+
+    async fn reopen_tabs(&self, tabs: &[ClosedTab], progress: impl Fn(u8) + 'static) -> Result<Vec<usize>, String> {
+        let callback = progress_callback(progress);
+        let reopened = reopenTabs(to_js(tabs)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Failed to reopen tabs: {:?}", e))?;
+        serde_wasm_bindgen::from_value(reopened).map_err(|e| format!("Failed to reopen tabs: {:?}", e))
+    }
+
+    async fn restore_tabs(&self, plan: &RestorePlan, progress: impl Fn(u8) + 'static) -> Result<(), String> {
+        let callback = progress_callback(progress);
+        restoreTabs(to_js(plan)?, callback.as_ref().unchecked_ref())
+            .await
+            .map_err(|e| format!("Restore failed: {:?}", e))
+    }
+}
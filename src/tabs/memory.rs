@@ -0,0 +1,641 @@
+//! Simulated browser for native tests
+//!
+//! Keeps Chrome's layout rules: pinned tabs come first, the tabs of a group
+//! are next to each other, and pinned tabs are never grouped. Pages never
+//! load, so a created tab's title is its URL.
+
+use super::{NewTab, TabBackend, TabUpdate};
+use crate::tab_data::{TabGroupInfo, TabInfo};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug)]
+struct Window {
+    id: i32,
+    tabs: Vec<TabInfo>,
+}
+
+#[derive(Debug)]
+struct Browser {
+    windows: Vec<Window>,
+    current_window: i32,
+    next_window_id: i32,
+    next_tab_id: i32,
+    next_group_id: i32,
+    /// Titles and colors of groups that have been given one
+    groups: HashMap<i32, TabGroupInfo>,
+}
+
+/// In-memory browser with windows, pinned tabs and tab groups
+///
+/// Windows stay open when their last tab closes, so a test can collapse a
+/// whole window and restore into it.
+#[derive(Debug)]
+pub struct MemoryBrowser {
+    browser: RefCell<Browser>,
+}
+
+impl Default for MemoryBrowser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBrowser {
+    /// A browser with one empty window
+    pub fn new() -> Self {
+        MemoryBrowser {
+            browser: RefCell::new(Browser {
+                windows: vec![Window { id: 1, tabs: Vec::new() }],
+                current_window: 1,
+                next_window_id: 2,
+                next_tab_id: 1,
+                next_group_id: 1,
+                groups: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Open an empty window and make it the current one
+    pub fn open_window(&self) -> i32 {
+        let mut browser = self.browser.borrow_mut();
+        let id = browser.next_window_id;
+        browser.next_window_id += 1;
+        browser.windows.push(Window { id, tabs: Vec::new() });
+        browser.current_window = id;
+        id
+    }
+
+    pub fn focus_window(&self, window_id: i32) {
+        self.browser.borrow_mut().current_window = window_id;
+    }
+
+    /// Append a loaded tab to the current window; returns its ID
+    pub fn open(&self, url: &str, title: &str) -> i32 {
+        let mut browser = self.browser.borrow_mut();
+        let id = browser.next_tab_id;
+        browser.next_tab_id += 1;
+
+        let window = browser.current_mut();
+        let mut tab = TabInfo::new(id, url.to_string(), title.to_string(), false, 0);
+        tab.active = window.tabs.is_empty();
+        window.tabs.push(tab);
+        reindex(&mut window.tabs);
+        id
+    }
+
+    /// Tabs of a window, ordered by index
+    pub fn window_tabs(&self, window_id: i32) -> Vec<TabInfo> {
+        let browser = self.browser.borrow();
+        browser
+            .windows
+            .iter()
+            .find(|window| window.id == window_id)
+            .map(|window| browser.snapshot(window))
+            .unwrap_or_default()
+    }
+
+    pub fn window_ids(&self) -> Vec<i32> {
+        self.browser.borrow().windows.iter().map(|window| window.id).collect()
+    }
+}
+
+impl Browser {
+    fn current(&self) -> &Window {
+        let current = self.current_window;
+        self.windows
+            .iter()
+            .find(|window| window.id == current)
+            .expect("current window exists")
+    }
+
+    fn window_mut(&mut self, window_id: Option<i32>) -> Result<&mut Window, String> {
+        let window_id = window_id.unwrap_or(self.current_window);
+        self.windows
+            .iter_mut()
+            .find(|window| window.id == window_id)
+            .ok_or_else(|| format!("No window with id: {}", window_id))
+    }
+
+    fn current_mut(&mut self) -> &mut Window {
+        let current = self.current_window;
+        self.windows
+            .iter_mut()
+            .find(|window| window.id == current)
+            .expect("current window exists")
+    }
+
+    /// (window position, tab position) of a tab
+    fn locate(&self, tab_id: i32) -> Result<(usize, usize), String> {
+        self.windows
+            .iter()
+            .enumerate()
+            .find_map(|(w, window)| {
+                window
+                    .tabs
+                    .iter()
+                    .position(|tab| tab.id == tab_id)
+                    .map(|pos| (w, pos))
+            })
+            .ok_or_else(|| format!("No tab with id: {}", tab_id))
+    }
+
+    /// A window's tabs as chrome.tabs.query reports them, with their window
+    /// and group details filled in
+    fn snapshot(&self, window: &Window) -> Vec<TabInfo> {
+        window
+            .tabs
+            .iter()
+            .map(|tab| TabInfo {
+                window_id: Some(window.id),
+                group: tab
+                    .group_id
+                    .map(|group_id| self.groups.get(&group_id).cloned().unwrap_or_default()),
+                ..tab.clone()
+            })
+            .collect()
+    }
+
+    fn locate_group(&self, group_id: i32) -> Result<usize, String> {
+        self.windows
+            .iter()
+            .position(|window| window.tabs.iter().any(|tab| tab.group_id == Some(group_id)))
+            .ok_or_else(|| format!("No group with id: {}", group_id))
+    }
+}
+
+fn reindex(tabs: &mut [TabInfo]) {
+    for (index, tab) in tabs.iter_mut().enumerate() {
+        tab.index = index as i32;
+    }
+}
+
+fn pinned_count(tabs: &[TabInfo]) -> usize {
+    tabs.iter().take_while(|tab| tab.pinned).count()
+}
+
+/// Clamp a requested index into the pinned or unpinned range
+fn target_index(tabs: &[TabInfo], index: Option<i32>, pinned: bool) -> usize {
+    let len = tabs.len();
+    let target = match index {
+        Some(index) if index >= 0 && index as usize <= len => index as usize,
+        _ => len,
+    };
+    let pinned_end = pinned_count(tabs);
+    if pinned { target.min(pinned_end) } else { target.max(pinned_end) }
+}
+
+/// Group a tab inserted at `pos` would land inside (between two of its tabs)
+fn group_around(tabs: &[TabInfo], pos: usize) -> Option<i32> {
+    let left = pos.checked_sub(1).and_then(|i| tabs.get(i)).and_then(|tab| tab.group_id);
+    let right = tabs.get(pos).and_then(|tab| tab.group_id);
+    if left.is_some() && left == right { left } else { None }
+}
+
+/// Update the group of a tab just placed at `pos`, as Chrome does: landing
+/// inside a group joins it, landing away from its own group leaves it
+fn settle_group(tabs: &mut [TabInfo], pos: usize) {
+    if tabs[pos].pinned {
+        return;
+    }
+    let left = pos.checked_sub(1).and_then(|i| tabs[i].group_id);
+    let right = tabs.get(pos + 1).and_then(|tab| tab.group_id);
+    if left.is_some() && left == right {
+        tabs[pos].group_id = left;
+        return;
+    }
+    if let Some(group_id) = tabs[pos].group_id {
+        let has_others = tabs
+            .iter()
+            .enumerate()
+            .any(|(i, tab)| i != pos && tab.group_id == Some(group_id));
+        if has_others && left != Some(group_id) && right != Some(group_id) {
+            tabs[pos].group_id = None;
+        }
+    }
+}
+
+impl TabBackend for MemoryBrowser {
+    async fn query(&self) -> Result<Vec<TabInfo>, String> {
+        let browser = self.browser.borrow();
+        Ok(browser.snapshot(browser.current()))
+    }
+
+    async fn move_tab(&self, tab_id: i32, index: i32) -> Result<(), String> {
+        let mut browser = self.browser.borrow_mut();
+        let (w, pos) = browser.locate(tab_id)?;
+        let tabs = &mut browser.windows[w].tabs;
+
+        let tab = tabs.remove(pos);
+        let target = target_index(tabs, Some(index), tab.pinned);
+        tabs.insert(target, tab);
+        settle_group(tabs, target);
+        reindex(tabs);
+        Ok(())
+    }
+
+    async fn move_group(&self, group_id: i32, index: i32) -> Result<(), String> {
+        let mut browser = self.browser.borrow_mut();
+        let w = browser.locate_group(group_id)?;
+        let tabs = &mut browser.windows[w].tabs;
+
+        let (members, mut rest): (Vec<TabInfo>, Vec<TabInfo>) =
+            tabs.iter().cloned().partition(|tab| tab.group_id == Some(group_id));
+        let target = target_index(&rest, Some(index), false);
+        if group_around(&rest, target).is_some() {
+            return Err("Cannot move the group to an index that is in the middle of another group".to_string());
+        }
+
+        rest.splice(target..target, members);
+        *tabs = rest;
+        reindex(tabs);
+        Ok(())
+    }
+
+    async fn remove(&self, tab_ids: &[i32]) -> Result<(), String> {
+        let mut browser = self.browser.borrow_mut();
+        for &tab_id in tab_ids {
+            browser.locate(tab_id)?;
+        }
+
+        let closing: HashSet<i32> = tab_ids.iter().copied().collect();
+        for window in &mut browser.windows {
+            let active_pos = window.tabs.iter().position(|tab| tab.active);
+            window.tabs.retain(|tab| !closing.contains(&tab.id));
+            reindex(&mut window.tabs);
+
+            // Closing the active tab activates its neighbour
+            if let Some(active_pos) = active_pos
+                && !window.tabs.is_empty()
+                && !window.tabs.iter().any(|tab| tab.active)
+            {
+                let next = active_pos.min(window.tabs.len() - 1);
+                window.tabs[next].active = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn create(&self, tab: NewTab) -> Result<TabInfo, String> {
+        let mut browser = self.browser.borrow_mut();
+        let id = browser.next_tab_id;
+        let window = browser.window_mut(tab.window_id)?;
+        let window_id = window.id;
+        let tabs = &mut window.tabs;
+
+        let target = target_index(tabs, tab.index, tab.pinned);
+        let mut created = TabInfo::new(id, tab.url.clone(), tab.url, tab.pinned, 0);
+        if !tab.pinned {
+            created.group_id = group_around(tabs, target);
+        }
+        if tab.active || tabs.is_empty() {
+            for other in tabs.iter_mut() {
+                other.active = false;
+            }
+            created.active = true;
+        }
+
+        tabs.insert(target, created);
+        reindex(tabs);
+        let created = TabInfo {
+            window_id: Some(window_id),
+            ..tabs[target].clone()
+        };
+        browser.next_tab_id += 1;
+        Ok(created)
+    }
+
+    async fn create_window(&self, tab: NewTab) -> Result<TabInfo, String> {
+        let window_id = self.open_window();
+        self.create(NewTab {
+            index: None,
+            active: true,
+            window_id: Some(window_id),
+            ..tab
+        })
+        .await
+    }
+
+    async fn move_to_new_window(&self, tab_ids: &[i32], progress: impl Fn(u8) + 'static) -> Result<i32, String> {
+        if tab_ids.is_empty() {
+            return Err("No tabs to move".to_string());
+        }
+        let moving = {
+            let browser = self.browser.borrow();
+            let mut moving = Vec::with_capacity(tab_ids.len());
+            for &tab_id in tab_ids {
+                let (w, pos) = browser.locate(tab_id)?;
+                moving.push(browser.windows[w].tabs[pos].clone());
+            }
+            moving
+        };
+        self.remove(tab_ids).await?;
+
+        let window_id = self.open_window();
+        let mut browser = self.browser.borrow_mut();
+        let window = browser.current_mut();
+        // Pinned tabs stay pinned, so they go first
+        let (mut tabs, unpinned): (Vec<TabInfo>, Vec<TabInfo>) = moving.into_iter().partition(|tab| tab.pinned);
+        tabs.extend(unpinned);
+        for (i, tab) in tabs.iter_mut().enumerate() {
+            tab.group_id = None;
+            tab.active = i == 0;
+        }
+        window.tabs = tabs;
+        reindex(&mut window.tabs);
+        progress(100);
+        Ok(window_id)
+    }
+
+    async fn update(&self, tab_id: i32, update: TabUpdate) -> Result<(), String> {
+        let mut browser = self.browser.borrow_mut();
+        let (w, mut pos) = browser.locate(tab_id)?;
+        let tabs = &mut browser.windows[w].tabs;
+
+        // Pinning or unpinning moves the tab to the edge of the pinned block
+        if let Some(pinned) = update.pinned
+            && pinned != tabs[pos].pinned
+        {
+            let mut tab = tabs.remove(pos);
+            tab.pinned = pinned;
+            tab.group_id = None;
+            pos = pinned_count(tabs);
+            tabs.insert(pos, tab);
+        }
+
+        if let Some(muted) = update.muted {
+            tabs[pos].muted = muted;
+        }
+
+        if update.active == Some(true) {
+            for tab in tabs.iter_mut() {
+                tab.active = false;
+            }
+            tabs[pos].active = true;
+        }
+
+        reindex(tabs);
+        Ok(())
+    }
+
+    async fn group(&self, tab_ids: &[i32], group_id: Option<i32>) -> Result<i32, String> {
+        let mut browser = self.browser.borrow_mut();
+        let Some(&first_id) = tab_ids.first() else {
+            return Err("No tabs to group".to_string());
+        };
+
+        let (w, _) = browser.locate(first_id)?;
+        for &tab_id in tab_ids {
+            let (tab_window, pos) = browser.locate(tab_id)?;
+            if tab_window != w {
+                return Err("Tabs must be in the same window".to_string());
+            }
+            if browser.windows[tab_window].tabs[pos].pinned {
+                return Err("Cannot group pinned tabs".to_string());
+            }
+        }
+
+        let group_id = match group_id {
+            Some(group_id) => {
+                if browser.locate_group(group_id)? != w {
+                    return Err("Tabs must be in the same window".to_string());
+                }
+                group_id
+            }
+            None => {
+                let group_id = browser.next_group_id;
+                browser.next_group_id += 1;
+                group_id
+            }
+        };
+
+        let tabs = &mut browser.windows[w].tabs;
+        let grouping: HashSet<i32> = tab_ids.iter().copied().collect();
+        let first_pos = tabs
+            .iter()
+            .position(|tab| grouping.contains(&tab.id))
+            .unwrap_or(0);
+        let (mut members, mut rest): (Vec<TabInfo>, Vec<TabInfo>) =
+            tabs.drain(..).partition(|tab| grouping.contains(&tab.id));
+
+        // Join the end of the group, or form it where the first tab was
+        // (after any group that position would split)
+        let insert_at = match rest.iter().rposition(|tab| tab.group_id == Some(group_id)) {
+            Some(last) => last + 1,
+            None => {
+                let mut pos = first_pos;
+                if let Some(other) = group_around(&rest, pos) {
+                    while rest.get(pos).is_some_and(|tab| tab.group_id == Some(other)) {
+                        pos += 1;
+                    }
+                }
+                pos
+            }
+        };
+
+        for tab in &mut members {
+            tab.group_id = Some(group_id);
+        }
+        rest.splice(insert_at..insert_at, members);
+        *tabs = rest;
+        reindex(tabs);
+        Ok(group_id)
+    }
+
+    async fn update_group(&self, group_id: i32, info: &TabGroupInfo) -> Result<(), String> {
+        let mut browser = self.browser.borrow_mut();
+        browser.locate_group(group_id)?;
+        browser.groups.insert(group_id, info.clone());
+        Ok(())
+    }
+
+    async fn discard(&self, tab_id: i32) -> Result<i32, String> {
+        let mut browser = self.browser.borrow_mut();
+        let (w, pos) = browser.locate(tab_id)?;
+        let tab = &mut browser.windows[w].tabs[pos];
+        if tab.active {
+            return Err("Cannot discard the active tab".to_string());
+        }
+        tab.discarded = true;
+        Ok(tab_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::block_on;
+
+    /// Window with tabs a..f; b and c form a group, d is pinned first
+    fn create_test_browser() -> (MemoryBrowser, i32) {
+        let browser = MemoryBrowser::new();
+        for name in ["a", "b", "c", "d", "e", "f"] {
+            browser.open(&format!("https://{}.example.com/", name), name);
+        }
+        block_on(browser.update(4, TabUpdate { pinned: Some(true), ..TabUpdate::default() })).unwrap();
+        let group_id = block_on(browser.group(&[2, 3], None)).unwrap();
+        (browser, group_id)
+    }
+
+    fn ids(browser: &MemoryBrowser) -> Vec<i32> {
+        block_on(browser.query()).unwrap().iter().map(|tab| tab.id).collect()
+    }
+
+    fn group_of(browser: &MemoryBrowser, tab_id: i32) -> Option<i32> {
+        block_on(browser.query())
+            .unwrap()
+            .into_iter()
+            .find(|tab| tab.id == tab_id)
+            .and_then(|tab| tab.group_id)
+    }
+
+    #[test]
+    fn test_pinning_moves_to_pinned_block() {
+        let (browser, _) = create_test_browser();
+
+        assert_eq!(ids(&browser), vec![4, 1, 2, 3, 5, 6]);
+        let tabs = block_on(browser.query()).unwrap();
+        assert!(tabs[0].pinned);
+        assert!(tabs.iter().enumerate().all(|(i, tab)| tab.index == i as i32));
+    }
+
+    #[test]
+    fn test_moves_are_clamped_around_pinned_tabs() {
+        let (browser, _) = create_test_browser();
+
+        // Unpinned tabs cannot move before the pinned block...
+        block_on(browser.move_tab(6, 0)).unwrap();
+        assert_eq!(ids(&browser), vec![4, 6, 1, 2, 3, 5]);
+
+        // ...and pinned tabs cannot move after it
+        block_on(browser.move_tab(4, 5)).unwrap();
+        assert_eq!(ids(&browser)[0], 4);
+    }
+
+    #[test]
+    fn test_moving_into_and_out_of_groups() {
+        let (browser, group_id) = create_test_browser();
+
+        // Between two tabs of the group: joins it
+        block_on(browser.move_tab(5, 3)).unwrap();
+        assert_eq!(ids(&browser), vec![4, 1, 2, 5, 3, 6]);
+        assert_eq!(group_of(&browser, 5), Some(group_id));
+
+        // Away from the group: leaves it
+        block_on(browser.move_tab(5, 5)).unwrap();
+        assert_eq!(group_of(&browser, 5), None);
+
+        // Within the group's range: stays in it
+        block_on(browser.move_tab(3, 2)).unwrap();
+        assert_eq!(ids(&browser), vec![4, 1, 3, 2, 6, 5]);
+        assert_eq!(group_of(&browser, 3), Some(group_id));
+    }
+
+    #[test]
+    fn test_move_group() {
+        let (browser, group_id) = create_test_browser();
+
+        block_on(browser.move_group(group_id, 3)).unwrap();
+        assert_eq!(ids(&browser), vec![4, 1, 5, 2, 3, 6]);
+
+        let other = block_on(browser.group(&[1, 5], None)).unwrap();
+        assert_eq!(ids(&browser), vec![4, 1, 5, 2, 3, 6]);
+        assert!(block_on(browser.move_group(other, 2)).is_err());
+        assert_eq!(ids(&browser), vec![4, 1, 5, 2, 3, 6]);
+    }
+
+    #[test]
+    fn test_grouping_rules() {
+        let (browser, group_id) = create_test_browser();
+
+        assert!(block_on(browser.group(&[4], None)).is_err());
+        assert!(block_on(browser.group(&[1], Some(99))).is_err());
+
+        // Joining an existing group moves the tab to its end
+        block_on(browser.group(&[6], Some(group_id))).unwrap();
+        assert_eq!(ids(&browser), vec![4, 1, 2, 3, 6, 5]);
+        assert_eq!(group_of(&browser, 6), Some(group_id));
+    }
+
+    #[test]
+    fn test_remove_is_all_or_nothing() {
+        let (browser, _) = create_test_browser();
+
+        assert!(block_on(browser.remove(&[1, 42])).is_err());
+        assert_eq!(ids(&browser).len(), 6);
+
+        block_on(browser.remove(&[1, 2])).unwrap();
+        assert_eq!(ids(&browser), vec![4, 3, 5, 6]);
+    }
+
+    #[test]
+    fn test_closing_active_tab_activates_neighbour() {
+        let (browser, _) = create_test_browser();
+        block_on(browser.update(5, TabUpdate { active: Some(true), ..TabUpdate::default() })).unwrap();
+
+        block_on(browser.remove(&[5])).unwrap();
+
+        let active: Vec<i32> = block_on(browser.query())
+            .unwrap()
+            .iter()
+            .filter(|tab| tab.active)
+            .map(|tab| tab.id)
+            .collect();
+        assert_eq!(active, vec![6]);
+    }
+
+    #[test]
+    fn test_create_respects_layout() {
+        let (browser, group_id) = create_test_browser();
+
+        // An unpinned tab asked for index 0 lands after the pinned block
+        let first = block_on(browser.create(NewTab {
+            index: Some(0),
+            ..NewTab::new("https://new.example.com/")
+        }))
+        .unwrap();
+        assert_eq!(first.index, 1);
+        assert_eq!(first.title, "https://new.example.com/");
+
+        // Created inside a group, it joins the group
+        let inside = block_on(browser.create(NewTab {
+            index: Some(4),
+            ..NewTab::new("https://grouped.example.com/")
+        }))
+        .unwrap();
+        assert_eq!(inside.group_id, Some(group_id));
+    }
+
+    #[test]
+    fn test_move_to_new_window() {
+        let (browser, group_id) = create_test_browser();
+        let before = ids(&browser);
+        let moving = [before[5], before[0], before[1]];
+
+        let window_id = block_on(browser.move_to_new_window(&moving, |_| {})).unwrap();
+
+        assert_eq!(browser.window_ids(), vec![1, window_id]);
+        let moved = browser.window_tabs(window_id);
+        // The pinned tab stays first; the grouped one leaves its group
+        assert_eq!(moved.iter().map(|tab| tab.id).collect::<Vec<_>>(), vec![before[0], before[5], before[1]]);
+        assert!(moved[0].pinned && moved[0].active);
+        assert!(moved.iter().all(|tab| tab.group_id.is_none()));
+        assert_eq!(browser.window_tabs(1).len(), 3);
+        assert!(browser.window_tabs(1).iter().any(|tab| tab.group_id == Some(group_id)));
+    }
+
+    #[test]
+    fn test_windows() {
+        let (browser, _) = create_test_browser();
+        let second = browser.open_window();
+
+        block_on(browser.create(NewTab::new("https://other.example.com/"))).unwrap();
+
+        assert_eq!(browser.window_ids(), vec![1, second]);
+        assert_eq!(ids(&browser).len(), 1);
+        assert_eq!(browser.window_tabs(1).len(), 6);
+
+        browser.focus_window(1);
+        assert_eq!(ids(&browser).len(), 6);
+    }
+}
@@ -0,0 +1,95 @@
+//! Helpers shared by native tests
+
+use crate::tab_data::{CollapsedSession, SavedTab};
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+/// Run a future to completion
+///
+/// The in-memory backends never wait on anything, so polling with a no-op
+/// waker until the future is ready is enough.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+/// Builder for collapsed sessions in tests
+///
+/// `TestSession::new("a").build()` is named "Session a", saved at a fixed
+/// time and holds a single Google tab; the other methods change that.
+pub struct TestSession(CollapsedSession);
+
+impl TestSession {
+    pub fn new(id: &str) -> Self {
+        TestSession(CollapsedSession {
+            id: id.to_string(),
+            name: format!("Session {}", id),
+            timestamp: 1698508200000.0,
+            tabs: vec![SavedTab {
+                url: "https://google.com".to_string(),
+                title: "Google".to_string(),
+                domain: "google.com".to_string(),
+                pinned: false,
+                ..SavedTab::default()
+            }],
+        })
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.0.name = name.to_string();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: f64) -> Self {
+        self.0.timestamp = timestamp;
+        self
+    }
+
+    /// One tab titled "Page" per URL, grouped by `domain::group_key`
+    pub fn urls(mut self, urls: &[&str]) -> Self {
+        self.0.tabs = urls
+            .iter()
+            .map(|url| SavedTab {
+                url: url.to_string(),
+                title: "Page".to_string(),
+                domain: crate::domain::group_key(url).1,
+                pinned: false,
+                ..SavedTab::default()
+            })
+            .collect();
+        self
+    }
+
+    /// `count` issue pages spread over a few sites, the first one pinned
+    pub fn issue_tabs(mut self, count: usize) -> Self {
+        let sites = [
+            ("https://github.com", "github.com"),
+            ("https://docs.rs", "docs.rs"),
+            ("https://en.wikipedia.org", "wikipedia.org"),
+            ("https://news.ycombinator.com", "ycombinator.com"),
+        ];
+        self.0.tabs = (0..count)
+            .map(|i| {
+                let (origin, domain) = sites[i % sites.len()];
+                SavedTab {
+                    url: format!("{}/project-{}/issues/{}?tab=comments#c{}", origin, i % 7, i, i),
+                    title: format!("Issue {} · project-{}", i, i % 7),
+                    domain: domain.to_string(),
+                    pinned: i == 0,
+                    ..SavedTab::default()
+                }
+            })
+            .collect();
+        self
+    }
+
+    pub fn build(self) -> CollapsedSession {
+        self.0
+    }
+}
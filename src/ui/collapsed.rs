@@ -1,26 +1,25 @@
-/// Collapsed tabs viewer page
+//! Collapsed tabs viewer page
 
 use yew::prelude::*;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::{HtmlInputElement, console};
 use patternfly_yew::prelude::*;
-use crate::storage::StorageData;
+use crate::domain::display_domain;
+use crate::query::{duplicate_urls, Candidate, MatchOptions, Query};
+use crate::restore::{plan_restore, RestoreOptions, RestoreTarget};
+use crate::search_index::{group_by_session, highlight_ranges, query_terms, SearchIndex};
+use crate::storage::quota::format_bytes;
+use crate::storage::{keys, ChromeStorage, CompressionStats, QuotaReport, SessionEncoding, StorageData, StorageRepository, STORAGE_KEY};
+use crate::tabs::{ChromeTabs, TabBackend};
+use crate::ui::components::{DomainLabel, Highlighted};
 use crate::tab_data::{CollapsedSession, SavedTab};
+use crate::url_normalizer::UrlNormalizer;
 use std::collections::HashMap;
 
 // Import JS bridge functions
-#[wasm_bindgen(module = "/collapsed.js")]
+#[wasm_bindgen(module = "/files.js")]
 extern "C" {
-    #[wasm_bindgen(catch)]
-    async fn getStorage(key: &str) -> Result<JsValue, JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn setStorage(key: &str, value: JsValue) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn restoreTabs(tabs: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
-
     fn exportToFile(data: &str, filename: &str);
 }
 
@@ -35,10 +34,19 @@ enum ViewState {
 #[function_component(CollapsedViewer)]
 pub fn collapsed_viewer() -> Html {
     let state = use_state(|| ViewState::Loading);
-    let storage = use_state(|| StorageData::new());
-    let search_query = use_state(|| String::new());
+    let storage = use_state(StorageData::new);
+    let search_query = use_state(String::new);
     let editing_session = use_state(|| None::<String>); // session ID being edited
-    let edit_input_value = use_state(|| String::new());
+    let edit_input_value = use_state(String::new);
+    let url_normalizer = use_state(UrlNormalizer::default);
+    let session_encoding = use_state(SessionEncoding::default);
+    let compression_stats = use_state(|| None::<CompressionStats>);
+    let quota_report = use_state(|| None::<QuotaReport>);
+    let restore_options = use_state(RestoreOptions::default);
+    // Duplicates "Remove Duplicates" found, as (session name, tab), until confirmed
+    let pending_dedup = use_state(|| None::<Vec<(String, SavedTab)>>);
+    // Kept across renders and synced with the sessions before each search
+    let search_index = use_mut_ref(SearchIndex::new);
 
     // Load storage on mount
     {
@@ -47,7 +55,7 @@ pub fn collapsed_viewer() -> Html {
 
         use_effect_with((), move |_| {
             spawn_local(async move {
-                match load_storage().await {
+                match ChromeStorage.load_sessions().await {
                     Ok(data) => {
                         storage.set(data);
                         state.set(ViewState::Idle);
@@ -61,6 +69,140 @@ pub fn collapsed_viewer() -> Html {
         });
     }
 
+    // Reload when another page (e.g. the popup collapsing tabs) writes, so
+    // later edits here start from the latest sessions instead of clobbering
+    {
+        let state = state.clone();
+        let storage = storage.clone();
+
+        use_effect_with((), move |_| {
+            let on_change = Closure::wrap(Box::new(move |_index: JsValue| {
+                let state = state.clone();
+                let storage = storage.clone();
+                spawn_local(async move {
+                    match ChromeStorage.load_sessions().await {
+                        Ok(data) => storage.set(data),
+                        Err(e) => state.set(ViewState::Error(format!("Failed to load: {}", e))),
+                    }
+                });
+            }) as Box<dyn Fn(JsValue)>);
+
+            let unsubscribe = ChromeStorage.on_changed(STORAGE_KEY, on_change.as_ref().unchecked_ref());
+            move || {
+                let _ = unsubscribe.call0(&JsValue::NULL);
+                drop(on_change);
+            }
+        });
+    }
+
+    // Load duplicate-matching rules (shared with the popup), the session
+    // encoding and the restore options on mount
+    {
+        let url_normalizer = url_normalizer.clone();
+        let session_encoding = session_encoding.clone();
+        let restore_options = restore_options.clone();
+
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                if let Ok(Some(normalizer)) = ChromeStorage.get(keys::URL_NORMALIZER).await {
+                    url_normalizer.set(normalizer);
+                }
+                if let Ok(Some(encoding)) = ChromeStorage.get(keys::SESSION_ENCODING).await {
+                    session_encoding.set(encoding);
+                }
+                if let Ok(Some(options)) = ChromeStorage.get(keys::RESTORE_OPTIONS).await {
+                    restore_options.set(options);
+                }
+            });
+            || ()
+        });
+    }
+
+    // Measure stored sizes whenever the stored data changes
+    {
+        let compression_stats = compression_stats.clone();
+        let quota_report = quota_report.clone();
+        let data = (*storage).clone();
+
+        use_effect_with(storage.revision, move |_| {
+            spawn_local(async move {
+                compression_stats.set(ChromeStorage.compression_stats().await.ok());
+                quota_report.set(ChromeStorage.quota_report(&data).await.ok());
+            });
+            || ()
+        });
+    }
+
+    // Compression toggle: store the preference, then rewrite every session
+    // so existing archives shrink (or become plain JSON) right away
+    let on_compression_change = {
+        let storage = storage.clone();
+        let state = state.clone();
+        let session_encoding = session_encoding.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            let storage = storage.clone();
+            let state = state.clone();
+            let encoding = match *session_encoding {
+                SessionEncoding::Plain => SessionEncoding::Compressed,
+                SessionEncoding::Compressed => SessionEncoding::Plain,
+            };
+            session_encoding.set(encoding);
+
+            spawn_local(async move {
+                if let Err(e) = ChromeStorage.set(keys::SESSION_ENCODING, &encoding).await {
+                    state.set(ViewState::Error(format!("Failed to save: {}", e)));
+                    return;
+                }
+                match ChromeStorage.rewrite_sessions().await {
+                    Ok(data) => storage.set(data),
+                    Err(e) => state.set(ViewState::Error(format!("Failed to save: {}", e))),
+                }
+            });
+        })
+    };
+
+    // Restore options: store every change right away
+    let set_restore_options = {
+        let restore_options = restore_options.clone();
+        Callback::from(move |options: RestoreOptions| {
+            restore_options.set(options);
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::RESTORE_OPTIONS, &options).await;
+            });
+        })
+    };
+
+    let on_restore_target_change = {
+        let restore_options = restore_options.clone();
+        set_restore_options.reform(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            let target = select
+                .value()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| RestoreTarget::CHOICES.get(i).copied())
+                .unwrap_or_default();
+            RestoreOptions { target, ..*restore_options }
+        })
+    };
+
+    let on_recreate_groups_change = {
+        let restore_options = restore_options.clone();
+        set_restore_options.reform(move |_: MouseEvent| RestoreOptions {
+            recreate_groups: !restore_options.recreate_groups,
+            ..*restore_options
+        })
+    };
+
+    let on_lazy_restore_change = {
+        let restore_options = restore_options.clone();
+        set_restore_options.reform(move |_: MouseEvent| RestoreOptions {
+            lazy: !restore_options.lazy,
+            ..*restore_options
+        })
+    };
+
     // Search handler
     let on_search_input = {
         let search_query = search_query.clone();
@@ -77,16 +219,10 @@ pub fn collapsed_viewer() -> Html {
         let state = state.clone();
 
         Callback::from(move |session_id: String| {
-            let mut new_storage = (*storage).clone();
+            let previous = (*storage).clone();
+            let mut new_storage = previous.clone();
             new_storage.remove_session(&session_id);
-            storage.set(new_storage.clone());
-
-            let state = state.clone();
-            spawn_local(async move {
-                if let Err(e) = save_storage(&new_storage).await {
-                    state.set(ViewState::Error(format!("Failed to save: {}", e)));
-                }
-            });
+            save_edit(previous, new_storage, &storage, &state);
         })
     };
 
@@ -111,17 +247,11 @@ pub fn collapsed_viewer() -> Html {
         Callback::from(move |_| {
             if let Some(session_id) = (*editing_session).clone() {
                 let new_name = (*edit_input_value).clone();
-                let mut new_storage = (*storage).clone();
+                let previous = (*storage).clone();
+                let mut new_storage = previous.clone();
 
                 if new_storage.update_session_name(&session_id, new_name) {
-                    storage.set(new_storage.clone());
-
-                    let state = state.clone();
-                    spawn_local(async move {
-                        if let Err(e) = save_storage(&new_storage).await {
-                            state.set(ViewState::Error(format!("Failed to save: {}", e)));
-                        }
-                    });
+                    save_edit(previous, new_storage, &storage, &state);
                 }
 
                 editing_session.set(None);
@@ -150,13 +280,15 @@ pub fn collapsed_viewer() -> Html {
     // Restore entire session
     let on_restore_session = {
         let state = state.clone();
+        let restore_options = restore_options.clone();
 
         Callback::from(move |session: CollapsedSession| {
             let state = state.clone();
+            let options = *restore_options;
             state.set(ViewState::Restoring(0, "Restoring tabs...".to_string()));
 
             spawn_local(async move {
-                match restore_session_tabs(&session.tabs, state.clone()).await {
+                match restore_session_tabs(&session.tabs, options, state.clone()).await {
                     Ok(_) => {
                         state.set(ViewState::Idle);
                     }
@@ -168,15 +300,20 @@ pub fn collapsed_viewer() -> Html {
         })
     };
 
-    // Restore individual tab
+    // Restore individual tab, always into this window
     let on_restore_tab = {
         let state = state.clone();
+        let restore_options = restore_options.clone();
 
         Callback::from(move |tab: SavedTab| {
             let state = state.clone();
+            let options = RestoreOptions {
+                target: RestoreTarget::CurrentWindow,
+                ..*restore_options
+            };
 
             spawn_local(async move {
-                match restore_session_tabs(&vec![tab], state.clone()).await {
+                match restore_session_tabs(&[tab], options, state.clone()).await {
                     Ok(_) => {}
                     Err(e) => {
                         state.set(ViewState::Error(format!("Restore failed: {}", e)));
@@ -192,7 +329,8 @@ pub fn collapsed_viewer() -> Html {
         let state = state.clone();
 
         Callback::from(move |(session_id, tab_url): (String, String)| {
-            let mut new_storage = (*storage).clone();
+            let previous = (*storage).clone();
+            let mut new_storage = previous.clone();
 
             // Find session and remove tab
             if let Some(session) = new_storage.sessions.iter_mut().find(|s| s.id == session_id) {
@@ -203,14 +341,48 @@ pub fn collapsed_viewer() -> Html {
                     new_storage.remove_session(&session_id);
                 }
 
-                storage.set(new_storage.clone());
+                save_edit(previous, new_storage, &storage, &state);
+            }
+        })
+    };
 
-                let state = state.clone();
-                spawn_local(async move {
-                    if let Err(e) = save_storage(&new_storage).await {
-                        state.set(ViewState::Error(format!("Failed to save: {}", e)));
-                    }
-                });
+    // Remove duplicate tabs within each session
+    let on_dedup = {
+        let storage = storage.clone();
+        let url_normalizer = url_normalizer.clone();
+        let pending_dedup = pending_dedup.clone();
+
+        Callback::from(move |_| {
+            let duplicates: Vec<(String, SavedTab)> = storage
+                .session_duplicates(&url_normalizer)
+                .into_iter()
+                .map(|(session, tab)| (session.name.clone(), tab.clone()))
+                .collect();
+            console::log_1(&format!("Found {} duplicate tabs", duplicates.len()).into());
+            pending_dedup.set(Some(duplicates));
+        })
+    };
+
+    let on_dedup_cancel = {
+        let pending_dedup = pending_dedup.clone();
+        Callback::from(move |_| pending_dedup.set(None))
+    };
+
+    let on_dedup_confirm = {
+        let storage = storage.clone();
+        let state = state.clone();
+        let url_normalizer = url_normalizer.clone();
+        let pending_dedup = pending_dedup.clone();
+
+        Callback::from(move |_| {
+            pending_dedup.set(None);
+            let previous = (*storage).clone();
+            let mut new_storage = previous.clone();
+            let removed = new_storage.dedup_session_tabs(&url_normalizer);
+            console::log_1(&format!("Removed {} duplicate tabs", removed).into());
+
+            if removed > 0 {
+                save_edit(previous, new_storage, &storage, &state);
             }
         })
     };
@@ -247,33 +419,55 @@ pub fn collapsed_viewer() -> Html {
         })
     };
 
-    // Filter sessions by search query
-    let filtered_sessions: Vec<CollapsedSession> = if search_query.is_empty() {
-        storage.sessions.clone()
-    } else {
-        let query = search_query.to_lowercase();
-        storage
-            .sessions
-            .iter()
-            .filter(|session| {
-                session.name.to_lowercase().contains(&query)
-                    || session.tabs.iter().any(|tab| {
-                        tab.url.to_lowercase().contains(&query)
-                            || tab.title.to_lowercase().contains(&query)
-                            || tab.domain.to_lowercase().contains(&query)
+    // Sessions to show, each with its matching tabs while searching. Plain
+    // words go through the full-text index, best hits first; queries with
+    // fields, filters or operators are evaluated tab by tab in stored order.
+    let parsed_query = Query::parse(&search_query);
+    let search_error = parsed_query.as_ref().err().map(|e| e.to_string());
+    let (search_terms, filtered_sessions): (Vec<String>, Vec<SessionMatch>) = match &parsed_query {
+        Ok(query) if *query != Query::All(Vec::new()) => match query.plain_words() {
+            Some(_) => {
+                let mut index = search_index.borrow_mut();
+                index.sync(&storage);
+                let sessions = group_by_session(&index.search(&search_query))
+                    .into_iter()
+                    .filter_map(|hits| {
+                        let session = storage.sessions.iter().find(|session| session.id == hits.session_id)?;
+                        Some((session.clone(), Some(hits.tabs)))
                     })
-            })
-            .cloned()
-            .collect()
+                    .collect();
+                (query_terms(&search_query), sessions)
+            }
+            None => (query.highlight_terms(), filter_sessions(&storage, query, &url_normalizer)),
+        },
+        _ => (
+            Vec::new(),
+            storage.sessions.iter().map(|session| (session.clone(), None)).collect(),
+        ),
     };
 
     html! {
         <div class="container">
             <div class="header">
                 <h1 class="main-title">{"Collapsed Tabs"}</h1>
-                <Button onclick={on_export} variant={ButtonVariant::Secondary}>
-                    {"📥 Export All"}
-                </Button>
+                <div class="header-actions">
+                    <span class="compress-checkbox">
+                        <label>
+                            <input
+                                type="checkbox"
+                                checked={*session_encoding == SessionEncoding::Compressed}
+                                onclick={on_compression_change}
+                            />
+                            {" Compress archives"}
+                        </label>
+                    </span>
+                    <Button onclick={on_dedup} variant={ButtonVariant::Secondary}>
+                        {"🧹 Remove Duplicates"}
+                    </Button>
+                    <Button onclick={on_export} variant={ButtonVariant::Secondary}>
+                        {"📥 Export All"}
+                    </Button>
+                </div>
             </div>
 
             // Status display
@@ -298,15 +492,79 @@ pub fn collapsed_viewer() -> Html {
                 ViewState::Idle => html! {}
             }}
 
+            // Duplicate review
+            if let Some(duplicates) = &*pending_dedup {
+                <div class="plan-review">
+                    if duplicates.is_empty() {
+                        <p class="plan-review-title">{"No duplicate tabs in any session."}</p>
+                    } else {
+                        <p class="plan-review-title">
+                            {format!("Delete {} duplicate saved tabs? Each session keeps one copy of every page.", duplicates.len())}
+                        </p>
+                        <div class="plan-review-list">
+                            {for duplicates.iter().map(|(session_name, tab)| html! {
+                                <div class="plan-review-item" title={tab.url.clone()}>
+                                    <span class="plan-review-item-title">{&tab.title}</span>
+                                    <span class="plan-review-item-reason">{format!("in {}", session_name)}</span>
+                                </div>
+                            })}
+                        </div>
+                    }
+                    <div class="plan-review-actions">
+                        if !duplicates.is_empty() {
+                            <Button onclick={on_dedup_confirm} variant={ButtonVariant::Danger}>
+                                {"Delete"}
+                            </Button>
+                        }
+                        <Button onclick={on_dedup_cancel} variant={ButtonVariant::Secondary}>
+                            {if duplicates.is_empty() { "Close" } else { "Cancel" }}
+                        </Button>
+                    </div>
+                </div>
+            }
+
+            <div class="restore-options">
+                <select class="sort-select" title="Where restored tabs open" onchange={on_restore_target_change}>
+                    {for RestoreTarget::CHOICES.iter().enumerate().map(|(i, target)| html! {
+                        <option value={i.to_string()} selected={*target == restore_options.target}>
+                            {target.label()}
+                        </option>
+                    })}
+                </select>
+                <span class="recreate-groups-checkbox">
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={restore_options.recreate_groups}
+                            onclick={on_recreate_groups_change}
+                        />
+                        {" Recreate tab groups"}
+                    </label>
+                </span>
+                <span class="lazy-restore-checkbox" title="Tabs load when first opened instead of all at once">
+                    <label>
+                        <input
+                            type="checkbox"
+                            checked={restore_options.lazy}
+                            onclick={on_lazy_restore_change}
+                        />
+                        {" Lazy restore"}
+                    </label>
+                </span>
+            </div>
+
             // Search bar
             <div class="search-container">
                 <input
                     type="text"
-                    placeholder="Search sessions, domains, or URLs..."
+                    placeholder="Search sessions, e.g. rust session:\"Sprint 12\" age:>7d"
                     value={(*search_query).clone()}
                     oninput={on_search_input}
                     class="search-input"
                 />
+                if let Some(message) = &search_error {
+                    <div class="search-error">{message}</div>
+                }
             </div>
 
             // Sessions list
@@ -321,12 +579,15 @@ pub fn collapsed_viewer() -> Html {
                 </div>
             } else {
                 <div class="sessions-list">
-                    {for filtered_sessions.iter().map(|session| {
+                    {for filtered_sessions.iter().map(|(session, hits)| {
                         let is_editing = (*editing_session).as_ref() == Some(&session.id);
 
                         html! {
                             <SessionCard
+                                key={session.id.clone()}
                                 session={session.clone()}
+                                hits={hits.clone()}
+                                search_terms={search_terms.clone()}
                                 is_editing={is_editing}
                                 edit_value={(*edit_input_value).clone()}
                                 on_delete={on_delete_session.clone()}
@@ -350,6 +611,37 @@ pub fn collapsed_viewer() -> Html {
                     storage.sessions.len(),
                     storage.sessions.iter().map(|s| s.tabs.len()).sum::<usize>()
                 )}
+                if let Some(stats) = compression_stats.as_ref().filter(|stats| stats.compressed_sessions > 0) {
+                    <div class="footer-compression">{stats.summary()}</div>
+                }
+                if let Some(report) = quota_report.as_ref() {
+                    <details class="footer-usage">
+                        <summary>{format!("Storage: {}", report.usage.summary())}</summary>
+                        <div class="usage-breakdown">
+                            <div class="usage-column">
+                                <h4 class="usage-title">{"Largest sessions"}</h4>
+                                {for report.sessions.iter().take(10).map(|session| html! {
+                                    <div class="usage-row">
+                                        <span class="usage-name">{&session.name}</span>
+                                        <span class="usage-bytes">{format_bytes(session.bytes)}</span>
+                                    </div>
+                                })}
+                            </div>
+                            <div class="usage-column">
+                                <h4 class="usage-title">{"Largest domains"}</h4>
+                                {for report.domains.iter().take(10).map(|domain| html! {
+                                    <div class="usage-row">
+                                        <span class="usage-name">
+                                            <DomainLabel domain={display_domain(&domain.domain)} />
+                                            {format!(" ({})", domain.tabs)}
+                                        </span>
+                                        <span class="usage-bytes">{format_bytes(domain.bytes)}</span>
+                                    </div>
+                                })}
+                            </div>
+                        </div>
+                    </details>
+                }
             </div>
         </div>
     }
@@ -359,6 +651,10 @@ pub fn collapsed_viewer() -> Html {
 #[derive(Properties, PartialEq)]
 struct SessionCardProps {
     session: CollapsedSession,
+    /// Positions of the tabs matching the search, best first; `None` when
+    /// not searching
+    hits: Option<Vec<usize>>,
+    search_terms: Vec<String>,
     is_editing: bool,
     edit_value: String,
     on_delete: Callback<String>,
@@ -389,22 +685,61 @@ fn session_card(props: &SessionCardProps) -> Html {
     for tab in &session.tabs {
         domain_groups
             .entry(tab.domain.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(tab.clone());
     }
 
     let mut domains: Vec<String> = domain_groups.keys().cloned().collect();
     domains.sort();
 
-    let date = js_sys::Date::new(&JsValue::from_f64(session.timestamp));
-    let formatted_date = format!(
-        "{:04}-{:02}-{:02} {:02}:{:02}",
-        date.get_full_year(),
-        date.get_month() + 1,
-        date.get_date(),
-        date.get_hours(),
-        date.get_minutes()
-    );
+    let spoofed_domains = domains
+        .iter()
+        .filter(|domain| display_domain(domain).warning.is_some())
+        .count();
+
+    let formatted_date = format_date(session.timestamp);
+    let highlight = |text: &str| html! {
+        <Highlighted text={text.to_string()} ranges={highlight_ranges(text, &props.search_terms)} />
+    };
+
+    let tab_item = |tab: &SavedTab| {
+        let tab_clone = tab.clone();
+        let session_id = session.id.clone();
+        let tab_url = tab.url.clone();
+
+        html! {
+            <div key={tab.url.clone()} class="tab-item">
+                <div class="tab-content">
+                    <div class="tab-title" title={tab_times(tab)}>
+                        if let Some(icon) = tab.fav_icon_url.as_ref().filter(|icon| !icon.starts_with("chrome://")) {
+                            <img class="tab-favicon" src={icon.clone()} alt="" />
+                        }
+                        {if tab.pinned { "📌 " } else { "" }}
+                        {if tab.muted { "🔇 " } else { "" }}
+                        {highlight(&tab.title)}
+                    </div>
+                    <div class="tab-url">
+                        {highlight(&tab.url)}
+                    </div>
+                </div>
+                <div class="tab-actions">
+                    <Button
+                        onclick={props.on_restore_tab.reform(move |_| tab_clone.clone())}
+                        size={ButtonSize::Small}
+                    >
+                        {"🔄"}
+                    </Button>
+                    <Button
+                        onclick={props.on_delete_tab.reform(move |_| (session_id.clone(), tab_url.clone()))}
+                        variant={ButtonVariant::Danger}
+                        size={ButtonSize::Small}
+                    >
+                        {"✗"}
+                    </Button>
+                </div>
+            </div>
+        }
+    };
 
     html! {
         <div class="session-card">
@@ -441,13 +776,18 @@ fn session_card(props: &SessionCardProps) -> Html {
                                     move |_| (session_id.clone(), name.clone())
                                 })}
                             >
-                                {&session.name}
+                                {highlight(&session.name)}
                             </h3>
                             <span class="edit-icon">{"✏️"}</span>
                         </div>
                     }
                     <p class="session-date">
                         {format!("{} • {} tabs", formatted_date, session.tabs.len())}
+                        if spoofed_domains > 0 {
+                            <span class="spoof-warning" title="Expand to see which domains look like lookalikes">
+                                {format!(" • ⚠️ {} lookalike domain{}", spoofed_domains, if spoofed_domains == 1 { "" } else { "s" })}
+                            </span>
+                        }
                     </p>
                 </div>
 
@@ -488,6 +828,18 @@ fn session_card(props: &SessionCardProps) -> Html {
             </div>
 
             // Expanded tabs list
+            // Matching tabs while searching, best first
+            if let Some(hits) = props.hits.as_ref().filter(|_| !*expanded) {
+                <div class="tabs-container">
+                    <h4 class="domain-title">
+                        {format!("{} matching tab{}", hits.len(), if hits.len() == 1 { "" } else { "s" })}
+                    </h4>
+                    <div class="tabs-list">
+                        {for hits.iter().filter_map(|&i| session.tabs.get(i)).map(&tab_item)}
+                    </div>
+                </div>
+            }
+
             if *expanded {
                 <div class="tabs-container">
                     {for domains.iter().map(|domain| {
@@ -495,43 +847,11 @@ fn session_card(props: &SessionCardProps) -> Html {
                         html! {
                             <div key={domain.clone()} class="domain-group">
                                 <h4 class="domain-title">
-                                    {format!("{} ({})", domain, tabs.len())}
+                                    <DomainLabel domain={display_domain(domain)} />
+                                    {format!(" ({})", tabs.len())}
                                 </h4>
                                 <div class="tabs-list">
-                                    {for tabs.iter().map(|tab| {
-                                        let tab_clone = tab.clone();
-                                        let session_id = session.id.clone();
-                                        let tab_url = tab.url.clone();
-
-                                        html! {
-                                            <div key={tab.url.clone()} class="tab-item">
-                                                <div class="tab-content">
-                                                    <div class="tab-title">
-                                                        {if tab.pinned { "📌 " } else { "" }}
-                                                        {&tab.title}
-                                                    </div>
-                                                    <div class="tab-url">
-                                                        {&tab.url}
-                                                    </div>
-                                                </div>
-                                                <div class="tab-actions">
-                                                    <Button
-                                                        onclick={props.on_restore_tab.reform(move |_| tab_clone.clone())}
-                                                        size={ButtonSize::Small}
-                                                    >
-                                                        {"🔄"}
-                                                    </Button>
-                                                    <Button
-                                                        onclick={props.on_delete_tab.reform(move |_| (session_id.clone(), tab_url.clone()))}
-                                                        variant={ButtonVariant::Danger}
-                                                        size={ButtonSize::Small}
-                                                    >
-                                                        {"✗"}
-                                                    </Button>
-                                                </div>
-                                            </div>
-                                        }
-                                    })}
+                                    {for tabs.iter().map(&tab_item)}
                                 </div>
                             </div>
                         }
@@ -544,39 +864,103 @@ fn session_card(props: &SessionCardProps) -> Html {
 
 // Helper functions
 
-async fn load_storage() -> Result<StorageData, String> {
-    let storage_js = getStorage("tab_hoarder_data")
-        .await
-        .map_err(|e| format!("Failed to get storage: {:?}", e))?;
-
-    if storage_js.is_null() || storage_js.is_undefined() {
-        Ok(StorageData::new())
+/// A session to show, with the positions of its matching tabs while searching
+type SessionMatch = (CollapsedSession, Option<Vec<usize>>);
+
+/// Sessions with saved tabs matching a structured query, in stored order
+fn filter_sessions(
+    storage: &StorageData,
+    query: &Query,
+    normalizer: &UrlNormalizer,
+) -> Vec<SessionMatch> {
+    // A tab is a duplicate when the same page is saved anywhere else
+    let duplicates = if query.uses_duplicates() {
+        duplicate_urls(
+            storage.sessions.iter().flat_map(|session| session.tabs.iter().map(|tab| tab.url.as_str())),
+            normalizer,
+        )
     } else {
-        serde_wasm_bindgen::from_value(storage_js)
-            .map_err(|e| format!("Failed to parse storage: {:?}", e))
-    }
-}
+        Default::default()
+    };
+    let options = MatchOptions {
+        case_sensitive: false,
+        now: js_sys::Date::now(),
+    };
 
-async fn save_storage(storage: &StorageData) -> Result<(), String> {
-    let storage_js = serde_wasm_bindgen::to_value(storage)
-        .map_err(|e| format!("Failed to serialize storage: {:?}", e))?;
+    storage
+        .sessions
+        .iter()
+        .filter_map(|session| {
+            let tabs: Vec<usize> = session
+                .tabs
+                .iter()
+                .enumerate()
+                .filter(|(_, tab)| {
+                    let duplicate = !duplicates.is_empty() && duplicates.contains(&normalizer.normalize(&tab.url));
+                    query.matches(&Candidate::saved_tab(tab, session).with_duplicate(duplicate), options)
+                })
+                .map(|(i, _)| i)
+                .collect();
+            (!tabs.is_empty()).then(|| (session.clone(), Some(tabs)))
+        })
+        .collect()
+}
 
-    setStorage("tab_hoarder_data", storage_js)
-        .await
-        .map_err(|e| format!("Failed to save storage: {:?}", e))
+/// Milliseconds since the epoch as local "YYYY-MM-DD HH:MM"
+fn format_date(timestamp: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(timestamp));
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date(),
+        date.get_hours(),
+        date.get_minutes()
+    )
 }
 
-async fn restore_session_tabs(tabs: &[SavedTab], state: UseStateHandle<ViewState>) -> Result<(), String> {
-    let progress_callback = Closure::wrap(Box::new(move |progress: u8| {
-        state.set(ViewState::Restoring(progress, "Restoring tabs...".to_string()));
-    }) as Box<dyn Fn(u8)>);
+/// Tooltip with when a tab was last used and saved, for tabs that have it
+fn tab_times(tab: &SavedTab) -> Option<String> {
+    let mut lines = Vec::new();
+    if let Some(last_accessed) = tab.last_accessed {
+        lines.push(format!("Last used {}", format_date(last_accessed)));
+    }
+    if let Some(saved_at) = tab.saved_at {
+        lines.push(format!("Saved {}", format_date(saved_at)));
+    }
+    if let Some(group) = tab.group.as_ref().filter(|group| !group.title.is_empty()) {
+        lines.push(format!("Group \"{}\"", group.title));
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
 
-    let tabs_js = serde_wasm_bindgen::to_value(tabs)
-        .map_err(|e| format!("Failed to serialize tabs: {:?}", e))?;
+/// Show `edited` right away and save it over `previous` in the background
+fn save_edit(
+    previous: StorageData,
+    edited: StorageData,
+    storage: &UseStateHandle<StorageData>,
+    state: &UseStateHandle<ViewState>,
+) {
+    storage.set(edited.clone());
+    let storage = storage.clone();
+    let state = state.clone();
+    spawn_local(async move {
+        match ChromeStorage.save_sessions(&previous, &edited).await {
+            // May include sessions another page saved meanwhile
+            Ok(saved) => storage.set(saved),
+            Err(e) => state.set(ViewState::Error(format!("Failed to save: {}", e))),
+        }
+    });
+}
 
-    restoreTabs(tabs_js, progress_callback.as_ref().unchecked_ref())
+async fn restore_session_tabs(
+    tabs: &[SavedTab],
+    options: RestoreOptions,
+    state: UseStateHandle<ViewState>,
+) -> Result<(), String> {
+    ChromeTabs
+        .restore_tabs(&plan_restore(tabs, options), move |progress: u8| {
+            state.set(ViewState::Restoring(progress, "Restoring tabs...".to_string()));
+        })
         .await
-        .map_err(|e| format!("Restore failed: {:?}", e))?;
-
-    Ok(())
 }
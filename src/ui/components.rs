@@ -1,6 +1,8 @@
-/// Reusable UI components
+//! Reusable UI components
 
 use yew::prelude::*;
+use crate::domain::DisplayDomain;
+use std::ops::Range;
 
 #[derive(Properties, PartialEq)]
 pub struct ProgressBarProps {
@@ -48,19 +50,14 @@ pub struct ButtonProps {
     pub variant: ButtonVariant,
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Default)]
 pub enum ButtonVariant {
+    #[default]
     Primary,
     Secondary,
     Danger,
 }
 
-impl Default for ButtonVariant {
-    fn default() -> Self {
-        ButtonVariant::Primary
-    }
-}
-
 #[function_component(Button)]
 pub fn button(props: &ButtonProps) -> Html {
     let base_style = "padding: 10px 20px; border: none; border-radius: 4px; font-size: 14px; cursor: pointer; font-weight: 500; transition: all 0.2s;";
@@ -97,20 +94,15 @@ pub struct AlertProps {
     pub alert_type: AlertType,
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Default)]
 pub enum AlertType {
+    #[default]
     Info,
     Success,
     Warning,
     Error,
 }
 
-impl Default for AlertType {
-    fn default() -> Self {
-        AlertType::Info
-    }
-}
-
 #[function_component(Alert)]
 pub fn alert(props: &AlertProps) -> Html {
     let (bg_color, border_color) = match props.alert_type {
@@ -126,3 +118,55 @@ pub fn alert(props: &AlertProps) -> Html {
         </div>
     }
 }
+
+#[derive(Properties, PartialEq)]
+pub struct DomainLabelProps {
+    pub domain: DisplayDomain,
+}
+
+/// Unicode form of a domain, with a warning badge when it looks spoofed
+#[function_component(DomainLabel)]
+pub fn domain_label(props: &DomainLabelProps) -> Html {
+    let domain = &props.domain;
+
+    html! {
+        <>
+            {&domain.unicode}
+            if let Some(warning) = &domain.warning {
+                <span
+                    class="spoof-warning"
+                    title={format!("Possible lookalike domain: {} ({})", warning.message(), domain.ascii)}
+                >
+                    {" ⚠️"}
+                </span>
+            }
+        </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct HighlightedProps {
+    pub text: String,
+    /// Byte ranges of `text` to mark, in order and not overlapping
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// Text with search matches wrapped in `<mark>`
+#[function_component(Highlighted)]
+pub fn highlighted(props: &HighlightedProps) -> Html {
+    let text = &props.text;
+    let mut parts = Vec::new();
+    let mut last = 0;
+
+    for range in &props.ranges {
+        if range.start < last || range.end > text.len() {
+            continue;
+        }
+        parts.push(html! { {&text[last..range.start]} });
+        parts.push(html! { <mark class="search-match">{&text[range.clone()]}</mark> });
+        last = range.end;
+    }
+    parts.push(html! { {&text[last..]} });
+
+    html! { <>{for parts}</> }
+}
@@ -1,4 +1,4 @@
-/// UI module - Yew components for Tab Hoarder
+//! UI module - Yew components for Tab Hoarder
 
 pub mod popup;
 pub mod collapsed;
@@ -1,49 +1,48 @@
-/// Popup UI for Tab Hoarder extension
+//! Popup UI for Tab Hoarder extension
 
 use yew::prelude::*;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{console, InputEvent, MouseEvent};
+use web_sys::{console, InputEvent, KeyboardEvent, MouseEvent};
 use patternfly_yew::prelude::*;
-use crate::domain::{count_domains, get_top_domains};
-use crate::operations::{sort_tabs_by_domain, make_tabs_unique};
-use crate::tab_data::TabInfo;
-use crate::storage::StorageData;
-use crate::tab_data::SavedTab;
-use uuid::Uuid;
+use crate::domain::{count_domains, display_domain, get_top_domains, set_suffix_mode};
+use crate::ui::components::{DomainLabel, Highlighted};
+use crate::public_suffix::SuffixMode;
+use crate::actions;
+use crate::operations::{plan_collapse, plan_remove_duplicates, GroupHandling, KeepPolicy, NoDomainPlacement, OperationPlan, PlannedOperation, SortPreset};
+use crate::tab_data::{CollapsedSession, TabInfo};
+use crate::storage::{keys, ChromeStorage, QuotaPolicy, RoomPlan, StorageData, StorageRepository};
+use crate::tabs::{ChromeTabs, TabBackend, TabUpdate};
+use crate::fuzzy::{match_fields, match_ranges};
+use crate::query::{duplicate_urls, Candidate, MatchOptions, Query};
+use crate::search_index::highlight_ranges;
+use crate::switcher::{Switcher, SwitcherCommand, SwitcherKey};
+use crate::undo::{UndoAction, UndoJournal};
+use crate::url_normalizer::UrlNormalizer;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
 
 // Import JS bridge functions
 #[wasm_bindgen(module = "/popup.js")]
 extern "C" {
     #[wasm_bindgen(catch)]
-    async fn getCurrentWindowTabs() -> Result<JsValue, JsValue>;
+    async fn requestPermission(permission: &str) -> Result<JsValue, JsValue>;
 
     #[wasm_bindgen(catch)]
-    async fn sortTabsByDomain(tab_ids: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn removeTabs(tab_ids: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn closeTabs(tab_ids: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn activateTab(tab_id: i32) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn closeTab(tab_id: i32) -> Result<(), JsValue>;
-
-    #[wasm_bindgen(catch)]
-    async fn getStorage(key: &str) -> Result<JsValue, JsValue>;
+    async fn openCollapsedViewer() -> Result<(), JsValue>;
 
     #[wasm_bindgen(catch)]
-    async fn setStorage(key: &str, value: JsValue) -> Result<(), JsValue>;
+    async fn bookmarkTabs(folder_title: &str, tabs: JsValue, progress_callback: &js_sys::Function) -> Result<(), JsValue>;
 
     #[wasm_bindgen(catch)]
-    async fn getStorageQuota() -> Result<JsValue, JsValue>;
+    async fn copyText(text: &str) -> Result<(), JsValue>;
+}
 
+#[wasm_bindgen(module = "/files.js")]
+extern "C" {
     #[wasm_bindgen(catch)]
-    async fn openCollapsedViewer() -> Result<(), JsValue>;
+    async fn downloadFile(data: &str, filename: &str) -> Result<(), JsValue>;
 }
 
 #[derive(Clone, PartialEq)]
@@ -65,22 +64,94 @@ enum ActiveTab {
     Search,
     SortUnique,
     Archive,
+    History,
     Analyze,
 }
 
+/// What can be done with the tabs selected in the Search tab
+#[derive(Clone, Copy, PartialEq)]
+enum BulkAction {
+    Close,
+    Collapse,
+    MoveToNewWindow,
+    Pin,
+    Unpin,
+    Group,
+    Bookmark,
+    CopyUrls,
+}
+
+impl BulkAction {
+    const ALL: [BulkAction; 8] = [
+        BulkAction::Close,
+        BulkAction::Collapse,
+        BulkAction::MoveToNewWindow,
+        BulkAction::Pin,
+        BulkAction::Unpin,
+        BulkAction::Group,
+        BulkAction::Bookmark,
+        BulkAction::CopyUrls,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            BulkAction::Close => "Close",
+            BulkAction::Collapse => "Collapse",
+            BulkAction::MoveToNewWindow => "New window",
+            BulkAction::Pin => "Pin",
+            BulkAction::Unpin => "Unpin",
+            BulkAction::Group => "Group",
+            BulkAction::Bookmark => "Bookmark",
+            BulkAction::CopyUrls => "Copy URLs",
+        }
+    }
+
+    fn progress_message(self) -> &'static str {
+        match self {
+            BulkAction::Close | BulkAction::Collapse => "Closing tabs...",
+            BulkAction::MoveToNewWindow => "Moving tabs...",
+            BulkAction::Pin | BulkAction::Unpin => "Updating tabs...",
+            BulkAction::Group => "Grouping tabs...",
+            BulkAction::Bookmark => "Bookmarking tabs...",
+            BulkAction::CopyUrls => "Copying URLs...",
+        }
+    }
+}
+
 #[function_component(App)]
 pub fn app() -> Html {
     let state = use_state(|| AppState::Idle);
-    let domain_stats = use_state(|| Vec::<DomainStat>::new());
+    let domain_stats = use_state(Vec::<DomainStat>::new);
     let storage_warning = use_state(|| None::<String>);
     let is_domains_expanded = use_state(|| false);
     let active_tab = use_state(|| ActiveTab::Search);
+    let include_private_suffixes = use_state(|| true);
+    let no_domain_first = use_state(|| false);
+    let sort_preset = use_state(|| SortPreset::DomainThenUrl);
+    let sort_reverse = use_state(|| false);
+    let groups_as_units = use_state(|| false);
+    let url_normalizer = use_state(UrlNormalizer::default);
+    let keep_policy = use_state(KeepPolicy::default);
+    // Plan awaiting review before Make Tabs Unique / Collapse Tabs run
+    let pending_plan = use_state(|| None::<OperationPlan>);
+    // Whether a pending collapse fits in storage, and how room is made
+    let collapse_room = use_state(|| None::<Result<RoomPlan, String>>);
+    let quota_policy = use_state(QuotaPolicy::default);
+    let close_duplicates = use_state(|| false);
+    // Report of a collapse a previous popup did not finish
+    let recovery_notice = use_state(|| None::<String>);
+    let undo_journal = use_state(UndoJournal::default);
 
     // Search tab state
-    let search_tabs = use_state(|| Vec::<TabInfo>::new());
-    let search_query = use_state(|| String::new());
+    let search_tabs = use_state(Vec::<TabInfo>::new);
+    let search_query = use_state(String::new);
     let use_regex = use_state(|| false);
     let use_case_insensitive = use_state(|| true); // New: case-insensitive option (default true)
+    // Keyboard cursor and Shift selection over the results
+    let switcher = use_state(Switcher::default);
+    let search_input_ref = use_node_ref();
+    // Session, group or bookmark folder name for bulk actions
+    let bulk_name = use_state(String::new);
 
     // Load search preferences from storage on mount
     {
@@ -90,23 +161,74 @@ pub fn app() -> Html {
         use_effect_with((), move |_| {
             spawn_local(async move {
                 // Load search query
-                if let Ok(query_js) = getStorage("search_query").await {
-                    if let Ok(query) = serde_wasm_bindgen::from_value::<String>(query_js) {
-                        search_query.set(query);
-                    }
+                if let Ok(Some(query)) = ChromeStorage.get(keys::SEARCH_QUERY).await {
+                    search_query.set(query);
                 }
                 // Load regex preference
-                if let Ok(regex_js) = getStorage("search_use_regex").await {
-                    if let Ok(use_regex_val) = serde_wasm_bindgen::from_value::<bool>(regex_js) {
-                        use_regex.set(use_regex_val);
-                    }
+                if let Ok(Some(use_regex_val)) = ChromeStorage.get(keys::SEARCH_USE_REGEX).await {
+                    use_regex.set(use_regex_val);
                 }
                 // Load case-insensitive preference
-                if let Ok(case_js) = getStorage("search_case_insensitive").await {
-                    if let Ok(case_val) = serde_wasm_bindgen::from_value::<bool>(case_js) {
-                        use_case_insensitive.set(case_val);
-                    }
+                if let Ok(Some(case_val)) = ChromeStorage.get(keys::SEARCH_CASE_INSENSITIVE).await {
+                    use_case_insensitive.set(case_val);
+                }
+            });
+            || ()
+        });
+    }
+
+    // Load domain grouping preference from storage on mount
+    {
+        let include_private_suffixes = include_private_suffixes.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                if let Ok(Some(private_val)) = ChromeStorage.get(keys::DOMAIN_INCLUDE_PRIVATE_SUFFIXES).await {
+                    set_suffix_mode(suffix_mode_for(private_val));
+                    include_private_suffixes.set(private_val);
+                }
+            });
+            || ()
+        });
+    }
+
+    // Load sort preferences from storage on mount
+    {
+        let no_domain_first = no_domain_first.clone();
+        let sort_preset = sort_preset.clone();
+        let sort_reverse = sort_reverse.clone();
+        let groups_as_units = groups_as_units.clone();
+        let url_normalizer = url_normalizer.clone();
+        let keep_policy = keep_policy.clone();
+        let quota_policy = quota_policy.clone();
+        let close_duplicates = close_duplicates.clone();
+        let undo_journal = undo_journal.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                if let Ok(Some(first_val)) = ChromeStorage.get(keys::SORT_NO_DOMAIN_FIRST).await {
+                    no_domain_first.set(first_val);
+                }
+                if let Ok(Some(preset_val)) = ChromeStorage.get(keys::SORT_PRESET).await {
+                    sort_preset.set(preset_val);
                 }
+                if let Ok(Some(reverse_val)) = ChromeStorage.get(keys::SORT_REVERSE).await {
+                    sort_reverse.set(reverse_val);
+                }
+                if let Ok(Some(units_val)) = ChromeStorage.get(keys::SORT_GROUPS_AS_UNITS).await {
+                    groups_as_units.set(units_val);
+                }
+                if let Ok(Some(normalizer_val)) = ChromeStorage.get(keys::URL_NORMALIZER).await {
+                    url_normalizer.set(normalizer_val);
+                }
+                if let Ok(Some(policy_val)) = ChromeStorage.get(keys::DEDUP_KEEP_POLICY).await {
+                    keep_policy.set(policy_val);
+                }
+                if let Ok(Some(quota_val)) = ChromeStorage.get(keys::QUOTA_POLICY).await {
+                    quota_policy.set(quota_val);
+                }
+                if let Ok(Some(close_val)) = ChromeStorage.get(keys::COLLAPSE_CLOSE_DUPLICATES).await {
+                    close_duplicates.set(close_val);
+                }
+                undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
             });
             || ()
         });
@@ -117,20 +239,49 @@ pub fn app() -> Html {
         let storage_warning = storage_warning.clone();
         use_effect_with((), move |_| {
             spawn_local(async move {
-                if let Ok(quota_js) = getStorageQuota().await {
-                    if let Ok(quota) = serde_wasm_bindgen::from_value::<serde_json::Value>(quota_js) {
-                        if let Some(percent) = quota.get("percentUsed").and_then(|v| v.as_u64()) {
-                            if percent >= 90 {
-                                storage_warning.set(Some(format!("Storage {}% full!", percent)));
-                            }
-                        }
-                    }
+                if let Ok(usage) = ChromeStorage.usage().await
+                    && usage.nearly_full()
+                {
+                    storage_warning.set(Some(format!("Storage {}% full!", usage.percent_used())));
                 }
             });
             || ()
         });
     }
 
+    // Report a collapse that was cut short last time (e.g. the popup
+    // closed while tabs were closing)
+    {
+        let recovery_notice = recovery_notice.clone();
+        use_effect_with((), move |_| {
+            spawn_local(async move {
+                match actions::check_interrupted_collapse(&ChromeTabs, &ChromeStorage).await {
+                    Ok(Some(interrupted)) => recovery_notice.set(Some(interrupted.message())),
+                    Ok(None) => {}
+                    Err(e) => console::log_1(&format!("Failed to check for an interrupted collapse: {}", e).into()),
+                }
+            });
+            || ()
+        });
+    }
+
+    // Predict whether a collapse under review fits in storage
+    {
+        let collapse_room = collapse_room.clone();
+        let plan = (*pending_plan).clone();
+        let checked_tabs = plan.as_ref().map(OperationPlan::tabs_to_close);
+        use_effect_with((checked_tabs, *quota_policy), move |_| {
+            collapse_room.set(None);
+            if let Some(plan) = plan.filter(|plan| plan.operation == PlannedOperation::Collapse) {
+                spawn_local(async move {
+                    let room = actions::predict_collapse(&ChromeStorage, &plan, js_sys::Date::now()).await;
+                    collapse_room.set(Some(room));
+                });
+            }
+            || ()
+        });
+    }
+
     // Load tabs when Search tab is selected
     {
         let search_tabs = search_tabs.clone();
@@ -138,12 +289,8 @@ pub fn app() -> Html {
             if **tab == ActiveTab::Search {
                 spawn_local(async move {
                     // Load tabs from Chrome (don't reset search query - it persists)
-                    if let Ok(tabs_js) = getCurrentWindowTabs().await {
-                        if let Ok(mut tabs) = serde_wasm_bindgen::from_value::<Vec<TabInfo>>(tabs_js) {
-                            // Sort by tab index to maintain Chrome's tab order
-                            tabs.sort_by_key(|t| t.index);
-                            search_tabs.set(tabs);
-                        }
+                    if let Ok(tabs) = ChromeTabs.query().await {
+                        search_tabs.set(tabs);
                     }
                 });
             }
@@ -151,6 +298,35 @@ pub fn app() -> Html {
         });
     }
 
+    // Focus the search box whenever the Search tab shows, so typing and the
+    // arrow keys work straight away (also when opened with the shortcut)
+    {
+        let search_input_ref = search_input_ref.clone();
+        use_effect_with(active_tab.clone(), move |tab| {
+            if **tab == ActiveTab::Search
+                && let Some(input) = search_input_ref.cast::<web_sys::HtmlInputElement>()
+            {
+                let _ = input.focus();
+            }
+            || ()
+        });
+    }
+
+    // Keep the row under the keyboard cursor in view
+    {
+        use_effect_with(switcher.cursor, move |_| {
+            let row = web_sys::window()
+                .and_then(|window| window.document())
+                .and_then(|document| document.query_selector(".tab-item-cursor").ok().flatten());
+            if let Some(row) = row {
+                let options = web_sys::ScrollIntoViewOptions::new();
+                options.set_block(web_sys::ScrollLogicalPosition::Nearest);
+                row.scroll_into_view_with_scroll_into_view_options(&options);
+            }
+            || ()
+        });
+    }
+
     // Analyze domains handler
     let on_analyze = {
         let state = state.clone();
@@ -172,7 +348,7 @@ pub fn app() -> Html {
             state.set(AppState::Loading("Analyzing domains...".to_string()));
 
             spawn_local(async move {
-                match get_current_tabs().await {
+                match ChromeTabs.query().await {
                     Ok(tabs) => {
                         let urls: Vec<String> = tabs.iter().map(|t| t.url.clone()).collect();
                         let counts = count_domains(&urls);
@@ -195,65 +371,251 @@ pub fn app() -> Html {
         })
     };
 
+    // Analyze tab: Handle private suffix checkbox change
+    let on_private_suffixes_change = {
+        let include_private_suffixes = include_private_suffixes.clone();
+        let domain_stats = domain_stats.clone();
+        let is_domains_expanded = is_domains_expanded.clone();
+        Callback::from(move |_: MouseEvent| {
+            let new_value = !*include_private_suffixes;
+            include_private_suffixes.set(new_value);
+            set_suffix_mode(suffix_mode_for(new_value));
+            // Stats were computed with the old grouping
+            domain_stats.set(Vec::new());
+            is_domains_expanded.set(false);
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::DOMAIN_INCLUDE_PRIVATE_SUFFIXES, &new_value).await;
+            });
+        })
+    };
+
+    // Sort/unique tab: Handle "no domain first" checkbox change
+    let on_no_domain_first_change = {
+        let no_domain_first = no_domain_first.clone();
+        Callback::from(move |_: MouseEvent| {
+            let new_value = !*no_domain_first;
+            no_domain_first.set(new_value);
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::SORT_NO_DOMAIN_FIRST, &new_value).await;
+            });
+        })
+    };
+
+    // Sort/unique tab: Handle sort strategy selection
+    let on_sort_preset_change = {
+        let sort_preset = sort_preset.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            if let Some(preset) = select
+                .value()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| SortPreset::ALL.get(i).copied())
+            {
+                sort_preset.set(preset);
+                // Save to storage
+                spawn_local(async move {
+                    let _ = ChromeStorage.set(keys::SORT_PRESET, &preset).await;
+                });
+            }
+        })
+    };
+
+    // Sort/unique tab: Handle duplicate keep policy selection
+    let on_keep_policy_change = {
+        let keep_policy = keep_policy.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            if let Some(policy) = select
+                .value()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| KeepPolicy::CHOICES.get(i).copied())
+            {
+                keep_policy.set(policy);
+                // Save to storage
+                spawn_local(async move {
+                    let _ = ChromeStorage.set(keys::DEDUP_KEEP_POLICY, &policy).await;
+                });
+            }
+        })
+    };
+
+    // Archive tab: Handle "close duplicates" checkbox change
+    let on_close_duplicates_change = {
+        let close_duplicates = close_duplicates.clone();
+        Callback::from(move |_: MouseEvent| {
+            let new_value = !*close_duplicates;
+            close_duplicates.set(new_value);
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::COLLAPSE_CLOSE_DUPLICATES, &new_value).await;
+            });
+        })
+    };
+
+    // Archive tab: Handle full-storage policy selection
+    let on_quota_policy_change = {
+        let quota_policy = quota_policy.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            if let Some(policy) = select
+                .value()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| QuotaPolicy::CHOICES.get(i).copied())
+            {
+                let quota_policy = quota_policy.clone();
+                spawn_local(async move {
+                    // Archives are saved with chrome.downloads; without it,
+                    // keep the previous policy
+                    if policy == QuotaPolicy::ArchiveOldest && !request_permission("downloads").await {
+                        select.set_value(&QuotaPolicy::CHOICES.iter().position(|p| *p == *quota_policy).unwrap_or(0).to_string());
+                        return;
+                    }
+                    quota_policy.set(policy);
+                    // Save to storage
+                    let _ = ChromeStorage.set(keys::QUOTA_POLICY, &policy).await;
+                });
+            }
+        })
+    };
+
+    // Sort/unique tab: Handle reverse checkbox change
+    let on_sort_reverse_change = {
+        let sort_reverse = sort_reverse.clone();
+        Callback::from(move |_: MouseEvent| {
+            let new_value = !*sort_reverse;
+            sort_reverse.set(new_value);
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::SORT_REVERSE, &new_value).await;
+            });
+        })
+    };
+
+    // Sort/unique tab: Handle "groups as units" checkbox change
+    let on_groups_as_units_change = {
+        let groups_as_units = groups_as_units.clone();
+        Callback::from(move |_: MouseEvent| {
+            let new_value = !*groups_as_units;
+            groups_as_units.set(new_value);
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::SORT_GROUPS_AS_UNITS, &new_value).await;
+            });
+        })
+    };
+
+    // Sort/unique tab: Update and persist a duplicate-matching rule
+    let on_normalizer_change = {
+        let url_normalizer = url_normalizer.clone();
+        move |update: fn(&mut UrlNormalizer)| {
+            let url_normalizer = url_normalizer.clone();
+            Callback::from(move |_: MouseEvent| {
+                let mut new_value = (*url_normalizer).clone();
+                update(&mut new_value);
+                url_normalizer.set(new_value.clone());
+                // Save to storage
+                spawn_local(async move {
+                    let _ = ChromeStorage.set(keys::URL_NORMALIZER, &new_value).await;
+                });
+            })
+        }
+    };
+
+    // Sort/unique tab: Handle tracking parameter list edits
+    let on_tracking_params_change = {
+        let url_normalizer = url_normalizer.clone();
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            let mut new_value = (*url_normalizer).clone();
+            new_value.tracking_params.clear();
+            for name in input.value().split(',') {
+                new_value.add_tracking_param(name);
+            }
+            url_normalizer.set(new_value.clone());
+            // Save to storage
+            spawn_local(async move {
+                let _ = ChromeStorage.set(keys::URL_NORMALIZER, &new_value).await;
+            });
+        })
+    };
+
+    let group_handling = if *groups_as_units {
+        GroupHandling::GroupsAsUnits
+    } else {
+        GroupHandling::WithinGroups
+    };
+
+    let placement = if *no_domain_first {
+        NoDomainPlacement::Start
+    } else {
+        NoDomainPlacement::End
+    };
+
     // Sort tabs handler
     let on_sort = {
         let state = state.clone();
+        let undo_journal = undo_journal.clone();
+        let strategy = {
+            let strategy = sort_preset.strategy().with_no_domain_placement(placement);
+            if *sort_reverse { strategy.reversed() } else { strategy }
+        };
 
         Callback::from(move |_| {
             let state = state.clone();
+            let undo_journal = undo_journal.clone();
+            let strategy = strategy.clone();
 
             state.set(AppState::Loading("Sorting tabs...".to_string()));
 
             spawn_local(async move {
-                match get_current_tabs().await {
-                    Ok(tabs) => {
-                        let sorted = sort_tabs_by_domain(&tabs);
-                        let tab_ids: Vec<i32> = sorted.iter().map(|t| t.id).collect();
-
-                        match sort_tabs_with_progress(tab_ids, state.clone()).await {
-                            Ok(_) => {
-                                state.set(AppState::Idle);
-                            }
-                            Err(e) => {
-                                state.set(AppState::Error(format!("Sort failed: {}", e)));
-                            }
-                        }
+                let progress_state = state.clone();
+                let progress = move |progress: u8| {
+                    progress_state.set(AppState::Processing(progress, "Sorting tabs...".to_string()));
+                };
+
+                let result = actions::sort_tabs(&ChromeTabs, &ChromeStorage, &strategy, group_handling, js_sys::Date::now(), progress).await;
+                undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
+                match result {
+                    Ok(_) => {
+                        state.set(AppState::Idle);
                     }
                     Err(e) => {
-                        state.set(AppState::Error(format!("Failed to get tabs: {}", e)));
+                        state.set(AppState::Error(format!("Sort failed: {}", e)));
                     }
                 }
             });
         })
     };
 
-    // Make unique handler
+    // Make unique handler: build a plan for review
     let on_unique = {
         let state = state.clone();
+        let pending_plan = pending_plan.clone();
+        let normalizer = (*url_normalizer).clone();
+        let policy = *keep_policy;
 
         Callback::from(move |_| {
             let state = state.clone();
+            let pending_plan = pending_plan.clone();
+            let normalizer = normalizer.clone();
 
-            state.set(AppState::Loading("Removing duplicates...".to_string()));
+            state.set(AppState::Loading("Finding duplicates...".to_string()));
 
             spawn_local(async move {
-                match get_current_tabs().await {
+                match ChromeTabs.query().await {
                     Ok(tabs) => {
-                        let (_, remove_ids) = make_tabs_unique(&tabs);
-
-                        if remove_ids.is_empty() {
-                            state.set(AppState::Idle);
+                        let plan = plan_remove_duplicates(&tabs, &normalizer, policy);
+                        if plan.is_empty() {
                             console::log_1(&"No duplicates found".into());
                         } else {
-                            match remove_tabs_with_progress(remove_ids, state.clone()).await {
-                                Ok(_) => {
-                                    state.set(AppState::Idle);
-                                }
-                                Err(e) => {
-                                    state.set(AppState::Error(format!("Remove failed: {}", e)));
-                                }
-                            }
+                            pending_plan.set(Some(plan));
                         }
+                        state.set(AppState::Idle);
                     }
                     Err(e) => {
                         state.set(AppState::Error(format!("Failed to get tabs: {}", e)));
@@ -263,67 +625,31 @@ pub fn app() -> Html {
         })
     };
 
-    // Collapse tabs handler
+    // Collapse tabs handler: build a plan for review
     let on_collapse = {
         let state = state.clone();
+        let pending_plan = pending_plan.clone();
+        let normalizer = (*url_normalizer).clone();
+        let policy = *keep_policy;
+        let close_duplicates = *close_duplicates;
 
         Callback::from(move |_| {
             let state = state.clone();
+            let pending_plan = pending_plan.clone();
+            let normalizer = normalizer.clone();
 
-            state.set(AppState::Loading("Collapsing tabs...".to_string()));
+            state.set(AppState::Loading("Preparing collapse...".to_string()));
 
             spawn_local(async move {
-                match get_current_tabs().await {
+                match ChromeTabs.query().await {
                     Ok(tabs) => {
-                        // Sort and make unique before collapsing
-                        let sorted = sort_tabs_by_domain(&tabs);
-                        let (unique_tabs, _) = make_tabs_unique(&sorted);
-
-                        // Create session
-                        let session_id = Uuid::new_v4().to_string();
-                        let now = js_sys::Date::now();
-                        let date = js_sys::Date::new(&JsValue::from_f64(now));
-                        let name = format!("Session {}", format_date(&date));
-
-                        let saved_tabs: Vec<SavedTab> = unique_tabs.iter().filter_map(|tab| {
-                            match crate::domain::extract_domain(&tab.url) {
-                                Some(domain) => {
-                                    Some(SavedTab {
-                                        url: tab.url.clone(),
-                                        title: tab.title.clone(),
-                                        domain,
-                                        pinned: tab.pinned,
-                                    })
-                                },
-                                None => None,
-                            }
-                        }).collect();
-
-                        let session = crate::tab_data::CollapsedSession {
-                            id: session_id,
-                            name,
-                            timestamp: now,
-                            tabs: saved_tabs,
-                        };
-
-                        // Save to storage
-                        match save_session(session).await {
-                            Ok(_) => {
-                                // Close tabs
-                                let tab_ids: Vec<i32> = unique_tabs.iter().map(|t| t.id).collect();
-                                match close_tabs_with_progress(tab_ids, state.clone()).await {
-                                    Ok(_) => {
-                                        state.set(AppState::Idle);
-                                    }
-                                    Err(e) => {
-                                        state.set(AppState::Error(format!("Failed to close: {}", e)));
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                state.set(AppState::Error(format!("Failed to save: {}", e)));
-                            }
+                        let now = js_sys::Date::new(&JsValue::from_f64(js_sys::Date::now()));
+                        let name = format!("Session {}", format_date(&now));
+                        let plan = plan_collapse(&tabs, &normalizer, policy, placement, close_duplicates, &name);
+                        if !plan.is_empty() {
+                            pending_plan.set(Some(plan));
                         }
+                        state.set(AppState::Idle);
                     }
                     Err(e) => {
                         state.set(AppState::Error(format!("Failed to get tabs: {}", e)));
@@ -333,6 +659,126 @@ pub fn app() -> Html {
         })
     };
 
+    // Plan review: check/uncheck a single tab
+    let on_plan_toggle = {
+        let pending_plan = pending_plan.clone();
+        move |tab_id: i32| {
+            let pending_plan = pending_plan.clone();
+            Callback::from(move |_: MouseEvent| {
+                if let Some(mut plan) = (*pending_plan).clone() {
+                    plan.toggle(tab_id);
+                    pending_plan.set(Some(plan));
+                }
+            })
+        }
+    };
+
+    // Plan review: check/uncheck every tab
+    let on_plan_set_all = {
+        let pending_plan = pending_plan.clone();
+        move |included: bool| {
+            let pending_plan = pending_plan.clone();
+            Callback::from(move |_: MouseEvent| {
+                if let Some(mut plan) = (*pending_plan).clone() {
+                    plan.set_all(included);
+                    pending_plan.set(Some(plan));
+                }
+            })
+        }
+    };
+
+    // Plan review: discard the plan without touching any tab
+    let on_plan_cancel = {
+        let pending_plan = pending_plan.clone();
+        Callback::from(move |_: MouseEvent| {
+            pending_plan.set(None);
+        })
+    };
+
+    // Plan review: run the checked items
+    let on_plan_confirm = {
+        let state = state.clone();
+        let pending_plan = pending_plan.clone();
+        let undo_journal = undo_journal.clone();
+
+        Callback::from(move |_: MouseEvent| {
+            let Some(plan) = (*pending_plan).clone() else {
+                return;
+            };
+            let state = state.clone();
+            let undo_journal = undo_journal.clone();
+            pending_plan.set(None);
+
+            spawn_local(async move {
+                let message = match plan.operation {
+                    PlannedOperation::RemoveDuplicates => "Removing duplicates...",
+                    PlannedOperation::Collapse => "Closing tabs...",
+                };
+                let progress_state = state.clone();
+                let progress = move |progress: u8| {
+                    progress_state.set(AppState::Processing(progress, message.to_string()));
+                };
+
+                let result =
+                    actions::run_plan(&ChromeTabs, &ChromeStorage, &plan, js_sys::Date::now(), archive_sessions, progress)
+                        .await;
+                undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
+                if let Err(e) = result {
+                    state.set(AppState::Error(e));
+                } else {
+                    state.set(AppState::Idle);
+                }
+            });
+        })
+    };
+
+    // History tab: revert one entry (the newest when `id` is None)
+    let on_revert = {
+        let state = state.clone();
+        let undo_journal = undo_journal.clone();
+        move |id: Option<String>| {
+            let state = state.clone();
+            let undo_journal = undo_journal.clone();
+            Callback::from(move |_: MouseEvent| {
+                let state = state.clone();
+                let undo_journal = undo_journal.clone();
+                let id = id.clone();
+
+                spawn_local(async move {
+                    let journal = actions::load_undo_journal(&ChromeStorage).await;
+                    let entry = match &id {
+                        Some(id) => journal.entries.iter().find(|entry| &entry.id == id).cloned(),
+                        None => journal.last().cloned(),
+                    };
+                    let Some(entry) = entry else {
+                        undo_journal.set(journal);
+                        return;
+                    };
+
+                    state.set(AppState::Loading(format!("Undoing {}...", entry.kind.label().to_lowercase())));
+                    let message = match entry.undo {
+                        UndoAction::ReopenTabs { .. } => "Reopening tabs...",
+                        UndoAction::RestoreOrder { .. } => "Sorting tabs...",
+                    };
+                    let progress_state = state.clone();
+                    let progress = move |progress: u8| {
+                        progress_state.set(AppState::Processing(progress, message.to_string()));
+                    };
+
+                    match actions::revert(&ChromeTabs, &ChromeStorage, &entry, progress).await {
+                        Ok(_) => {
+                            undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
+                            state.set(AppState::Idle);
+                        }
+                        Err(e) => {
+                            state.set(AppState::Error(format!("Undo failed: {}", e)));
+                        }
+                    }
+                });
+            })
+        }
+    };
+
     // View collapsed tabs handler
     let on_view_collapsed = {
         Callback::from(move |_| {
@@ -342,74 +788,95 @@ pub fn app() -> Html {
         })
     };
 
-    let is_busy = !matches!(*state, AppState::Idle);
+    let is_busy = !matches!(*state, AppState::Idle) || pending_plan.is_some();
 
-    // Search tab: Filter tabs based on search query
-    let filtered_tabs: Vec<TabInfo> = if search_query.is_empty() {
-        (*search_tabs).clone()
-    } else {
+    // Search tab: Filter tabs based on search query, each with the title
+    // ranges to highlight
+    let (filtered_tabs, search_error): (Vec<SearchResult>, Option<String>) = if search_query.is_empty() {
+        (search_tabs.iter().map(|tab| (tab.clone(), Vec::new())).collect(), None)
+    } else if *use_regex {
         use regex::Regex;
 
-        if *use_regex {
-            // Regex matching
-            let regex_result = if *use_case_insensitive {
-                Regex::new(&format!("(?i){}", &**search_query))
-            } else {
-                Regex::new(&**search_query)
-            };
-
-            if let Ok(re) = regex_result {
-                search_tabs
-                    .iter()
-                    .filter(|tab| {
-                        // Search in title, URL, and domain
-                        let domain = crate::domain::extract_domain(&tab.url).unwrap_or_default();
-                        re.is_match(&tab.title) || re.is_match(&tab.url) || re.is_match(&domain)
-                    })
-                    .cloned()
-                    .collect()
-            } else {
-                // Invalid regex - keep showing current results
-                (*search_tabs).clone()
-            }
+        // Regex matching
+        let regex_result = if *use_case_insensitive {
+            Regex::new(&format!("(?i){}", &**search_query))
         } else {
-            // Text matching (case-sensitive or case-insensitive)
-            if *use_case_insensitive {
-                let query_lower = search_query.to_lowercase();
-                search_tabs
-                    .iter()
-                    .filter(|tab| {
-                        let domain = crate::domain::extract_domain(&tab.url).unwrap_or_default();
-                        tab.title.to_lowercase().contains(&query_lower)
-                            || tab.url.to_lowercase().contains(&query_lower)
-                            || domain.to_lowercase().contains(&query_lower)
-                    })
-                    .cloned()
-                    .collect()
-            } else {
-                search_tabs
-                    .iter()
-                    .filter(|tab| {
-                        let domain = crate::domain::extract_domain(&tab.url).unwrap_or_default();
-                        tab.title.contains(&**search_query)
-                            || tab.url.contains(&**search_query)
-                            || domain.contains(&**search_query)
-                    })
-                    .cloned()
-                    .collect()
-            }
+            Regex::new(&search_query)
+        };
+
+        if let Ok(re) = regex_result {
+            let matching = search_tabs
+                .iter()
+                .filter(|tab| {
+                    // Search in title, URL, and domain (punycode and Unicode forms)
+                    let (ascii, unicode) = domain_forms(&tab.url);
+                    re.is_match(&tab.title) || re.is_match(&tab.url) || re.is_match(&ascii) || re.is_match(&unicode)
+                })
+                .map(|tab| (tab.clone(), re.find_iter(&tab.title).map(|m| m.range()).filter(|r| !r.is_empty()).collect()))
+                .collect();
+            (matching, None)
+        } else {
+            // Invalid regex - keep showing current results
+            (search_tabs.iter().map(|tab| (tab.clone(), Vec::new())).collect(), None)
+        }
+    } else {
+        match Query::parse(&search_query) {
+            Ok(query) => match query.plain_words() {
+                // Plain words: fuzzy match, best first
+                Some(words) => {
+                    let mut ranked: Vec<(i64, SearchResult)> = search_tabs
+                        .iter()
+                        .filter_map(|tab| {
+                            let (_, unicode) = domain_forms(&tab.url);
+                            let found = match_fields(&words, &[&tab.title, &tab.url, &unicode], !*use_case_insensitive)?;
+                            Some((found.score, (tab.clone(), match_ranges(&tab.title, &found.positions[0]))))
+                        })
+                        .collect();
+                    ranked.sort_by_key(|(score, _)| Reverse(*score));
+                    (ranked.into_iter().map(|(_, result)| result).collect(), None)
+                }
+                // Query language: fields, is:/age: filters and AND/OR/NOT
+                None => {
+                    let duplicates = if query.uses_duplicates() {
+                        duplicate_urls(search_tabs.iter().map(|tab| tab.url.as_str()), &url_normalizer)
+                    } else {
+                        HashSet::new()
+                    };
+                    let options = MatchOptions {
+                        case_sensitive: !*use_case_insensitive,
+                        now: js_sys::Date::now(),
+                    };
+                    let terms = query.highlight_terms();
+                    let matching = search_tabs
+                        .iter()
+                        .filter(|tab| {
+                            let duplicate = !duplicates.is_empty() && duplicates.contains(&url_normalizer.normalize(&tab.url));
+                            query.matches(&Candidate::open_tab(tab).with_duplicate(duplicate), options)
+                        })
+                        .map(|tab| (tab.clone(), highlight_ranges(&tab.title, &terms)))
+                        .collect();
+                    (matching, None)
+                }
+            },
+            // Unfinished query - keep showing all tabs and explain
+            Err(e) => (
+                search_tabs.iter().map(|tab| (tab.clone(), Vec::new())).collect(),
+                Some(e.to_string()),
+            ),
         }
     };
 
     // Search tab: Handle search query input change
     let on_search_query_change = {
         let search_query = search_query.clone();
+        let switcher = switcher.clone();
         Callback::from(move |value: String| {
             let query = value;
             search_query.set(query.clone());
+            switcher.set(Switcher::default());
             // Save to storage
             spawn_local(async move {
-                let _ = setStorage("search_query", serde_wasm_bindgen::to_value(&query).unwrap()).await;
+                let _ = ChromeStorage.set(keys::SEARCH_QUERY, &query).await;
             });
         })
     };
@@ -422,7 +889,7 @@ pub fn app() -> Html {
             use_regex.set(new_value);
             // Save to storage
             spawn_local(async move {
-                let _ = setStorage("search_use_regex", serde_wasm_bindgen::to_value(&new_value).unwrap()).await;
+                let _ = ChromeStorage.set(keys::SEARCH_USE_REGEX, &new_value).await;
             });
         })
     };
@@ -435,7 +902,7 @@ pub fn app() -> Html {
             use_case_insensitive.set(new_value);
             // Save to storage
             spawn_local(async move {
-                let _ = setStorage("search_case_insensitive", serde_wasm_bindgen::to_value(&new_value).unwrap()).await;
+                let _ = ChromeStorage.set(keys::SEARCH_CASE_INSENSITIVE, &new_value).await;
             });
         })
     };
@@ -444,31 +911,220 @@ pub fn app() -> Html {
     let on_search_tab_click = {
         Callback::from(move |tab_id: i32| {
             spawn_local(async move {
-                let _ = activateTab(tab_id).await;
+                let _ = ChromeTabs
+                    .update(tab_id, TabUpdate { active: Some(true), ..TabUpdate::default() })
+                    .await;
             });
         })
     };
 
-    // Search tab: Handle close button (X) click
+    // Search tab: Close tabs (X button, or Delete in the search box)
     let on_search_tab_close = {
+        let state = state.clone();
         let search_tabs = search_tabs.clone();
-        Callback::from(move |tab_id: i32| {
+        let undo_journal = undo_journal.clone();
+        Callback::from(move |tab_ids: Vec<i32>| {
+            let state = state.clone();
             let search_tabs = search_tabs.clone();
+            let undo_journal = undo_journal.clone();
             spawn_local(async move {
-                // Close tab in Chrome
-                if closeTab(tab_id).await.is_ok() {
-                    // Remove from local state
-                    let updated_tabs: Vec<TabInfo> = search_tabs
-                        .iter()
-                        .filter(|t| t.id != tab_id)
-                        .cloned()
-                        .collect();
-                    search_tabs.set(updated_tabs);
+                let closing: Vec<TabInfo> = search_tabs
+                    .iter()
+                    .filter(|t| tab_ids.contains(&t.id))
+                    .cloned()
+                    .collect();
+                if closing.is_empty() {
+                    return;
+                }
+
+                // Close tabs in Chrome
+                let closed = actions::close_tabs(&ChromeTabs, &ChromeStorage, &closing, js_sys::Date::now(), |_| {}).await;
+                undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
+                match closed {
+                    Ok(()) => {
+                        // Remove from local state
+                        let updated_tabs: Vec<TabInfo> = search_tabs
+                            .iter()
+                            .filter(|t| !tab_ids.contains(&t.id))
+                            .cloned()
+                            .collect();
+                        search_tabs.set(updated_tabs);
+                    }
+                    Err(e) => state.set(AppState::Error(format!("Failed to close tabs: {}", e))),
+                }
+            });
+        })
+    };
+
+    // Search tab: Run a bulk action on tabs (the selection, or Ctrl+Enter's targets)
+    let on_bulk_action = {
+        let state = state.clone();
+        let search_tabs = search_tabs.clone();
+        let switcher = switcher.clone();
+        let undo_journal = undo_journal.clone();
+        let bulk_name = bulk_name.clone();
+        let normalizer = (*url_normalizer).clone();
+        let policy = *keep_policy;
+        Callback::from(move |(action, tab_ids): (BulkAction, Vec<i32>)| {
+            let mut tabs: Vec<TabInfo> = search_tabs
+                .iter()
+                .filter(|t| tab_ids.contains(&t.id))
+                .cloned()
+                .collect();
+            if tabs.is_empty() {
+                return;
+            }
+            tabs.sort_by_key(|t| t.index);
+            switcher.set(Switcher::default());
+
+            let state = state.clone();
+            let search_tabs = search_tabs.clone();
+            let undo_journal = undo_journal.clone();
+            let normalizer = normalizer.clone();
+            let now = js_sys::Date::now();
+            let title = bulk_name.trim().to_string();
+            let name = match bulk_name.trim() {
+                "" => format!("Session {}", format_date(&js_sys::Date::new(&JsValue::from_f64(now)))),
+                name => name.to_string(),
+            };
+
+            spawn_local(async move {
+                let progress_state = state.clone();
+                let progress = move |progress: u8| {
+                    progress_state.set(AppState::Processing(progress, action.progress_message().to_string()));
+                };
+
+                let result = match action {
+                    BulkAction::Close => actions::close_tabs(&ChromeTabs, &ChromeStorage, &tabs, now, progress).await,
+                    BulkAction::Collapse => {
+                        // Every tab passed is selected, duplicates included
+                        let plan = plan_collapse(&tabs, &normalizer, policy, placement, true, &name);
+                        actions::run_plan(&ChromeTabs, &ChromeStorage, &plan, now, archive_sessions, progress).await
+                    }
+                    BulkAction::MoveToNewWindow => actions::move_to_new_window(&ChromeTabs, &tabs, progress).await.map(|_| ()),
+                    BulkAction::Pin => actions::set_pinned(&ChromeTabs, &tabs, true, progress).await,
+                    BulkAction::Unpin => actions::set_pinned(&ChromeTabs, &tabs, false, progress).await,
+                    // An empty name makes an untitled group rather than a dated one
+                    BulkAction::Group => actions::group_tabs(&ChromeTabs, &tabs, &title, progress).await.map(|_| ()),
+                    // Bookmarks is an optional permission, asked for on first use
+                    BulkAction::Bookmark if !request_permission("bookmarks").await => {
+                        Err("Bookmarking needs permission to access bookmarks".to_string())
+                    }
+                    BulkAction::Bookmark => {
+                        let callback = Closure::wrap(Box::new(progress) as Box<dyn Fn(u8)>);
+                        match serde_wasm_bindgen::to_value(&tabs) {
+                            Ok(tabs_js) => bookmarkTabs(&name, tabs_js, callback.as_ref().unchecked_ref())
+                                .await
+                                .map_err(|e| format!("Failed to bookmark tabs: {:?}", e)),
+                            Err(e) => Err(format!("Failed to serialize: {:?}", e)),
+                        }
+                    }
+                    BulkAction::CopyUrls => {
+                        let urls: Vec<&str> = tabs.iter().map(|t| t.url.as_str()).collect();
+                        copyText(&urls.join("\n"))
+                            .await
+                            .map_err(|e| format!("Failed to copy URLs: {:?}", e))
+                    }
+                };
+
+                undo_journal.set(actions::load_undo_journal(&ChromeStorage).await);
+                match result {
+                    Ok(()) => state.set(AppState::Idle),
+                    Err(e) => state.set(AppState::Error(e)),
+                }
+                // Tabs were closed, moved or changed
+                if let Ok(tabs) = ChromeTabs.query().await {
+                    search_tabs.set(tabs);
                 }
             });
         })
     };
 
+    // Search tab: Results in displayed order
+    let result_ids: Vec<i32> = filtered_tabs.iter().map(|(tab, _)| tab.id).collect();
+    // Closing tabs can leave the cursor past the end until the next key
+    let cursor = switcher.cursor.min(result_ids.len().saturating_sub(1));
+
+    // Search tab: Name used by Collapse, Group and Bookmark
+    let on_bulk_name_change = {
+        let bulk_name = bulk_name.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            bulk_name.set(input.value());
+        })
+    };
+
+    // Search tab: Check/uncheck a result, or every result
+    let on_select_tab = {
+        let switcher = switcher.clone();
+        move |tab_id: i32| {
+            let switcher = switcher.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.stop_propagation();
+                let mut next = (*switcher).clone();
+                next.toggle(tab_id);
+                switcher.set(next);
+            })
+        }
+    };
+    let on_select_all = {
+        let switcher = switcher.clone();
+        let result_ids = result_ids.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut next = (*switcher).clone();
+            next.select_all(&result_ids, !switcher.all_selected(&result_ids));
+            switcher.set(next);
+        })
+    };
+
+    // Search tab: Keyboard switcher in the search box
+    let on_search_keydown = {
+        let switcher = switcher.clone();
+        let result_ids = result_ids.clone();
+        let on_search_tab_close = on_search_tab_close.clone();
+        let on_bulk_action = on_bulk_action.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            let Some(key) = SwitcherKey::from_key(&e.key(), e.ctrl_key() || e.meta_key()) else {
+                return;
+            };
+            // Delete edits the query unless the caret is at its end
+            if key == SwitcherKey::Delete {
+                let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                let end = input.value().encode_utf16().count() as u32;
+                if input.selection_start().ok().flatten() != Some(end) {
+                    return;
+                }
+            }
+
+            let mut next = (*switcher).clone();
+            let command = next.key(key, e.shift_key(), &result_ids);
+            if command == SwitcherCommand::Ignored {
+                return;
+            }
+            e.prevent_default();
+            switcher.set(next);
+
+            match command {
+                SwitcherCommand::Activate(tab_id) => {
+                    spawn_local(async move {
+                        let activated = ChromeTabs
+                            .update(tab_id, TabUpdate { active: Some(true), ..TabUpdate::default() })
+                            .await;
+                        // Switching is done; get out of the way
+                        if activated.is_ok()
+                            && let Some(window) = web_sys::window()
+                        {
+                            let _ = window.close();
+                        }
+                    });
+                }
+                SwitcherCommand::MoveToNewWindow(tab_ids) => on_bulk_action.emit((BulkAction::MoveToNewWindow, tab_ids)),
+                SwitcherCommand::Close(tab_ids) => on_search_tab_close.emit(tab_ids),
+                SwitcherCommand::Moved | SwitcherCommand::Ignored => {}
+            }
+        })
+    };
+
     // Tab click handlers
     let on_tab_click = {
         let active_tab = active_tab.clone();
@@ -490,6 +1146,12 @@ pub fn app() -> Html {
                 </Alert>
             }
 
+            if let Some(notice) = (*recovery_notice).clone() {
+                <Alert r#type={AlertType::Warning} title={"Interrupted collapse"} inline={true}>
+                    {notice}
+                </Alert>
+            }
+
             // Tab navigation
             <div class="pf-v5-c-tabs tabs-nav">
                 <ul class="pf-v5-c-tabs__list">
@@ -517,6 +1179,14 @@ pub fn app() -> Html {
                             <span class="pf-v5-c-tabs__item-text">{"Archive"}</span>
                         </button>
                     </li>
+                    <li class={if *active_tab == ActiveTab::History { "pf-v5-c-tabs__item pf-m-current" } else { "pf-v5-c-tabs__item" }}>
+                        <button
+                            class="pf-v5-c-tabs__link"
+                            onclick={on_tab_click(ActiveTab::History)}
+                        >
+                            <span class="pf-v5-c-tabs__item-text">{"History"}</span>
+                        </button>
+                    </li>
                     <li class={if *active_tab == ActiveTab::Analyze { "pf-v5-c-tabs__item pf-m-current" } else { "pf-v5-c-tabs__item" }}>
                         <button
                             class="pf-v5-c-tabs__link"
@@ -552,6 +1222,57 @@ pub fn app() -> Html {
                 AppState::Idle => html! {}
             }}
 
+            // Plan review
+            if let Some(plan) = &*pending_plan {
+                <div class="plan-review">
+                    <p class="plan-review-title">
+                        {match plan.operation {
+                            PlannedOperation::RemoveDuplicates => format!("Close {} of {} duplicate tabs?", plan.included_count(), plan.items.len()),
+                            PlannedOperation::Collapse => format!("Save and close {} of {} tabs?", plan.tabs_to_close().len(), plan.items.len()),
+                        }}
+                    </p>
+                    if let Some(session) = plan.session_description() {
+                        <p class="plan-review-session">{session}</p>
+                    }
+                    <div class="plan-review-select">
+                        <button class="link-button" onclick={on_plan_set_all(true)}>{"Select all"}</button>
+                        <button class="link-button" onclick={on_plan_set_all(false)}>{"Select none"}</button>
+                    </div>
+                    <div class="plan-review-list">
+                        {for plan.items.iter().map(|item| html! {
+                            <label class="plan-review-item" title={item.tab.url.clone()}>
+                                <input
+                                    type="checkbox"
+                                    checked={item.included}
+                                    onclick={on_plan_toggle(item.tab.id)}
+                                />
+                                <span class="plan-review-item-title">{&item.tab.title}</span>
+                                <span class="plan-review-item-reason">{item.reason.description()}</span>
+                            </label>
+                        })}
+                    </div>
+                    if plan.operation == PlannedOperation::Collapse {
+                        {match &*collapse_room {
+                            None | Some(Ok(RoomPlan::Fits)) => html! {},
+                            Some(Ok(room)) => html! {
+                                <p class="plan-review-quota">{room.describe()}</p>
+                            },
+                            Some(Err(e)) => html! {
+                                <p class="plan-review-quota plan-review-quota-error">{e.clone()}</p>
+                            },
+                        }}
+                    }
+                    <div class="plan-review-actions">
+                        <Button onclick={on_plan_confirm} disabled={plan.included_count() == 0} variant={ButtonVariant::Danger}>
+                            {"Confirm"}
+                        </Button>
+                        <Button onclick={on_plan_cancel} variant={ButtonVariant::Secondary}>
+                            {"Cancel"}
+                        </Button>
+                    </div>
+                </div>
+            }
+
             // Tab content
             <div class="tab-pane-content">
                 {match &*active_tab {
@@ -561,7 +1282,9 @@ pub fn app() -> Html {
                             <div class="search-controls">
                                 <input
                                     type="text"
-                                    placeholder="Search tabs by title, domain, or URL..."
+                                    placeholder="Search tabs, e.g. domain:github.com is:pinned age:>7d"
+                                    ref={search_input_ref.clone()}
+                                    onkeydown={on_search_keydown}
                                     value={(*search_query).clone()}
                                     oninput={
                                         let on_search_query_change = on_search_query_change.clone();
@@ -595,6 +1318,52 @@ pub fn app() -> Html {
                                     </span>
                                 </div>
                             </div>
+                            {if let Some(message) = &search_error {
+                                html! { <div class="search-error">{message}</div> }
+                            } else {
+                                html! {}
+                            }}
+                            <div class="switcher-hint">
+                                {"↑↓ move · Enter switch · Ctrl+Enter new window · Del close · Shift select"}
+                                if switcher.selected_count() > 0 {
+                                    {format!(" · {} selected", switcher.selected_count())}
+                                }
+                            </div>
+                            if !filtered_tabs.is_empty() {
+                                <div class="bulk-actions">
+                                    <label class="select-all-checkbox">
+                                        <input
+                                            type="checkbox"
+                                            checked={switcher.all_selected(&result_ids)}
+                                            onclick={on_select_all}
+                                        />
+                                        {format!(" Select all matching ({})", filtered_tabs.len())}
+                                    </label>
+                                    if switcher.selected_count() > 0 {
+                                        <input
+                                            type="text"
+                                            class="bulk-name-input"
+                                            placeholder="Name for session, group or bookmarks"
+                                            value={(*bulk_name).clone()}
+                                            oninput={on_bulk_name_change}
+                                        />
+                                        <div class="bulk-action-buttons">
+                                            {for BulkAction::ALL.iter().map(|&action| {
+                                                let onclick = {
+                                                    let on_bulk_action = on_bulk_action.clone();
+                                                    let tab_ids = switcher.targets(&result_ids);
+                                                    Callback::from(move |_: MouseEvent| on_bulk_action.emit((action, tab_ids.clone())))
+                                                };
+                                                html! {
+                                                    <button class="bulk-action-btn" disabled={is_busy} {onclick}>
+                                                        {action.label()}
+                                                    </button>
+                                                }
+                                            })}
+                                        </div>
+                                    }
+                                </div>
+                            }
 
                             // Tabs list
                             <div class="tabs-list">
@@ -611,32 +1380,54 @@ pub fn app() -> Html {
                                 } else {
                                     html! {
                                         <div class="scrollable-tabs">
-                                            {for filtered_tabs.iter().map(|tab| {
+                                            {for filtered_tabs.iter().enumerate().map(|(i, (tab, title_ranges))| {
                                                 let tab_id = tab.id;
                                                 let on_click = {
                                                     let on_search_tab_click = on_search_tab_click.clone();
+                                                    let switcher = switcher.clone();
+                                                    let result_ids = result_ids.clone();
                                                     Callback::from(move |e: MouseEvent| {
                                                         e.prevent_default();
-                                                        on_search_tab_click.emit(tab_id);
+                                                        // Shift-click extends the selection instead
+                                                        if e.shift_key() {
+                                                            let mut next = (*switcher).clone();
+                                                            next.move_to(i, true, &result_ids);
+                                                            switcher.set(next);
+                                                        } else {
+                                                            on_search_tab_click.emit(tab_id);
+                                                        }
                                                     })
                                                 };
                                                 let on_close = {
                                                     let on_search_tab_close = on_search_tab_close.clone();
                                                     Callback::from(move |e: MouseEvent| {
                                                         e.stop_propagation();
-                                                        on_search_tab_close.emit(tab_id);
+                                                        on_search_tab_close.emit(vec![tab_id]);
                                                     })
                                                 };
+                                                let row_class = classes!(
+                                                    "tab-item",
+                                                    (i == cursor).then_some("tab-item-cursor"),
+                                                    switcher.is_selected(tab_id).then_some("tab-item-selected"),
+                                                );
 
-                                                let display_text = if let Some(domain) = crate::domain::extract_domain(&tab.url) {
-                                                    format!("{}: {}", domain, tab.title)
-                                                } else {
-                                                    tab.title.clone()
-                                                };
+                                                let display_domain = crate::domain::extract_display_domain(&tab.url);
 
                                                 html! {
-                                                    <div class="tab-item" onclick={on_click}>
-                                                        <span class="tab-title">{display_text}</span>
+                                                    <div class={row_class} onclick={on_click}>
+                                                        <input
+                                                            type="checkbox"
+                                                            class="tab-select-checkbox"
+                                                            checked={switcher.is_selected(tab_id)}
+                                                            onclick={on_select_tab(tab_id)}
+                                                        />
+                                                        <span class="tab-title">
+                                                            if let Some(domain) = display_domain {
+                                                                <DomainLabel domain={domain} />
+                                                                {": "}
+                                                            }
+                                                            <Highlighted text={tab.title.clone()} ranges={title_ranges.clone()} />
+                                                        </span>
                                                         <button class="tab-close-btn" onclick={on_close}>{"×"}</button>
                                                     </div>
                                                 }
@@ -649,12 +1440,86 @@ pub fn app() -> Html {
                     },
                     ActiveTab::SortUnique => html! {
                         <div class="flex-column-gap">
+                            <div class="sort-options">
+                                <select class="sort-select" onchange={on_sort_preset_change}>
+                                    {for SortPreset::ALL.iter().enumerate().map(|(i, preset)| html! {
+                                        <option value={i.to_string()} selected={*preset == *sort_preset}>
+                                            {preset.label()}
+                                        </option>
+                                    })}
+                                </select>
+                                <span class="sort-reverse-checkbox">
+                                    <label>
+                                        <input
+                                            type="checkbox"
+                                            checked={*sort_reverse}
+                                            onclick={on_sort_reverse_change}
+                                        />
+                                        {" Reverse"}
+                                    </label>
+                                </span>
+                            </div>
                             <Button onclick={on_sort} disabled={is_busy} variant={ButtonVariant::Secondary} block={true}>
-                                {"🔤 Sort Tabs by Domain"}
+                                {"🔤 Sort Tabs"}
                             </Button>
                             <Button onclick={on_unique} disabled={is_busy} variant={ButtonVariant::Secondary} block={true}>
                                 {"🗑️ Make Tabs Unique"}
                             </Button>
+                            <span class="no-domain-checkbox">
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked={*no_domain_first}
+                                        onclick={on_no_domain_first_change}
+                                    />
+                                    {" Put tabs without a domain first"}
+                                </label>
+                            </span>
+                            <span class="groups-as-units-checkbox">
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked={*groups_as_units}
+                                        onclick={on_groups_as_units_change}
+                                    />
+                                    {" Sort tab groups as whole units"}
+                                </label>
+                            </span>
+                            <details class="dedup-options">
+                                <summary>{"Duplicate matching"}</summary>
+                                <select class="sort-select" title="Which duplicate to keep" onchange={on_keep_policy_change}>
+                                    {for KeepPolicy::CHOICES.iter().enumerate().map(|(i, policy)| html! {
+                                        <option value={i.to_string()} selected={*policy == *keep_policy}>
+                                            {policy.label()}
+                                        </option>
+                                    })}
+                                </select>
+                                {for [
+                                    ("Ignore #fragments", url_normalizer.strip_fragment, (|n: &mut UrlNormalizer| n.strip_fragment = !n.strip_fragment) as fn(&mut UrlNormalizer)),
+                                    ("Ignore trailing slash", url_normalizer.strip_trailing_slash, |n: &mut UrlNormalizer| n.strip_trailing_slash = !n.strip_trailing_slash),
+                                    ("Ignore www.", url_normalizer.strip_www, |n: &mut UrlNormalizer| n.strip_www = !n.strip_www),
+                                    ("Treat http and https as equal", url_normalizer.ignore_scheme, |n: &mut UrlNormalizer| n.ignore_scheme = !n.ignore_scheme),
+                                    ("Ignore query parameter order", url_normalizer.sort_query_params, |n: &mut UrlNormalizer| n.sort_query_params = !n.sort_query_params),
+                                    ("Ignore tracking parameters", url_normalizer.strip_tracking_params, |n: &mut UrlNormalizer| n.strip_tracking_params = !n.strip_tracking_params),
+                                ].into_iter().map(|(label, checked, update)| html! {
+                                    <label class="dedup-option">
+                                        <input
+                                            type="checkbox"
+                                            checked={checked}
+                                            onclick={on_normalizer_change(update)}
+                                        />
+                                        {format!(" {}", label)}
+                                    </label>
+                                })}
+                                <input
+                                    type="text"
+                                    class="tracking-params-input"
+                                    title="Tracking parameters (comma-separated, * for prefix)"
+                                    value={url_normalizer.tracking_params.join(", ")}
+                                    disabled={!url_normalizer.strip_tracking_params}
+                                    onchange={on_tracking_params_change}
+                                />
+                            </details>
                         </div>
                     },
                     ActiveTab::Archive => html! {
@@ -665,6 +1530,53 @@ pub fn app() -> Html {
                             <Button onclick={on_view_collapsed} disabled={is_busy} variant={ButtonVariant::Secondary} block={true}>
                                 {"📂 View Collapsed Tabs"}
                             </Button>
+                            <span class="close-duplicates-checkbox">
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked={*close_duplicates}
+                                        onclick={on_close_duplicates_change}
+                                    />
+                                    {" Also close duplicate copies"}
+                                </label>
+                            </span>
+                            <select class="sort-select" title="What to do when storage is nearly full" onchange={on_quota_policy_change}>
+                                {for QuotaPolicy::CHOICES.iter().enumerate().map(|(i, policy)| html! {
+                                    <option value={i.to_string()} selected={*policy == *quota_policy}>
+                                        {policy.label()}
+                                    </option>
+                                })}
+                            </select>
+                        </div>
+                    },
+                    ActiveTab::History => html! {
+                        <div class="flex-column-gap">
+                            <Button onclick={on_revert(None)} disabled={is_busy || undo_journal.is_empty()} variant={ButtonVariant::Secondary} block={true}>
+                                {"↩️ Undo last action"}
+                            </Button>
+                            if undo_journal.is_empty() {
+                                <div class="empty-message">{"No operations to undo"}</div>
+                            } else {
+                                <div class="history-list">
+                                    {for undo_journal.newest_first().map(|entry| html! {
+                                        <div class="history-item">
+                                            <div class="history-item-text">
+                                                <span class="history-item-summary">{entry.summary()}</span>
+                                                <span class="history-item-time">
+                                                    {format_date(&js_sys::Date::new(&JsValue::from_f64(entry.timestamp)))}
+                                                </span>
+                                            </div>
+                                            <button
+                                                class="link-button"
+                                                disabled={is_busy}
+                                                onclick={on_revert(Some(entry.id.clone()))}
+                                            >
+                                                {"Revert"}
+                                            </button>
+                                        </div>
+                                    })}
+                                </div>
+                            }
                         </div>
                     },
                     ActiveTab::Analyze => html! {
@@ -672,6 +1584,16 @@ pub fn app() -> Html {
                             <Button onclick={on_analyze} disabled={is_busy} variant={ButtonVariant::Secondary} block={true}>
                                 {"📊 Analyze Domains"}
                             </Button>
+                            <span class="private-suffix-checkbox">
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked={*include_private_suffixes}
+                                        onclick={on_private_suffixes_change}
+                                    />
+                                    {" Group hosted sites separately (foo.github.io)"}
+                                </label>
+                            </span>
 
                             // Domain stats (only show when expanded)
                             if *is_domains_expanded && !domain_stats.is_empty() {
@@ -680,7 +1602,9 @@ pub fn app() -> Html {
                                     <div class="stats-box">
                                         {for domain_stats.iter().map(|stat| html! {
                                             <div class="stat-item">
-                                                <span class="stat-domain">{&stat.domain}</span>
+                                                <span class="stat-domain">
+                                                    <DomainLabel domain={display_domain(&stat.domain)} />
+                                                </span>
                                                 <span class="stat-count">{stat.count}</span>
                                             </div>
                                         })}
@@ -701,83 +1625,43 @@ pub fn app() -> Html {
 
 // Helper functions
 
-async fn get_current_tabs() -> Result<Vec<TabInfo>, String> {
-    getCurrentWindowTabs()
-        .await
-        .map_err(|e| format!("Failed to get tabs: {:?}", e))
-        .and_then(|tabs_js| {
-            serde_wasm_bindgen::from_value(tabs_js)
-                .map_err(|e| format!("Failed to parse tabs: {:?}", e))
-        })
-}
-
-async fn sort_tabs_with_progress(tab_ids: Vec<i32>, state: UseStateHandle<AppState>) -> Result<(), String> {
-    let progress_callback = Closure::wrap(Box::new(move |progress: u8| {
-        state.set(AppState::Processing(progress, "Sorting tabs...".to_string()));
-    }) as Box<dyn Fn(u8)>);
-
-    let tab_ids_js = serde_wasm_bindgen::to_value(&tab_ids)
-        .map_err(|e| format!("Failed to serialize: {:?}", e))?;
+/// A tab found by the search, with the byte ranges of its title to highlight
+type SearchResult = (TabInfo, Vec<Range<usize>>);
 
-    sortTabsByDomain(tab_ids_js, progress_callback.as_ref().unchecked_ref())
-        .await
-        .map_err(|e| format!("Sort failed: {:?}", e))?;
-
-    Ok(())
+/// Grouping (punycode) and display (Unicode) forms of a tab's domain
+fn domain_forms(url: &str) -> (String, String) {
+    crate::domain::extract_display_domain(url)
+        .map(|domain| (domain.ascii, domain.unicode))
+        .unwrap_or_default()
 }
 
-async fn remove_tabs_with_progress(tab_ids: Vec<i32>, state: UseStateHandle<AppState>) -> Result<(), String> {
-    let progress_callback = Closure::wrap(Box::new(move |progress: u8| {
-        state.set(AppState::Processing(progress, "Removing duplicates...".to_string()));
-    }) as Box<dyn Fn(u8)>);
-
-    let tab_ids_js = serde_wasm_bindgen::to_value(&tab_ids)
-        .map_err(|e| format!("Failed to serialize: {:?}", e))?;
-
-    removeTabs(tab_ids_js, progress_callback.as_ref().unchecked_ref())
-        .await
-        .map_err(|e| format!("Remove failed: {:?}", e))?;
-
-    Ok(())
+fn suffix_mode_for(include_private: bool) -> SuffixMode {
+    if include_private {
+        SuffixMode::IncludePrivate
+    } else {
+        SuffixMode::IcannOnly
+    }
 }
 
-async fn close_tabs_with_progress(tab_ids: Vec<i32>, state: UseStateHandle<AppState>) -> Result<(), String> {
-    let progress_callback = Closure::wrap(Box::new(move |progress: u8| {
-        state.set(AppState::Processing(progress, "Closing tabs...".to_string()));
-    }) as Box<dyn Fn(u8)>);
-
-    let tab_ids_js = serde_wasm_bindgen::to_value(&tab_ids)
-        .map_err(|e| format!("Failed to serialize: {:?}", e))?;
-
-    closeTabs(tab_ids_js, progress_callback.as_ref().unchecked_ref())
+/// Download sessions a full store is about to delete, in the viewer's
+/// export format; returns once the file is written
+async fn archive_sessions(sessions: &[CollapsedSession]) -> Result<(), String> {
+    let archive = StorageData {
+        sessions: sessions.to_vec(),
+        ..StorageData::new()
+    };
+    let json = serde_json::to_string_pretty(&archive).map_err(|e| format!("Failed to serialize: {:?}", e))?;
+    downloadFile(&json, &format!("tab-hoarder-archive-{}.json", js_sys::Date::now() as i64))
         .await
-        .map_err(|e| format!("Close failed: {:?}", e))?;
-
-    Ok(())
+        .map_err(|e| format!("{:?}", e))
 }
 
-async fn save_session(session: crate::tab_data::CollapsedSession) -> Result<(), String> {
-    // Load existing storage
-    let storage_js = getStorage("tab_hoarder_data").await
-        .map_err(|e| format!("Failed to get storage: {:?}", e))?;
-
-    let mut storage: StorageData = if storage_js.is_null() || storage_js.is_undefined() {
-        StorageData::new()
-    } else {
-        serde_wasm_bindgen::from_value(storage_js)
-            .map_err(|e| format!("Failed to parse storage: {:?}", e))?
-    };
-
-    storage.add_session(session);
-
-    let storage_js = serde_wasm_bindgen::to_value(&storage)
-        .map_err(|e| format!("Failed to serialize storage: {:?}", e))?;
-
-    setStorage("tab_hoarder_data", storage_js)
+/// Ask for an optional permission; false when refused or on error
+async fn request_permission(permission: &str) -> bool {
+    requestPermission(permission)
         .await
-        .map_err(|e| format!("Failed to save storage: {:?}", e))?;
-
-    Ok(())
+        .map(|granted| granted.as_bool() == Some(true))
+        .unwrap_or(false)
 }
 
 fn format_date(date: &js_sys::Date) -> String {
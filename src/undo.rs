@@ -0,0 +1,254 @@
+//! Undo journal for operations that close or reorder tabs
+//!
+//! Every close, dedup, collapse and sort records its inverse before it
+//! runs. The journal is stored in chrome.storage (`undo_journal`) and keeps
+//! the most recent `DEFAULT_HISTORY_LIMIT` entries.
+
+use crate::operations::{plan_current_order, SortPlan};
+use crate::tab_data::TabInfo;
+use serde::{Deserialize, Serialize};
+
+/// Number of operations kept in the history
+pub const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// Operation a journal entry can revert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationKind {
+    CloseTab,
+    RemoveDuplicates,
+    Collapse,
+    Sort,
+}
+
+impl OperationKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            OperationKind::CloseTab => "Close tab",
+            OperationKind::RemoveDuplicates => "Remove duplicates",
+            OperationKind::Collapse => "Collapse tabs",
+            OperationKind::Sort => "Sort tabs",
+        }
+    }
+}
+
+/// Everything needed to reopen a closed tab where it was
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClosedTab {
+    pub url: String,
+    pub title: String,
+    pub pinned: bool,
+    pub index: i32,
+    #[serde(default)]
+    pub group_id: Option<i32>,
+}
+
+impl From<&TabInfo> for ClosedTab {
+    fn from(tab: &TabInfo) -> Self {
+        ClosedTab {
+            url: tab.url.clone(),
+            title: tab.title.clone(),
+            pinned: tab.pinned,
+            index: tab.index,
+            group_id: tab.group_id,
+        }
+    }
+}
+
+/// The inverse of an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum UndoAction {
+    /// Reopen closed tabs, and for a collapse take them back out of the
+    /// session it wrote
+    ReopenTabs {
+        /// Ordered by original index, so reopening left to right puts each
+        /// tab back at its old position
+        tabs: Vec<ClosedTab>,
+        #[serde(default)]
+        session_id: Option<String>,
+    },
+    /// Move tabs back into their previous order
+    RestoreOrder { plan: SortPlan },
+}
+
+impl UndoAction {
+    /// Inverse of closing `tabs`
+    pub fn reopen(tabs: &[TabInfo], session_id: Option<String>) -> Self {
+        let mut closed: Vec<ClosedTab> = tabs.iter().map(ClosedTab::from).collect();
+        closed.sort_by_key(|tab| tab.index);
+        UndoAction::ReopenTabs { tabs: closed, session_id }
+    }
+
+    /// Inverse of reordering a window whose tabs are currently `tabs`
+    pub fn restore_order(tabs: &[TabInfo]) -> Self {
+        UndoAction::RestoreOrder {
+            plan: plan_current_order(tabs),
+        }
+    }
+
+    /// Number of tabs the undo touches
+    pub fn tab_count(&self) -> usize {
+        match self {
+            UndoAction::ReopenTabs { tabs, .. } => tabs.len(),
+            UndoAction::RestoreOrder { plan } => plan.tab_order().len(),
+        }
+    }
+}
+
+/// One recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub kind: OperationKind,
+    /// When the operation ran, in ms since the epoch
+    pub timestamp: f64,
+    pub undo: UndoAction,
+}
+
+impl JournalEntry {
+    /// Short description for the history list, e.g. "Collapse tabs (12 tabs)"
+    pub fn summary(&self) -> String {
+        let count = self.undo.tab_count();
+        format!(
+            "{} ({} tab{})",
+            self.kind.label(),
+            count,
+            if count == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Bounded history of operations, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UndoJournal {
+    pub entries: Vec<JournalEntry>,
+    pub limit: usize,
+}
+
+impl UndoJournal {
+    pub fn new(limit: usize) -> Self {
+        UndoJournal {
+            entries: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Add an entry, dropping the oldest ones beyond the limit
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > self.limit {
+            let excess = self.entries.len() - self.limit;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Most recent entry
+    pub fn last(&self) -> Option<&JournalEntry> {
+        self.entries.last()
+    }
+
+    /// Remove and return the entry with `id`
+    pub fn take(&mut self, id: &str) -> Option<JournalEntry> {
+        let position = self.entries.iter().position(|entry| entry.id == id)?;
+        Some(self.entries.remove(position))
+    }
+
+    /// Entries newest first, as shown in the history list
+    pub fn newest_first(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter().rev()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for UndoJournal {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_tab(id: i32, url: &str, index: i32) -> TabInfo {
+        TabInfo::new(id, url.to_string(), format!("Tab {}", id), false, index)
+    }
+
+    fn create_test_entry(id: &str, kind: OperationKind) -> JournalEntry {
+        JournalEntry {
+            id: id.to_string(),
+            kind,
+            timestamp: 1698508200000.0,
+            undo: UndoAction::reopen(&[create_test_tab(1, "https://a.com", 0)], None),
+        }
+    }
+
+    #[test]
+    fn test_reopen_orders_by_original_index() {
+        let mut tabs = vec![
+            create_test_tab(1, "https://c.com", 7),
+            create_test_tab(2, "https://a.com", 2),
+        ];
+        tabs[0].pinned = true;
+        tabs[0].group_id = Some(4);
+
+        let UndoAction::ReopenTabs { tabs: closed, session_id } = UndoAction::reopen(&tabs, Some("s1".to_string())) else {
+            panic!("expected ReopenTabs");
+        };
+
+        let indices: Vec<i32> = closed.iter().map(|tab| tab.index).collect();
+        assert_eq!(indices, vec![2, 7]);
+        assert!(closed[1].pinned);
+        assert_eq!(closed[1].group_id, Some(4));
+        assert_eq!(session_id, Some("s1".to_string()));
+    }
+
+    #[test]
+    fn test_restore_order_records_previous_layout() {
+        let tabs = vec![
+            create_test_tab(1, "https://b.com", 1),
+            create_test_tab(2, "https://a.com", 0),
+        ];
+
+        let undo = UndoAction::restore_order(&tabs);
+
+        let UndoAction::RestoreOrder { plan } = &undo else {
+            panic!("expected RestoreOrder");
+        };
+        assert_eq!(plan.tab_order(), vec![2, 1]);
+        assert_eq!(undo.tab_count(), 2);
+    }
+
+    #[test]
+    fn test_journal_keeps_last_entries() {
+        let mut journal = UndoJournal::new(2);
+
+        journal.record(create_test_entry("a", OperationKind::Sort));
+        journal.record(create_test_entry("b", OperationKind::CloseTab));
+        journal.record(create_test_entry("c", OperationKind::Collapse));
+
+        let ids: Vec<&str> = journal.newest_first().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "b"]);
+        assert_eq!(journal.last().unwrap().id, "c");
+    }
+
+    #[test]
+    fn test_take_removes_any_entry() {
+        let mut journal = UndoJournal::default();
+        journal.record(create_test_entry("a", OperationKind::Sort));
+        journal.record(create_test_entry("b", OperationKind::RemoveDuplicates));
+
+        assert_eq!(journal.take("a").unwrap().kind, OperationKind::Sort);
+        assert!(journal.take("a").is_none());
+        assert_eq!(journal.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_summary() {
+        let entry = create_test_entry("a", OperationKind::CloseTab);
+
+        assert_eq!(entry.summary(), "Close tab (1 tab)");
+    }
+}
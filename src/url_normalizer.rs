@@ -0,0 +1,273 @@
+//! URL normalization for duplicate detection
+//!
+//! Two URLs are treated as the same page when their normalized keys are
+//! equal. Each rule can be switched off; the defaults are tuned for
+//! "Make Tabs Unique", where a false positive closes a tab the user wanted.
+//! Ignoring fragments and the scheme is therefore opt-in: hash-routed apps
+//! keep whole pages in the fragment (`mail.google.com/#inbox` vs `#sent`).
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Query parameters that only carry campaign/click tracking
+///
+/// A trailing `*` matches any parameter with that prefix.
+pub const DEFAULT_TRACKING_PARAMS: &[&str] = &[
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_ga",
+    "_gl",
+    "ref_src",
+];
+
+/// Configurable rules for turning a URL into a comparison key
+///
+/// Default ports (`:443` on https, `:80` on http) are always dropped, since
+/// URL parsing already normalizes them away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UrlNormalizer {
+    /// `/page#section` → `/page` (off by default)
+    pub strip_fragment: bool,
+    /// `/docs/` → `/docs`, and `example.com/` → `example.com`
+    pub strip_trailing_slash: bool,
+    /// `www.example.com` → `example.com`
+    pub strip_www: bool,
+    /// Treat `http://` and `https://` as the same page (off by default)
+    pub ignore_scheme: bool,
+    /// `?b=2&a=1` → `?a=1&b=2`
+    pub sort_query_params: bool,
+    /// Drop parameters listed in `tracking_params`
+    pub strip_tracking_params: bool,
+    /// Parameter names to drop; `utm_*` matches by prefix
+    pub tracking_params: Vec<String>,
+}
+
+impl UrlNormalizer {
+    /// Normalizer that only compares exact URLs
+    pub fn exact() -> Self {
+        UrlNormalizer {
+            strip_fragment: false,
+            strip_trailing_slash: false,
+            strip_www: false,
+            ignore_scheme: false,
+            sort_query_params: false,
+            strip_tracking_params: false,
+            tracking_params: Vec::new(),
+        }
+    }
+
+    /// Add a parameter name (or `prefix*`) to the tracking list
+    pub fn add_tracking_param(&mut self, name: &str) {
+        let name = name.trim().to_string();
+        if !name.is_empty() && !self.tracking_params.contains(&name) {
+            self.tracking_params.push(name);
+        }
+    }
+
+    /// Remove a parameter name from the tracking list
+    pub fn remove_tracking_param(&mut self, name: &str) -> bool {
+        let original_len = self.tracking_params.len();
+        self.tracking_params.retain(|p| p != name.trim());
+        self.tracking_params.len() < original_len
+    }
+
+    /// Whether two URLs normalize to the same key
+    pub fn same_page(&self, a: &str, b: &str) -> bool {
+        self.normalize(a) == self.normalize(b)
+    }
+
+    /// Comparison key for a URL
+    ///
+    /// Algorithm:
+    /// 1. Parse with url::Url (lowercases the host, drops default ports);
+    ///    unparseable input is returned unchanged
+    /// 2. URLs without a host (data:, about:, ...) keep everything but,
+    ///    optionally, the fragment
+    /// 3. Otherwise rebuild scheme://host:port/path?query#fragment,
+    ///    applying each enabled rule
+    pub fn normalize(&self, url: &str) -> String {
+        let Ok(parsed) = Url::parse(url.trim()) else {
+            return url.trim().to_string();
+        };
+
+        let Some(host) = parsed.host_str() else {
+            let mut key = parsed.clone();
+            if self.strip_fragment {
+                key.set_fragment(None);
+            }
+            return key.to_string();
+        };
+
+        let scheme = match parsed.scheme() {
+            "http" | "https" if self.ignore_scheme => "http(s)",
+            scheme => scheme,
+        };
+
+        let host = match host.strip_prefix("www.") {
+            Some(rest) if self.strip_www && rest.contains('.') => rest,
+            _ => host,
+        };
+
+        let mut key = format!("{}://", scheme);
+        if !parsed.username().is_empty() {
+            key.push_str(parsed.username());
+            key.push('@');
+        }
+        key.push_str(host);
+        if let Some(port) = parsed.port() {
+            key.push_str(&format!(":{}", port));
+        }
+
+        let path = parsed.path();
+        if self.strip_trailing_slash {
+            key.push_str(path.trim_end_matches('/'));
+        } else {
+            key.push_str(path);
+        }
+
+        if let Some(query) = parsed.query() {
+            let mut params: Vec<&str> = query
+                .split('&')
+                .filter(|param| !param.is_empty())
+                .filter(|param| !(self.strip_tracking_params && self.is_tracking_param(param)))
+                .collect();
+            if self.sort_query_params {
+                params.sort_unstable();
+            }
+            if !params.is_empty() {
+                key.push('?');
+                key.push_str(&params.join("&"));
+            }
+        }
+
+        if !self.strip_fragment && let Some(fragment) = parsed.fragment() {
+            key.push('#');
+            key.push_str(fragment);
+        }
+
+        key
+    }
+
+    fn is_tracking_param(&self, param: &str) -> bool {
+        let name = param.split('=').next().unwrap_or_default();
+        self.tracking_params.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+    }
+}
+
+impl Default for UrlNormalizer {
+    fn default() -> Self {
+        UrlNormalizer {
+            strip_fragment: false,
+            strip_trailing_slash: true,
+            strip_www: true,
+            ignore_scheme: false,
+            sort_query_params: true,
+            strip_tracking_params: true,
+            tracking_params: DEFAULT_TRACKING_PARAMS.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules_match_equivalent_urls() {
+        let normalizer = UrlNormalizer::default();
+        let key = normalizer.normalize("https://x.com/a");
+
+        assert_eq!(normalizer.normalize("https://x.com/a/"), key);
+        assert_eq!(normalizer.normalize("https://www.x.com/a?utm_source=foo"), key);
+        assert_eq!(normalizer.normalize("https://X.com:443/a?fbclid=123&gclid=9"), key);
+    }
+
+    #[test]
+    fn test_fragments_and_scheme_kept_by_default() {
+        let mut normalizer = UrlNormalizer::default();
+
+        assert!(!normalizer.same_page("https://mail.google.com/mail/u/0/#inbox", "https://mail.google.com/mail/u/0/#sent"));
+        assert!(!normalizer.same_page("https://x.com/a", "http://x.com/a"));
+
+        normalizer.strip_fragment = true;
+        normalizer.ignore_scheme = true;
+        assert!(normalizer.same_page("https://mail.google.com/mail/u/0/#inbox", "https://mail.google.com/mail/u/0/#sent"));
+        assert!(normalizer.same_page("https://x.com/a#section", "http://x.com/a"));
+    }
+
+    #[test]
+    fn test_root_path_and_query_order() {
+        let normalizer = UrlNormalizer::default();
+
+        assert!(normalizer.same_page("https://example.com", "https://example.com/"));
+        assert!(normalizer.same_page("https://x.com/s?b=2&a=1", "https://x.com/s?a=1&b=2"));
+        assert!(!normalizer.same_page("https://x.com/s?a=1", "https://x.com/s?a=2"));
+        assert!(!normalizer.same_page("https://x.com/a", "https://x.com/b"));
+    }
+
+    #[test]
+    fn test_rules_can_be_disabled() {
+        let normalizer = UrlNormalizer::exact();
+
+        assert!(!normalizer.same_page("https://x.com/a", "https://x.com/a/"));
+        assert!(!normalizer.same_page("https://x.com/a", "https://x.com/a#top"));
+        assert!(!normalizer.same_page("https://x.com/a", "http://x.com/a"));
+        assert!(!normalizer.same_page("https://x.com/a", "https://www.x.com/a"));
+        assert!(!normalizer.same_page("https://x.com/a", "https://x.com/a?utm_source=foo"));
+        // Default ports are always dropped
+        assert!(normalizer.same_page("https://x.com/a", "https://x.com:443/a"));
+    }
+
+    #[test]
+    fn test_www_only_stripped_before_a_domain() {
+        let normalizer = UrlNormalizer::default();
+
+        assert_eq!(normalizer.normalize("https://www.com/"), "https://www.com");
+    }
+
+    #[test]
+    fn test_tracking_param_list_is_editable() {
+        let mut normalizer = UrlNormalizer::default();
+        assert!(!normalizer.same_page("https://x.com/?ref=hn", "https://x.com/"));
+
+        normalizer.add_tracking_param("ref");
+        assert!(normalizer.same_page("https://x.com/?ref=hn", "https://x.com/"));
+
+        assert!(normalizer.remove_tracking_param("utm_*"));
+        assert!(!normalizer.same_page("https://x.com/?utm_source=a", "https://x.com/"));
+    }
+
+    #[test]
+    fn test_hostless_urls() {
+        let normalizer = UrlNormalizer {
+            strip_fragment: true,
+            ..UrlNormalizer::default()
+        };
+
+        assert_eq!(normalizer.normalize("about:blank#x"), "about:blank");
+        assert_eq!(normalizer.normalize("not a url"), "not a url");
+    }
+
+    #[test]
+    fn test_deserialize_partial_config() {
+        let normalizer: UrlNormalizer = serde_json::from_str(r#"{"strip_www": false}"#).unwrap();
+
+        assert!(!normalizer.strip_www);
+        assert!(normalizer.strip_trailing_slash);
+        assert!(!normalizer.tracking_params.is_empty());
+    }
+}
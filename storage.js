@@ -0,0 +1,95 @@
+// JavaScript bridge for chrome.storage.local
+// Shared by the popup and the collapsed viewer (see storage::chrome)
+
+/**
+ * Get storage data
+ * @param {string} key - Storage key
+ * @returns {Promise<any>} Stored data
+ */
+export async function getStorage(key) {
+  const result = await chrome.storage.local.get(key);
+  return result[key];
+}
+
+/**
+ * Set storage data
+ * @param {string} key - Storage key
+ * @param {any} value - Value to store
+ */
+export async function setStorage(key, value) {
+  await chrome.storage.local.set({ [key]: value });
+}
+
+/**
+ * Remove storage keys
+ * @param {Array<string>} keys - Storage keys
+ */
+export async function removeStorage(keys) {
+  await chrome.storage.local.remove(keys);
+}
+
+/**
+ * Get several storage keys at once
+ * @param {Array<string>} keys - Storage keys
+ * @returns {Promise<Object>} Map of key to stored value (missing keys are absent)
+ */
+export async function getStorageItems(keys) {
+  return await chrome.storage.local.get(keys);
+}
+
+/**
+ * Write storage keys only if the stored index is still at a revision
+ *
+ * Runs under a Web Lock shared by every extension page, so the revision
+ * check and the write cannot interleave with another page's write.
+ * @param {string} indexKey - Key of the index holding the revision
+ * @param {number} expectedRevision - Revision the write was computed against
+ * @param {Object} items - Map of key to value (including the new index)
+ * @param {Array<string>} removedKeys - Keys to remove after setting items
+ * @returns {Promise<boolean>} false if another write got there first
+ */
+export async function compareAndSetStorage(indexKey, expectedRevision, items, removedKeys) {
+  return await navigator.locks.request('tab_hoarder_storage', async () => {
+    const result = await chrome.storage.local.get(indexKey);
+    const currentRevision = result[indexKey]?.revision ?? 0;
+    if (currentRevision !== expectedRevision) {
+      return false;
+    }
+
+    await chrome.storage.local.set(items);
+    if (removedKeys.length > 0) {
+      await chrome.storage.local.remove(removedKeys);
+    }
+    return true;
+  });
+}
+
+/**
+ * Get how much of the storage quota is in use
+ * @returns {Promise<{bytes_in_use: number, quota_bytes: number}>}
+ */
+export async function getStorageUsage() {
+  const bytesInUse = await chrome.storage.local.getBytesInUse(null);
+  const quotaBytes = chrome.storage.local.QUOTA_BYTES || 10485760; // 10MB default
+
+  return {
+    bytes_in_use: bytesInUse,
+    quota_bytes: quotaBytes
+  };
+}
+
+/**
+ * Listen for changes to a storage key made by any extension page
+ * @param {string} key - Storage key to watch
+ * @param {Function} callback - Called with the key's new value
+ * @returns {Function} Call to stop listening
+ */
+export function onStorageChanged(key, callback) {
+  const listener = (changes, areaName) => {
+    if (areaName === 'local' && changes[key]) {
+      callback(changes[key].newValue);
+    }
+  };
+  chrome.storage.onChanged.addListener(listener);
+  return () => chrome.storage.onChanged.removeListener(listener);
+}
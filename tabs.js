@@ -0,0 +1,461 @@
+// JavaScript bridge for chrome.tabs
+// Shared by the popup and the collapsed viewer (see tabs::chrome)
+
+const CHUNK_SIZE = 50; // Process tabs in chunks of 50
+const COMMIT_TIMEOUT_MS = 5000; // Longest wait for a new tab's URL before discarding it
+
+/**
+ * Convert a chrome.tabs.Tab to the shape TabInfo deserializes from
+ * @param {chrome.tabs.Tab} tab - Chrome tab
+ * @param {Map<number, chrome.tabGroups.TabGroup>} groupsById - Groups of the tab's window
+ * @returns {Object} Tab info
+ */
+function toTabInfo(tab, groupsById = new Map()) {
+  const group = tab.groupId >= 0 ? groupsById.get(tab.groupId) : undefined;
+  return {
+    id: tab.id,
+    url: tab.url || tab.pendingUrl || '',
+    title: tab.title || '',
+    pinned: tab.pinned || false,
+    index: tab.index,
+    last_accessed: tab.lastAccessed ?? null,
+    group_id: tab.groupId >= 0 ? tab.groupId : null,
+    active: tab.active || false,
+    fav_icon_url: tab.favIconUrl || null,
+    muted: tab.mutedInfo?.muted || false,
+    incognito: tab.incognito || false,
+    window_id: tab.windowId ?? null,
+    group: group ? { title: group.title || '', color: group.color, collapsed: group.collapsed } : null,
+    discarded: tab.discarded || false
+  };
+}
+
+/**
+ * Get all tabs in the current window, with their group details
+ * @returns {Promise<Array>} Array of tab objects
+ */
+export async function getCurrentWindowTabs() {
+  const [tabs, groups] = await Promise.all([
+    chrome.tabs.query({ currentWindow: true }),
+    chrome.tabGroups.query({ windowId: chrome.windows.WINDOW_ID_CURRENT })
+  ]);
+  const groupsById = new Map(groups.map(group => [group.id, group]));
+  return tabs.map(tab => toTabInfo(tab, groupsById));
+}
+
+/**
+ * Move a tab within its window
+ * @param {number} tabId - ID of the tab to move
+ * @param {number} index - Target index
+ */
+export async function moveTab(tabId, index) {
+  await chrome.tabs.move(tabId, { index });
+}
+
+/**
+ * Move a whole tab group within its window
+ * @param {number} groupId - ID of the group to move
+ * @param {number} index - Target index of the group's first tab
+ */
+export async function moveGroup(groupId, index) {
+  await chrome.tabGroups.move(groupId, { index });
+}
+
+/**
+ * Open a tab in the current window, or in properties.windowId
+ * @param {Object} properties - { url, pinned, index?, active, windowId? }
+ * @returns {Promise<Object>} The new tab
+ */
+export async function createTab(properties) {
+  const tab = await chrome.tabs.create(properties);
+  return toTabInfo(tab);
+}
+
+/**
+ * Open a focused window holding one tab
+ * @param {Object} properties - { url, pinned }
+ * @returns {Promise<Object>} The window's tab
+ */
+export async function createWindow(properties) {
+  const created = await chrome.windows.create({ url: properties.url, focused: true });
+  let tab = created.tabs[0];
+  if (properties.pinned) {
+    tab = await chrome.tabs.update(tab.id, { pinned: true });
+  }
+  return toTabInfo(tab);
+}
+
+/**
+ * Move tabs, in order, into a new focused window, with batch processing
+ * @param {Array<number>} tabIds - Tabs to move; the first one opens the window
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ * @returns {Promise<number>} ID of the new window
+ */
+export async function moveToNewWindow(tabIds, progressCallback) {
+  const total = tabIds.length;
+  const [first, ...rest] = tabIds;
+  const created = await chrome.windows.create({ tabId: first, focused: true });
+
+  for (let i = 0; i < rest.length; i += CHUNK_SIZE) {
+    const chunk = rest.slice(i, i + CHUNK_SIZE);
+    await chrome.tabs.move(chunk, { windowId: created.id, index: -1 });
+
+    // Update progress
+    if (progressCallback) {
+      progressCallback(Math.round(((1 + i + chunk.length) / total) * 100));
+    }
+
+    // Yield control to browser
+    await new Promise(resolve => setTimeout(resolve, 0));
+  }
+
+  if (progressCallback) {
+    progressCallback(100);
+  }
+  return created.id;
+}
+
+/**
+ * Change properties of an open tab
+ * @param {number} tabId - ID of the tab to update
+ * @param {Object} properties - { active?, pinned?, muted? }
+ */
+export async function updateTab(tabId, properties) {
+  await chrome.tabs.update(tabId, properties);
+}
+
+/**
+ * Set a group's title, color and collapsed state
+ * @param {number} groupId - ID of the group
+ * @param {Object} info - { title, color, collapsed }
+ */
+export async function updateGroup(groupId, info) {
+  await chrome.tabGroups.update(groupId, groupProperties(info));
+}
+
+/**
+ * chrome.tabGroups.update properties for saved group details
+ * @param {Object} info - { title, color, collapsed }
+ * @returns {Object} Properties, without an empty color
+ */
+function groupProperties(info) {
+  const properties = { title: info.title, collapsed: info.collapsed };
+  if (info.color) {
+    properties.color = info.color;
+  }
+  return properties;
+}
+
+/**
+ * Wait until a new tab has committed its URL
+ *
+ * A tab discarded before then can come back blank when it is focused.
+ * @param {number} tabId - ID of the tab
+ * @returns {Promise<boolean>} false if the tab closed or took too long
+ */
+function urlCommitted(tabId) {
+  return new Promise(resolve => {
+    const settle = committed => {
+      clearTimeout(timer);
+      chrome.tabs.onUpdated.removeListener(listener);
+      resolve(committed);
+    };
+    const timer = setTimeout(() => settle(false), COMMIT_TIMEOUT_MS);
+    const listener = (updatedId, changeInfo) => {
+      if (updatedId === tabId && changeInfo.url) {
+        settle(true);
+      }
+    };
+    chrome.tabs.onUpdated.addListener(listener);
+
+    // It may have committed before the listener was added
+    chrome.tabs.get(tabId).then(
+      tab => {
+        if (tab.url && !tab.pendingUrl) {
+          settle(true);
+        }
+      },
+      () => settle(false)
+    );
+  });
+}
+
+/**
+ * Unload a tab until it is next focused
+ * @param {number} tabId - ID of the tab
+ * @returns {Promise<number>} ID of the tab once discarded
+ */
+export async function discardTab(tabId) {
+  const tab = await chrome.tabs.discard(tabId);
+  return tab ? tab.id : tabId;
+}
+
+/**
+ * Add tabs to a group
+ * @param {Array<number>} tabIds - Tabs to group
+ * @param {number|undefined} groupId - Existing group, or undefined for a new one
+ * @returns {Promise<number>} ID of the group
+ */
+export async function groupTabs(tabIds, groupId) {
+  const options = groupId === undefined || groupId === null ? { tabIds } : { groupId, tabIds };
+  return await chrome.tabs.group(options);
+}
+
+/**
+ * Reorder tabs according to a sort plan, with batch processing
+ *
+ * Units are placed left to right starting after the pinned tabs. A grouped
+ * unit is moved with chrome.tabGroups.move so the group stays intact, then
+ * its tabs are reordered inside the group's own range. A group that no
+ * longer exists is skipped and its tabs are placed as they are.
+ * @param {Object} plan - { start_index, units: [{ group_id, tab_ids }] }
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ */
+export async function applySortPlan(plan, progressCallback) {
+  const total = plan.units.reduce((sum, unit) => sum + unit.tab_ids.length, 0);
+  let targetIndex = plan.start_index;
+  let processed = 0;
+  let lastReportedChunk = 0;
+
+  for (const unit of plan.units) {
+    let groupId = unit.group_id;
+    if (groupId !== null && groupId !== undefined) {
+      try {
+        await chrome.tabGroups.move(groupId, { index: targetIndex });
+      } catch (error) {
+        // Ungrouped or closed since the plan was made; place the tabs as they are
+        console.warn('Tab group no longer exists:', groupId);
+        groupId = null;
+      }
+    }
+
+    // Moves must run in order: each one shifts the tabs after it
+    for (let offset = 0; offset < unit.tab_ids.length; offset++) {
+      await chrome.tabs.move(unit.tab_ids[offset], { index: targetIndex + offset });
+    }
+
+    // Moving a tab to the edge of its group can ungroup it; re-attach
+    if (groupId !== null && groupId !== undefined) {
+      await chrome.tabs.group({ groupId, tabIds: unit.tab_ids });
+    }
+
+    targetIndex += unit.tab_ids.length;
+    processed += unit.tab_ids.length;
+
+    // Update progress and yield once per chunk of tabs
+    const chunk = Math.floor(processed / CHUNK_SIZE);
+    if (chunk > lastReportedChunk || processed === total) {
+      lastReportedChunk = chunk;
+      if (progressCallback) {
+        progressCallback(Math.round((processed / total) * 100));
+      }
+      await new Promise(resolve => setTimeout(resolve, 0));
+    }
+  }
+}
+
+/**
+ * Close tabs, with batch processing
+ * @param {Array} tabIdsToRemove - Array of tab IDs to close
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ */
+export async function removeTabs(tabIdsToRemove, progressCallback) {
+  if (tabIdsToRemove.length === 0) return;
+
+  const total = tabIdsToRemove.length;
+  const chunks = [];
+
+  // Split into chunks
+  for (let i = 0; i < tabIdsToRemove.length; i += CHUNK_SIZE) {
+    chunks.push(tabIdsToRemove.slice(i, i + CHUNK_SIZE));
+  }
+
+  // Process each chunk
+  for (let chunkIndex = 0; chunkIndex < chunks.length; chunkIndex++) {
+    const chunk = chunks[chunkIndex];
+
+    await chrome.tabs.remove(chunk);
+
+    // Update progress
+    if (progressCallback) {
+      const processed = Math.min((chunkIndex + 1) * CHUNK_SIZE, total);
+      progressCallback(Math.round((processed / total) * 100));
+    }
+
+    // Yield control to browser
+    await new Promise(resolve => setTimeout(resolve, 0));
+  }
+}
+
+/**
+ * Apply the same properties to several tabs, one after another in order
+ * (pinning moves each tab to the end of the pinned block, so order matters)
+ * @param {Array<number>} tabIds - Tabs to update
+ * @param {Object} properties - { active?, pinned?, muted? }
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ */
+export async function updateTabs(tabIds, properties, progressCallback) {
+  const total = tabIds.length;
+
+  for (let i = 0; i < total; i++) {
+    await chrome.tabs.update(tabIds[i], properties);
+
+    // Update progress and yield once per chunk of tabs
+    if ((i + 1) % CHUNK_SIZE === 0 || i + 1 === total) {
+      if (progressCallback) {
+        progressCallback(Math.round(((i + 1) / total) * 100));
+      }
+      await new Promise(resolve => setTimeout(resolve, 0));
+    }
+  }
+}
+
+/**
+ * Put tabs in a new group, with batch processing
+ * @param {Array<number>} tabIds - Tabs to group
+ * @param {Object} info - { title, color, collapsed }
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ * @returns {Promise<number>} ID of the group
+ */
+export async function createGroup(tabIds, info, progressCallback) {
+  const total = tabIds.length;
+  let groupId;
+
+  for (let i = 0; i < total; i += CHUNK_SIZE) {
+    const chunk = tabIds.slice(i, i + CHUNK_SIZE);
+    groupId = groupId === undefined
+      ? await chrome.tabs.group({ tabIds: chunk })
+      : await chrome.tabs.group({ groupId, tabIds: chunk });
+
+    // Update progress
+    if (progressCallback) {
+      progressCallback(Math.round(((i + chunk.length) / total) * 100));
+    }
+
+    // Yield control to browser
+    await new Promise(resolve => setTimeout(resolve, 0));
+  }
+
+  await chrome.tabGroups.update(groupId, groupProperties(info));
+  return groupId;
+}
+
+/**
+ * Reopen closed tabs at their original positions (for undo)
+ *
+ * Tabs must be ordered by original index so each one lands where it was.
+ * Tabs that cannot be reopened (e.g. privileged URLs) are skipped, and a
+ * tab whose group no longer exists is left ungrouped.
+ * @param {Array} tabs - [{ url, pinned, index, group_id }]
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ * @returns {Promise<Array<number>>} Positions in tabs of the ones reopened
+ */
+export async function reopenTabs(tabs, progressCallback) {
+  const total = tabs.length;
+  const reopened = [];
+
+  for (let i = 0; i < total; i++) {
+    const tab = tabs[i];
+    try {
+      const created = await chrome.tabs.create({
+        url: tab.url,
+        pinned: tab.pinned,
+        index: tab.index,
+        active: false
+      });
+      reopened.push(i);
+
+      if (tab.group_id !== null && tab.group_id !== undefined) {
+        try {
+          await chrome.tabs.group({ groupId: tab.group_id, tabIds: [created.id] });
+        } catch (error) {
+          console.warn('Tab group no longer exists:', tab.group_id);
+        }
+      }
+    } catch (error) {
+      console.warn('Failed to reopen tab:', tab.url, error);
+    }
+
+    // Update progress and yield once per chunk of tabs
+    if ((i + 1) % CHUNK_SIZE === 0 || i + 1 === total) {
+      if (progressCallback) {
+        progressCallback(Math.round(((i + 1) / total) * 100));
+      }
+      await new Promise(resolve => setTimeout(resolve, 0));
+    }
+  }
+
+  return reopened;
+}
+
+/**
+ * Open saved tabs according to a restore plan, in the background
+ *
+ * Each planned window is filled left to right, at the end of the current
+ * window or in a new one. Tabs are created one at a time so they keep their
+ * order. A lazy plan discards each tab as soon as its URL has committed,
+ * so pages only finish loading when first focused; a tab that cannot be
+ * discarded stays loaded. Then the window's groups are recreated.
+ * @param {Object} plan - { windows: [{ new_window, tabs: [{ url, pinned, muted, group }], groups }], discard }
+ * @param {Function} progressCallback - Called with progress percentage (0-100)
+ */
+export async function restoreTabs(plan, progressCallback) {
+  const total = plan.windows.reduce((sum, target) => sum + target.tabs.length, 0);
+  let processed = 0;
+
+  for (const target of plan.windows) {
+    let windowId;
+    const tabIds = [];
+
+    for (const tab of target.tabs) {
+      let created;
+      if (target.new_window && windowId === undefined) {
+        created = await createWindow(tab);
+        windowId = created.window_id;
+      } else {
+        const properties = { url: tab.url, active: false, pinned: tab.pinned || false };
+        if (windowId !== undefined) {
+          properties.windowId = windowId;
+        }
+        created = await chrome.tabs.create(properties);
+      }
+
+      if (tab.muted) {
+        await chrome.tabs.update(created.id, { muted: true });
+      }
+      let tabId = created.id;
+      if (plan.discard && !created.active) {
+        if (await urlCommitted(tabId)) {
+          try {
+            tabId = await discardTab(tabId);
+          } catch (error) {
+            console.warn('Failed to discard tab:', tab.url, error);
+          }
+        } else {
+          console.warn('Tab did not start loading in time, leaving it loaded:', tab.url);
+        }
+      }
+      tabIds.push(tabId);
+
+      // Update progress and yield once per chunk of tabs
+      processed++;
+      if (processed % CHUNK_SIZE === 0 || processed === total) {
+        if (progressCallback) {
+          progressCallback(Math.round((processed / total) * 100));
+        }
+        await new Promise(resolve => setTimeout(resolve, 0));
+      }
+    }
+
+    for (let index = 0; index < target.groups.length; index++) {
+      const members = tabIds.filter((_, i) => target.tabs[i].group === index);
+      if (members.length === 0) continue;
+      const options = { tabIds: members };
+      if (windowId !== undefined) {
+        options.createProperties = { windowId };
+      }
+      const groupId = await chrome.tabs.group(options);
+      await updateGroup(groupId, target.groups[index]);
+    }
+  }
+}